@@ -1,15 +1,13 @@
 use std::borrow::Cow;
-use std::io::Cursor;
 use std::process::Command;
 use std::thread;
 use std::time::{Duration, Instant};
 
 use arboard::{Clipboard, ImageData};
-use image::{DynamicImage, ImageFormat, RgbaImage};
 
 use super::{
-    should_emit_change, ClipCallback, ClipboardError, ClipboardPayload, ClipboardService,
-    ImagePayload,
+    join_paths, should_emit_change, ClipCallback, ClipboardError, ClipboardPayload,
+    ClipboardService, ImagePayload, RichTextPayload, SelectionSource,
 };
 use crate::utils::hash::{sha256_hex, sha256_hex_bytes};
 
@@ -28,41 +26,59 @@ impl MacOsClipboard {
         }
     }
 
-    fn clipboard() -> Result<Clipboard, ClipboardError> {
+    pub(super) fn clipboard() -> Result<Clipboard, ClipboardError> {
         Clipboard::new()
             .map_err(|err| ClipboardError::Command(format!("failed to access clipboard: {err}")))
     }
 
-    fn encode_image_payload(image_data: ImageData<'_>) -> Result<ImagePayload, ClipboardError> {
-        let width = image_data.width as u32;
-        let height = image_data.height as u32;
-        let bytes = image_data.bytes.into_owned();
-        let rgba = RgbaImage::from_raw(width, height, bytes).ok_or_else(|| {
-            ClipboardError::Command("clipboard image payload was malformed".to_string())
-        })?;
-
-        let mut output = Cursor::new(Vec::new());
-        DynamicImage::ImageRgba8(rgba)
-            .write_to(&mut output, ImageFormat::Png)
-            .map_err(|err| ClipboardError::Command(format!("failed to encode image: {err}")))?;
-
-        Ok(ImagePayload {
-            bytes: output.into_inner(),
-            mime: "image/png".to_string(),
-            format: "png".to_string(),
-            width,
-            height,
-        })
+    pub(super) fn read_payload(
+        clipboard: &mut Clipboard,
+    ) -> Result<Option<ClipboardPayload>, ClipboardError> {
+        Self::read_payload_with(clipboard, native::read_encoded_image)
     }
 
-    fn read_payload(clipboard: &mut Clipboard) -> Result<Option<ClipboardPayload>, ClipboardError> {
+    /// `read_payload` for backends that can fetch an image in its original
+    /// encoding themselves; arboard's decoded bitmap is only the fallback.
+    pub(super) fn read_payload_with(
+        clipboard: &mut Clipboard,
+        read_encoded_image: impl FnOnce() -> Option<ImagePayload>,
+    ) -> Result<Option<ClipboardPayload>, ClipboardError> {
+        // File managers also put an icon image and the path text on the
+        // pasteboard, so the file list has to win.
+        if let Ok(paths) = clipboard.get().file_list() {
+            if !paths.is_empty() {
+                return Ok(Some(ClipboardPayload::Files(paths)));
+            }
+        }
+
+        if let Some(image) = read_encoded_image() {
+            return Ok(Some(ClipboardPayload::Image(image)));
+        }
+
         if let Ok(image_data) = clipboard.get_image() {
-            let image = Self::encode_image_payload(image_data)?;
+            let image = ImagePayload::from_rgba(
+                image_data.width as u32,
+                image_data.height as u32,
+                image_data.bytes.into_owned(),
+            )?;
             return Ok(Some(ClipboardPayload::Image(image)));
         }
 
         if let Ok(text) = clipboard.get_text() {
-            return Ok(Some(ClipboardPayload::Text(text)));
+            let html = clipboard
+                .get()
+                .html()
+                .ok()
+                .filter(|html| !html.trim().is_empty());
+            let rtf = native::read_rtf();
+            if html.is_none() && rtf.is_none() {
+                return Ok(Some(ClipboardPayload::Text(text)));
+            }
+            return Ok(Some(ClipboardPayload::RichText(RichTextPayload {
+                text,
+                html,
+                rtf,
+            })));
         }
 
         Ok(None)
@@ -78,10 +94,12 @@ impl MacOsClipboard {
         Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
     }
 
-    fn payload_signature(payload: &ClipboardPayload) -> String {
+    pub(super) fn payload_signature(payload: &ClipboardPayload) -> String {
         match payload {
             ClipboardPayload::Text(text) => format!("text:{}", sha256_hex(text)),
+            ClipboardPayload::RichText(rich) => format!("rich:{}", rich.digest()),
             ClipboardPayload::Image(image) => format!("image:{}", sha256_hex_bytes(&image.bytes)),
+            ClipboardPayload::Files(paths) => format!("files:{}", sha256_hex(&join_paths(paths))),
         }
     }
 
@@ -102,6 +120,7 @@ impl ClipboardService for MacOsClipboard {
             ClipboardPayload::Text(content) => clipboard
                 .set_text(content.clone())
                 .map_err(|err| ClipboardError::Command(format!("failed to set text: {err}")))?,
+            ClipboardPayload::RichText(rich) => native::write_rich_text(&mut clipboard, rich)?,
             ClipboardPayload::Image(image) => {
                 let (width, height, rgba) = Self::decode_image_bytes(image)?;
                 clipboard
@@ -112,10 +131,17 @@ impl ClipboardService for MacOsClipboard {
                     })
                     .map_err(|err| ClipboardError::Command(format!("failed to set image: {err}")))?;
             }
+            ClipboardPayload::Files(paths) => clipboard.set().file_list(paths).map_err(|err| {
+                ClipboardError::Command(format!("failed to set file list: {err}"))
+            })?,
         }
         Ok(())
     }
 
+    fn current_payload(&self) -> Result<Option<ClipboardPayload>, ClipboardError> {
+        Self::read_payload(&mut Self::clipboard()?)
+    }
+
     fn watch_changes(&self, callback: ClipCallback) -> Result<(), ClipboardError> {
         let poll = self.poll_ms;
         thread::spawn(move || {
@@ -148,7 +174,7 @@ impl ClipboardService for MacOsClipboard {
                                 &mut last_emitted,
                                 debounce,
                             ) {
-                                callback(next);
+                                callback(SelectionSource::Clipboard, next);
                             }
                         }
                         Ok(None) => {}
@@ -164,9 +190,130 @@ impl ClipboardService for MacOsClipboard {
     }
 
     fn active_bundle_id(&self) -> Option<String> {
-        let script = "tell application \"System Events\" to get bundle identifier of first process whose frontmost is true";
-        Self::run_osascript(script)
-            .ok()
-            .filter(|bundle| !bundle.is_empty())
+        native::frontmost_bundle_id().filter(|bundle| !bundle.is_empty())
+    }
+
+    fn paste_into(&self, app_id: Option<&str>) -> Result<(), ClipboardError> {
+        if let Some(app_id) = app_id.filter(|app_id| is_bundle_id(app_id)) {
+            Self::run_osascript(&format!("tell application id \"{app_id}\" to activate"))?;
+        }
+        // System Events only sends keystrokes for apps granted Accessibility
+        // access, so without it this fails and nothing is pasted.
+        Self::run_osascript(
+            "tell application \"System Events\" to keystroke \"v\" using command down",
+        )?;
+        Ok(())
+    }
+}
+
+/// Bundle ids are spliced into AppleScript, so only their own alphabet is
+/// accepted.
+fn is_bundle_id(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '-' | '_'))
+}
+
+/// RTF and encoded image flavors are not exposed by arboard, so on macOS they
+/// go straight through `NSPasteboard`. Other platforms only round-trip HTML
+/// here; the Linux backends fetch encoded images from the selection owner.
+#[cfg(target_os = "macos")]
+mod native {
+    use arboard::Clipboard;
+    use objc2_app_kit::{
+        NSPasteboard, NSPasteboardTypeHTML, NSPasteboardTypeRTF, NSPasteboardTypeString,
+        NSWorkspace,
+    };
+    use objc2_foundation::{NSData, NSString};
+
+    use super::{ClipboardError, ImagePayload, RichTextPayload};
+
+    /// Uniform type identifiers matching `NATIVE_IMAGE_MIME_TYPES`.
+    const NATIVE_IMAGE_TYPES: [&str; 3] = ["public.jpeg", "org.webmproject.webp", "public.png"];
+
+    pub(super) fn read_encoded_image() -> Option<ImagePayload> {
+        let pasteboard = NSPasteboard::generalPasteboard();
+        NATIVE_IMAGE_TYPES.iter().find_map(|uti| {
+            let data = pasteboard.dataForType(&NSString::from_str(uti))?;
+            ImagePayload::from_encoded(data.to_vec()).ok()
+        })
+    }
+
+    /// Asks `NSWorkspace` directly: it answers in-process, where going
+    /// through System Events costs an `osascript` round trip on every window
+    /// open.
+    pub(super) fn frontmost_bundle_id() -> Option<String> {
+        let app = NSWorkspace::sharedWorkspace().frontmostApplication()?;
+        Some(app.bundleIdentifier()?.to_string())
+    }
+
+    pub(super) fn read_rtf() -> Option<String> {
+        // SAFETY: the pasteboard type constants are immutable AppKit statics.
+        let rtf_type = unsafe { NSPasteboardTypeRTF };
+        let data = NSPasteboard::generalPasteboard().dataForType(rtf_type)?;
+        let rtf = String::from_utf8_lossy(&data.to_vec()).into_owned();
+        Some(rtf).filter(|rtf| !rtf.trim().is_empty())
+    }
+
+    pub(super) fn write_rich_text(
+        _clipboard: &mut Clipboard,
+        rich: &RichTextPayload,
+    ) -> Result<(), ClipboardError> {
+        // SAFETY: the pasteboard type constants are immutable AppKit statics.
+        let (string_type, html_type, rtf_type) = unsafe {
+            (
+                NSPasteboardTypeString,
+                NSPasteboardTypeHTML,
+                NSPasteboardTypeRTF,
+            )
+        };
+        let pasteboard = NSPasteboard::generalPasteboard();
+        pasteboard.clearContents();
+        let mut written =
+            pasteboard.setString_forType(&NSString::from_str(&rich.text), string_type);
+        if let Some(html) = rich.html.as_deref() {
+            written &= pasteboard.setString_forType(&NSString::from_str(html), html_type);
+        }
+        if let Some(rtf) = rich.rtf.as_deref() {
+            let data = NSData::with_bytes(rtf.as_bytes());
+            written &= pasteboard.setData_forType(Some(&data), rtf_type);
+        }
+        if !written {
+            return Err(ClipboardError::Command(
+                "failed to write rich text to pasteboard".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+mod native {
+    use arboard::Clipboard;
+
+    use super::{ClipboardError, ImagePayload, RichTextPayload};
+
+    pub(super) fn read_encoded_image() -> Option<ImagePayload> {
+        None
+    }
+
+    pub(super) fn frontmost_bundle_id() -> Option<String> {
+        None
+    }
+
+    pub(super) fn read_rtf() -> Option<String> {
+        None
+    }
+
+    pub(super) fn write_rich_text(
+        clipboard: &mut Clipboard,
+        rich: &RichTextPayload,
+    ) -> Result<(), ClipboardError> {
+        let result = match rich.html.as_deref() {
+            Some(html) => clipboard.set_html(html, Some(rich.text.as_str())),
+            None => clipboard.set_text(rich.text.as_str()),
+        };
+        result.map_err(|err| ClipboardError::Command(format!("failed to set rich text: {err}")))
     }
 }
@@ -0,0 +1,217 @@
+use std::sync::{Arc, Mutex};
+
+use super::{ClipCallback, ClipboardError, ClipboardPayload, ClipboardService, SelectionSource};
+
+/// Scriptable in-memory backend for headless tests. Nothing reaches the
+/// watcher until a test pushes it, and writes and pastes are recorded
+/// instead of touching a real pasteboard.
+#[derive(Default)]
+pub struct MemoryClipboard {
+    callback: Mutex<Option<ClipCallback>>,
+    active_bundle_id: Mutex<Option<String>>,
+    current: Mutex<Option<ClipboardPayload>>,
+    writes: Mutex<Vec<ClipboardPayload>>,
+    pastes: Mutex<Vec<Option<String>>>,
+    pastes_fail: Mutex<bool>,
+    capture_primary: Mutex<bool>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Simulates a user copy into CLIPBOARD. Delivery is synchronous, so the
+    /// engine has finished ingesting by the time this returns.
+    pub fn push(&self, payload: ClipboardPayload) {
+        self.push_from(SelectionSource::Clipboard, payload);
+    }
+
+    pub fn push_from(&self, source: SelectionSource, payload: ClipboardPayload) {
+        if source == SelectionSource::Clipboard {
+            *self.current.lock().expect("current lock") = Some(payload.clone());
+        }
+        let callback = self.callback.lock().expect("callback lock").clone();
+        if let Some(callback) = callback {
+            callback(source, payload);
+        }
+    }
+
+    pub fn set_active_app(&self, bundle_id: Option<&str>) {
+        *self.active_bundle_id.lock().expect("active app lock") = bundle_id.map(str::to_string);
+    }
+
+    pub fn writes(&self) -> Vec<ClipboardPayload> {
+        self.writes.lock().expect("writes lock").clone()
+    }
+
+    /// The app id each `paste_into` call targeted, in order.
+    pub fn pastes(&self) -> Vec<Option<String>> {
+        self.pastes.lock().expect("pastes lock").clone()
+    }
+
+    /// Makes every later `paste_into` fail, like a backend that cannot send
+    /// keystrokes.
+    pub fn fail_pastes(&self) {
+        *self.pastes_fail.lock().expect("pastes fail lock") = true;
+    }
+
+    /// Replays the latest `set_payload` through the watcher, the way a real
+    /// pasteboard reports our own writes back as a change.
+    pub fn echo_last_write(&self) -> bool {
+        let last = self.writes.lock().expect("writes lock").last().cloned();
+        match last {
+            Some(payload) => {
+                self.push(payload);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.callback.lock().expect("callback lock").is_some()
+    }
+
+    /// The last value the engine passed to `set_capture_primary`.
+    pub fn captures_primary(&self) -> bool {
+        *self.capture_primary.lock().expect("capture primary lock")
+    }
+}
+
+impl ClipboardService for MemoryClipboard {
+    fn set_payload(&self, payload: &ClipboardPayload) -> Result<(), ClipboardError> {
+        self.writes
+            .lock()
+            .map_err(|_| ClipboardError::Command("writes lock poisoned".to_string()))?
+            .push(payload.clone());
+        *self
+            .current
+            .lock()
+            .map_err(|_| ClipboardError::Command("current lock poisoned".to_string()))? =
+            Some(payload.clone());
+        Ok(())
+    }
+
+    fn current_payload(&self) -> Result<Option<ClipboardPayload>, ClipboardError> {
+        Ok(self
+            .current
+            .lock()
+            .map_err(|_| ClipboardError::Command("current lock poisoned".to_string()))?
+            .clone())
+    }
+
+    fn watch_changes(&self, callback: ClipCallback) -> Result<(), ClipboardError> {
+        *self
+            .callback
+            .lock()
+            .map_err(|_| ClipboardError::Command("callback lock poisoned".to_string()))? =
+            Some(callback);
+        Ok(())
+    }
+
+    fn set_capture_primary(&self, enabled: bool) {
+        if let Ok(mut capture_primary) = self.capture_primary.lock() {
+            *capture_primary = enabled;
+        }
+    }
+
+    fn active_bundle_id(&self) -> Option<String> {
+        self.active_bundle_id.lock().ok()?.clone()
+    }
+
+    fn paste_into(&self, app_id: Option<&str>) -> Result<(), ClipboardError> {
+        if *self
+            .pastes_fail
+            .lock()
+            .map_err(|_| ClipboardError::Command("pastes fail lock poisoned".to_string()))?
+        {
+            return Err(ClipboardError::Command(
+                "pasting is not supported".to_string(),
+            ));
+        }
+        self.pastes
+            .lock()
+            .map_err(|_| ClipboardError::Command("pastes lock poisoned".to_string()))?
+            .push(app_id.map(str::to_string));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording_watcher(
+        clipboard: &MemoryClipboard,
+    ) -> Arc<Mutex<Vec<(SelectionSource, ClipboardPayload)>>> {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+        clipboard
+            .watch_changes(Arc::new(move |source, payload| {
+                sink.lock().expect("sink lock").push((source, payload));
+            }))
+            .expect("watch");
+        received
+    }
+
+    #[test]
+    fn pushes_are_dropped_until_watched() {
+        let clipboard = MemoryClipboard::new();
+        clipboard.push(ClipboardPayload::Text("early".to_string()));
+        assert!(!clipboard.is_watching());
+
+        let received = recording_watcher(&clipboard);
+        clipboard.push_from(
+            SelectionSource::Primary,
+            ClipboardPayload::Text("selected".to_string()),
+        );
+        assert_eq!(
+            *received.lock().expect("received"),
+            vec![(
+                SelectionSource::Primary,
+                ClipboardPayload::Text("selected".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn records_writes_and_echoes_the_latest() {
+        let clipboard = MemoryClipboard::new();
+        assert!(!clipboard.echo_last_write());
+
+        let received = recording_watcher(&clipboard);
+        clipboard
+            .set_payload(&ClipboardPayload::Text("first".to_string()))
+            .expect("write first");
+        clipboard
+            .set_payload(&ClipboardPayload::Text("second".to_string()))
+            .expect("write second");
+        assert_eq!(clipboard.writes().len(), 2);
+        assert!(received.lock().expect("received").is_empty());
+
+        assert!(clipboard.echo_last_write());
+        assert_eq!(
+            *received.lock().expect("received"),
+            vec![(
+                SelectionSource::Clipboard,
+                ClipboardPayload::Text("second".to_string())
+            )]
+        );
+        assert_eq!(
+            clipboard.current_payload().expect("current"),
+            Some(ClipboardPayload::Text("second".to_string()))
+        );
+    }
+
+    #[test]
+    fn reports_scripted_active_app() {
+        let clipboard = MemoryClipboard::new();
+        assert_eq!(clipboard.active_bundle_id(), None);
+        clipboard.set_active_app(Some("com.apple.Safari"));
+        assert_eq!(
+            clipboard.active_bundle_id(),
+            Some("com.apple.Safari".to_string())
+        );
+    }
+}
@@ -1,9 +1,20 @@
+use std::io::Cursor;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use image::{DynamicImage, ImageFormat, ImageReader, RgbaImage};
 use thiserror::Error;
 
+use crate::utils::hash::sha256_hex;
+
 pub mod macos;
+#[cfg(test)]
+pub mod memory;
+#[cfg(target_os = "linux")]
+pub mod wayland;
+#[cfg(target_os = "linux")]
+pub mod x11;
 
 #[derive(Debug, Error)]
 pub enum ClipboardError {
@@ -22,18 +33,144 @@ pub struct ImagePayload {
     pub height: u32,
 }
 
+/// Encoded image flavors kept byte-for-byte, most specific first. TIFF is
+/// deliberately absent: pasteboards use it as an uncompressed interchange
+/// bitmap, so it takes the PNG fallback instead.
+pub const NATIVE_IMAGE_MIME_TYPES: [&str; 3] = ["image/jpeg", "image/webp", "image/png"];
+
+impl ImagePayload {
+    /// Wraps already-encoded image bytes without re-encoding them. TIFF is
+    /// treated as a raw bitmap and converted to PNG.
+    pub fn from_encoded(bytes: Vec<u8>) -> Result<Self, ClipboardError> {
+        let reader = ImageReader::new(Cursor::new(&bytes)).with_guessed_format()?;
+        let (mime, format) = match reader.format() {
+            Some(ImageFormat::Jpeg) => ("image/jpeg", "jpeg"),
+            Some(ImageFormat::WebP) => ("image/webp", "webp"),
+            Some(ImageFormat::Png) => ("image/png", "png"),
+            Some(ImageFormat::Tiff) => {
+                let rgba = reader.decode().map_err(image_error)?.to_rgba8();
+                let (width, height) = rgba.dimensions();
+                return Self::from_rgba(width, height, rgba.into_raw());
+            }
+            _ => {
+                return Err(ClipboardError::Command(
+                    "unsupported clipboard image format".to_string(),
+                ))
+            }
+        };
+        let (width, height) = reader.into_dimensions().map_err(image_error)?;
+        Ok(Self {
+            bytes,
+            mime: mime.to_string(),
+            format: format.to_string(),
+            width,
+            height,
+        })
+    }
+
+    /// Encodes a raw RGBA bitmap as PNG, for sources that only hand out
+    /// decoded pixels.
+    pub fn from_rgba(width: u32, height: u32, rgba: Vec<u8>) -> Result<Self, ClipboardError> {
+        let rgba = RgbaImage::from_raw(width, height, rgba).ok_or_else(|| {
+            ClipboardError::Command("clipboard image payload was malformed".to_string())
+        })?;
+
+        let mut output = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(rgba)
+            .write_to(&mut output, ImageFormat::Png)
+            .map_err(|err| ClipboardError::Command(format!("failed to encode image: {err}")))?;
+
+        Ok(Self {
+            bytes: output.into_inner(),
+            mime: "image/png".to_string(),
+            format: "png".to_string(),
+            width,
+            height,
+        })
+    }
+}
+
+fn image_error(err: image::ImageError) -> ClipboardError {
+    ClipboardError::Command(format!("failed to read clipboard image: {err}"))
+}
+
+/// Formatted text: the plain-text flavor plus whichever of the HTML and RTF
+/// flavors the source app put on the pasteboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RichTextPayload {
+    pub text: String,
+    pub html: Option<String>,
+    pub rtf: Option<String>,
+}
+
+impl RichTextPayload {
+    pub fn byte_len(&self) -> usize {
+        self.text.len()
+            + self.html.as_ref().map_or(0, String::len)
+            + self.rtf.as_ref().map_or(0, String::len)
+    }
+
+    pub fn digest(&self) -> String {
+        sha256_hex(&format!(
+            "{}\0{}\0{}",
+            self.text,
+            self.html.as_deref().unwrap_or_default(),
+            self.rtf.as_deref().unwrap_or_default()
+        ))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ClipboardPayload {
     Text(String),
+    RichText(RichTextPayload),
     Image(ImagePayload),
+    /// Files and folders copied in a file manager, in the order given.
+    Files(Vec<PathBuf>),
+}
+
+/// One path per line, the form file-list clips are stored and searched in.
+pub fn join_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Which selection a payload was read from. Only Linux has a PRIMARY
+/// (middle-click) selection; every other backend reports `Clipboard`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SelectionSource {
+    Clipboard,
+    Primary,
+}
+
+impl SelectionSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Clipboard => "clipboard",
+            Self::Primary => "primary",
+        }
+    }
 }
 
-pub type ClipCallback = Arc<dyn Fn(ClipboardPayload) + Send + Sync + 'static>;
+pub type ClipCallback = Arc<dyn Fn(SelectionSource, ClipboardPayload) + Send + Sync + 'static>;
 
 pub trait ClipboardService: Send + Sync {
     fn set_payload(&self, payload: &ClipboardPayload) -> Result<(), ClipboardError>;
+    /// What is on the clipboard right now, so it can be put back later.
+    fn current_payload(&self) -> Result<Option<ClipboardPayload>, ClipboardError>;
     fn watch_changes(&self, callback: ClipCallback) -> Result<(), ClipboardError>;
+    /// Whether the watcher should read the PRIMARY selection at all. Off
+    /// until the engine turns it on, so selected text is never read unless
+    /// the user opted in. Backends without a PRIMARY selection ignore it.
+    fn set_capture_primary(&self, _enabled: bool) {}
     fn active_bundle_id(&self) -> Option<String>;
+    /// Brings `app_id` (as reported by `active_bundle_id`) to the front and
+    /// sends it the platform paste keystroke. Without an id the keystroke
+    /// goes to whichever app has focus.
+    fn paste_into(&self, app_id: Option<&str>) -> Result<(), ClipboardError>;
 }
 
 pub fn should_emit_change(
@@ -59,7 +196,56 @@ pub fn should_emit_change(
     true
 }
 
-pub fn default_service() -> Arc<dyn ClipboardService> {
+/// Debounce state for a single selection source, for backends that watch
+/// more than one selection at a time.
+pub struct ChangeTracker {
+    previous_signature: Option<String>,
+    last_emitted: Instant,
+    debounce: Duration,
+}
+
+impl ChangeTracker {
+    pub fn new(debounce: Duration) -> Self {
+        Self {
+            previous_signature: None,
+            last_emitted: Instant::now() - debounce * 2,
+            debounce,
+        }
+    }
+
+    pub fn seed(&mut self, signature: String) {
+        self.previous_signature = Some(signature);
+    }
+
+    pub fn should_emit(&mut self, signature: &str) -> bool {
+        should_emit_change(
+            &mut self.previous_signature,
+            signature,
+            &mut self.last_emitted,
+            self.debounce,
+        )
+    }
+}
+
+/// `app_id` is the bundle id the engine treats as our own app; backends that
+/// identify windows some other way report our windows under it.
+pub fn default_service(app_id: &str) -> Arc<dyn ClipboardService> {
+    #[cfg(target_os = "linux")]
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        match wayland::WaylandClipboard::connect() {
+            Ok(service) => return Arc::new(service),
+            Err(err) => tracing::warn!("wayland clipboard unavailable, trying x11: {err}"),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if std::env::var_os("DISPLAY").is_some() {
+        match x11::X11Clipboard::connect(app_id) {
+            Ok(service) => return Arc::new(service),
+            Err(err) => tracing::warn!("x11 clipboard unavailable, falling back to polling: {err}"),
+        }
+    }
+
     Arc::new(macos::MacOsClipboard::new())
 }
 
@@ -67,6 +253,36 @@ pub fn default_service() -> Arc<dyn ClipboardService> {
 mod tests {
     use super::*;
 
+    fn encoded(format: ImageFormat) -> Vec<u8> {
+        let rgb = image::RgbImage::from_raw(2, 1, vec![255, 0, 0, 0, 0, 255]).expect("image");
+        let mut output = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(rgb)
+            .write_to(&mut output, format)
+            .expect("encode");
+        output.into_inner()
+    }
+
+    #[test]
+    fn keeps_encoded_images_byte_for_byte() {
+        let jpeg = encoded(ImageFormat::Jpeg);
+        let payload = ImagePayload::from_encoded(jpeg.clone()).expect("jpeg payload");
+        assert_eq!(payload.bytes, jpeg);
+        assert_eq!(payload.mime, "image/jpeg");
+        assert_eq!(payload.format, "jpeg");
+        assert_eq!((payload.width, payload.height), (2, 1));
+    }
+
+    #[test]
+    fn converts_tiff_bitmaps_to_png() {
+        let payload = ImagePayload::from_encoded(encoded(ImageFormat::Tiff)).expect("tiff payload");
+        assert_eq!(payload.mime, "image/png");
+        assert_eq!(
+            image::guess_format(&payload.bytes).expect("format"),
+            ImageFormat::Png
+        );
+        assert_eq!((payload.width, payload.height), (2, 1));
+    }
+
     #[test]
     fn debounce_blocks_rapid_changes() {
         let mut previous = None;
@@ -87,6 +303,14 @@ mod tests {
         assert!(emitted);
     }
 
+    #[test]
+    fn change_tracker_ignores_seeded_signature() {
+        let mut tracker = ChangeTracker::new(Duration::from_millis(10));
+        tracker.seed("current".to_string());
+        assert!(!tracker.should_emit("current"));
+        assert!(tracker.should_emit("next"));
+    }
+
     #[test]
     fn identical_signature_is_not_emitted() {
         let mut previous = Some("same".to_string());
@@ -0,0 +1,690 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use tracing::warn;
+use wayland_client::backend::ObjectId;
+use wayland_client::globals::{registry_queue_init, GlobalList, GlobalListContents};
+use wayland_client::protocol::wl_registry::WlRegistry;
+use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_client::{
+    delegate_noop, event_created_child, Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+};
+use wayland_protocols::ext::data_control::v1::client::{
+    ext_data_control_device_v1::{self, ExtDataControlDeviceV1},
+    ext_data_control_manager_v1::ExtDataControlManagerV1,
+    ext_data_control_offer_v1::{self, ExtDataControlOfferV1},
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+use super::macos::MacOsClipboard;
+use super::{
+    ChangeTracker, ClipCallback, ClipboardError, ClipboardPayload, ClipboardService, ImagePayload,
+    RichTextPayload, SelectionSource, NATIVE_IMAGE_MIME_TYPES,
+};
+
+const DEBOUNCE_MS: u64 = 120;
+const RECEIVE_TIMEOUT_MS: u64 = 2000;
+
+const TIFF_MIME_TYPE: &str = "image/tiff";
+const TEXT_MIME_TYPES: [&str; 5] = [
+    "text/plain;charset=utf-8",
+    "UTF8_STRING",
+    "text/plain",
+    "TEXT",
+    "STRING",
+];
+const FILE_LIST_MIME_TYPE: &str = "text/uri-list";
+const HTML_MIME_TYPE: &str = "text/html";
+const RTF_MIME_TYPE: &str = "text/rtf";
+
+/// Wayland backend built on `ext-data-control` (or `wlr-data-control` on
+/// older compositors). The compositor pushes CLIPBOARD and PRIMARY selection
+/// changes to us, and the focused app id comes from
+/// `wlr-foreign-toplevel-management` when the compositor offers it. Writes go
+/// through arboard's data-control support.
+pub struct WaylandClipboard {
+    pasteboard: MacOsClipboard,
+    conn: Connection,
+    queue: Mutex<Option<EventQueue<WaylandState>>>,
+    focused_app_id: Arc<Mutex<Option<String>>>,
+    capture_primary: Arc<AtomicBool>,
+}
+
+impl WaylandClipboard {
+    pub fn connect() -> Result<Self, ClipboardError> {
+        let conn = Connection::connect_to_env().map_err(wayland_error)?;
+        let (globals, queue) = registry_queue_init::<WaylandState>(&conn).map_err(wayland_error)?;
+        let qh = queue.handle();
+        bind_data_device(&globals, &qh)?;
+
+        if globals
+            .bind::<ZwlrForeignToplevelManagerV1, _, _>(&qh, 1..=3, ())
+            .is_err()
+        {
+            warn!("compositor does not expose foreign toplevels, app denylist is inactive");
+        }
+
+        Ok(Self {
+            pasteboard: MacOsClipboard::new(),
+            conn,
+            queue: Mutex::new(Some(queue)),
+            focused_app_id: Arc::new(Mutex::new(None)),
+            capture_primary: Arc::new(AtomicBool::new(false)),
+        })
+    }
+}
+
+impl ClipboardService for WaylandClipboard {
+    fn set_payload(&self, payload: &ClipboardPayload) -> Result<(), ClipboardError> {
+        self.pasteboard.set_payload(payload)
+    }
+
+    /// The watcher thread owns the main event queue, so this reads the
+    /// selection over a short-lived connection of its own. Going through the
+    /// offer keeps images in the encoding the source app offered.
+    fn current_payload(&self) -> Result<Option<ClipboardPayload>, ClipboardError> {
+        let conn = Connection::connect_to_env().map_err(wayland_error)?;
+        let (globals, mut queue) =
+            registry_queue_init::<WaylandState>(&conn).map_err(wayland_error)?;
+        bind_data_device(&globals, &queue.handle())?;
+
+        // A new device is sent the current selections straight away.
+        let mut state = WaylandState::new(Arc::new(Mutex::new(None)));
+        queue.roundtrip(&mut state).map_err(wayland_error)?;
+        let Some(Some(offer)) = state.pending_selections.remove(&SelectionSource::Clipboard) else {
+            return Ok(None);
+        };
+        let mime_types = state
+            .offer_mime_types
+            .remove(&offer.id())
+            .unwrap_or_default();
+        let payload = read_offer(&conn, &offer, &mime_types);
+        offer.destroy();
+        payload
+    }
+
+    fn watch_changes(&self, callback: ClipCallback) -> Result<(), ClipboardError> {
+        let mut queue = self
+            .queue
+            .lock()
+            .map_err(|_| ClipboardError::Command("wayland queue lock poisoned".to_string()))?
+            .take()
+            .ok_or_else(|| {
+                ClipboardError::Command("wayland clipboard is already being watched".to_string())
+            })?;
+        let conn = self.conn.clone();
+        let mut state = WaylandState::new(Arc::clone(&self.focused_app_id));
+        let capture_primary = Arc::clone(&self.capture_primary);
+
+        thread::spawn(move || {
+            let debounce = Duration::from_millis(DEBOUNCE_MS);
+            let mut trackers: HashMap<SelectionSource, ChangeTracker> = HashMap::new();
+
+            loop {
+                if let Err(err) = queue.blocking_dispatch(&mut state) {
+                    warn!("wayland clipboard watcher stopped: {err}");
+                    return;
+                }
+                if state.finished {
+                    warn!("wayland data-control device was removed by the compositor");
+                    return;
+                }
+
+                let changes: Vec<_> = state.pending_selections.drain().collect();
+                for (source, offer) in changes {
+                    // The compositor replays the current selections as soon as
+                    // the device is created; use them as the baseline so app
+                    // launch does not re-ingest the last copied item.
+                    let seeding = !trackers.contains_key(&source);
+                    let tracker = trackers
+                        .entry(source)
+                        .or_insert_with(|| ChangeTracker::new(debounce));
+                    let Some(offer) = offer else {
+                        continue;
+                    };
+                    let mime_types = state
+                        .offer_mime_types
+                        .remove(&offer.id())
+                        .unwrap_or_default();
+                    if source == SelectionSource::Primary
+                        && !capture_primary.load(Ordering::Relaxed)
+                    {
+                        offer.destroy();
+                        continue;
+                    }
+                    let payload = read_offer(&conn, &offer, &mime_types);
+                    offer.destroy();
+
+                    match payload {
+                        Ok(Some(next)) => {
+                            let signature = MacOsClipboard::payload_signature(&next);
+                            if seeding {
+                                tracker.seed(signature);
+                            } else if tracker.should_emit(&signature) {
+                                callback(source, next);
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(err) => warn!("failed to read wayland selection: {err}"),
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn set_capture_primary(&self, enabled: bool) {
+        self.capture_primary.store(enabled, Ordering::Relaxed);
+    }
+
+    fn active_bundle_id(&self) -> Option<String> {
+        self.focused_app_id.lock().ok()?.clone()
+    }
+
+    fn paste_into(&self, _app_id: Option<&str>) -> Result<(), ClipboardError> {
+        // Wayland gives clients no way to focus other surfaces or inject
+        // keystrokes. The engine puts the previous clipboard back when
+        // restoring is on; otherwise the clip stays there for a manual paste.
+        Err(ClipboardError::Command(
+            "pasting into other apps is not supported on Wayland".to_string(),
+        ))
+    }
+}
+
+/// Creates a data-control device on the seat, preferring `ext-data-control`
+/// over the older wlr protocol.
+fn bind_data_device(
+    globals: &GlobalList,
+    qh: &QueueHandle<WaylandState>,
+) -> Result<(), ClipboardError> {
+    let seat: WlSeat = globals.bind(qh, 1..=1, ()).map_err(wayland_error)?;
+    if let Ok(manager) = globals.bind::<ExtDataControlManagerV1, _, _>(qh, 1..=1, ()) {
+        manager.get_data_device(&seat, qh, ());
+    } else {
+        let manager: ZwlrDataControlManagerV1 = globals.bind(qh, 1..=2, ()).map_err(|_| {
+            ClipboardError::Command(
+                "compositor does not support ext-data-control or wlr-data-control".to_string(),
+            )
+        })?;
+        manager.get_data_device(&seat, qh, ());
+    }
+    Ok(())
+}
+
+enum SelectionOffer {
+    Ext(ExtDataControlOfferV1),
+    Wlr(ZwlrDataControlOfferV1),
+}
+
+impl SelectionOffer {
+    fn id(&self) -> ObjectId {
+        match self {
+            Self::Ext(offer) => offer.id(),
+            Self::Wlr(offer) => offer.id(),
+        }
+    }
+
+    fn receive(&self, mime_type: &str, fd: BorrowedFd<'_>) {
+        match self {
+            Self::Ext(offer) => offer.receive(mime_type.to_string(), fd),
+            Self::Wlr(offer) => offer.receive(mime_type.to_string(), fd),
+        }
+    }
+
+    fn destroy(&self) {
+        match self {
+            Self::Ext(offer) => offer.destroy(),
+            Self::Wlr(offer) => offer.destroy(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Toplevel {
+    app_id: Option<String>,
+    activated: bool,
+}
+
+struct WaylandState {
+    offer_mime_types: HashMap<ObjectId, Vec<String>>,
+    pending_selections: HashMap<SelectionSource, Option<SelectionOffer>>,
+    finished: bool,
+    toplevels: HashMap<ObjectId, Toplevel>,
+    focused_app_id: Arc<Mutex<Option<String>>>,
+}
+
+impl WaylandState {
+    fn new(focused_app_id: Arc<Mutex<Option<String>>>) -> Self {
+        Self {
+            offer_mime_types: HashMap::new(),
+            pending_selections: HashMap::new(),
+            finished: false,
+            toplevels: HashMap::new(),
+            focused_app_id,
+        }
+    }
+
+    fn set_selection(&mut self, source: SelectionSource, offer: Option<SelectionOffer>) {
+        if let Some(Some(previous)) = self.pending_selections.insert(source, offer) {
+            self.offer_mime_types.remove(&previous.id());
+            previous.destroy();
+        }
+    }
+
+    fn refresh_focused_app_id(&self) {
+        let focused = self
+            .toplevels
+            .values()
+            .find(|toplevel| toplevel.activated)
+            .and_then(|toplevel| toplevel.app_id.clone());
+        if let Ok(mut current) = self.focused_app_id.lock() {
+            *current = focused;
+        }
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _registry: &WlRegistry,
+        _event: <WlRegistry as Proxy>::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(WaylandState: ignore WlSeat);
+delegate_noop!(WaylandState: ExtDataControlManagerV1);
+delegate_noop!(WaylandState: ZwlrDataControlManagerV1);
+
+impl Dispatch<ExtDataControlDeviceV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _device: &ExtDataControlDeviceV1,
+        event: ext_data_control_device_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_data_control_device_v1::Event::Selection { id } => {
+                state.set_selection(SelectionSource::Clipboard, id.map(SelectionOffer::Ext));
+            }
+            ext_data_control_device_v1::Event::PrimarySelection { id } => {
+                state.set_selection(SelectionSource::Primary, id.map(SelectionOffer::Ext));
+            }
+            ext_data_control_device_v1::Event::Finished => state.finished = true,
+            _ => {}
+        }
+    }
+
+    event_created_child!(WaylandState, ExtDataControlDeviceV1, [
+        ext_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ExtDataControlOfferV1, ()),
+    ]);
+}
+
+impl Dispatch<ExtDataControlOfferV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        offer: &ExtDataControlOfferV1,
+        event: ext_data_control_offer_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let ext_data_control_offer_v1::Event::Offer { mime_type } = event {
+            state
+                .offer_mime_types
+                .entry(offer.id())
+                .or_default()
+                .push(mime_type);
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _device: &ZwlrDataControlDeviceV1,
+        event: zwlr_data_control_device_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_data_control_device_v1::Event::Selection { id } => {
+                state.set_selection(SelectionSource::Clipboard, id.map(SelectionOffer::Wlr));
+            }
+            zwlr_data_control_device_v1::Event::PrimarySelection { id } => {
+                state.set_selection(SelectionSource::Primary, id.map(SelectionOffer::Wlr));
+            }
+            zwlr_data_control_device_v1::Event::Finished => state.finished = true,
+            _ => {}
+        }
+    }
+
+    event_created_child!(WaylandState, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrDataControlOfferV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        offer: &ZwlrDataControlOfferV1,
+        event: zwlr_data_control_offer_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let zwlr_data_control_offer_v1::Event::Offer { mime_type } = event {
+            state
+                .offer_mime_types
+                .entry(offer.id())
+                .or_default()
+                .push(mime_type);
+        }
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _manager: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            state.toplevels.insert(toplevel.id(), Toplevel::default());
+        }
+    }
+
+    event_created_child!(WaylandState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                let toplevel = state.toplevels.entry(handle.id()).or_default();
+                toplevel.app_id = Some(app_id).filter(|id| !id.is_empty());
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state: flags } => {
+                let toplevel = state.toplevels.entry(handle.id()).or_default();
+                toplevel.activated = is_activated(&flags);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Done => state.refresh_focused_app_id(),
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                state.toplevels.remove(&handle.id());
+                handle.destroy();
+                state.refresh_focused_app_id();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The toplevel `state` event carries an array of native-endian u32 values.
+fn is_activated(flags: &[u8]) -> bool {
+    let activated = zwlr_foreign_toplevel_handle_v1::State::Activated as u32;
+    flags
+        .chunks_exact(4)
+        .map(|chunk| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .any(|value| value == activated)
+}
+
+/// File managers offer the copied paths as `text/uri-list` next to an icon
+/// and plain text, so the file list is preferred over everything else.
+fn preferred_mime_type(offered: &[String]) -> Option<&'static str> {
+    [FILE_LIST_MIME_TYPE]
+        .iter()
+        .chain(NATIVE_IMAGE_MIME_TYPES.iter())
+        .chain([TIFF_MIME_TYPE].iter())
+        .chain(TEXT_MIME_TYPES.iter())
+        .find(|candidate| offered.iter().any(|mime| mime == *candidate))
+        .copied()
+}
+
+fn read_offer(
+    conn: &Connection,
+    offer: &SelectionOffer,
+    mime_types: &[String],
+) -> Result<Option<ClipboardPayload>, ClipboardError> {
+    let Some(mime_type) = preferred_mime_type(mime_types) else {
+        return Ok(None);
+    };
+
+    let bytes = receive_bytes(conn, offer, mime_type)?;
+    let payload = payload_from_bytes(mime_type, bytes)?;
+    let Some(ClipboardPayload::Text(text)) = payload else {
+        return Ok(payload);
+    };
+
+    let mut rich = RichTextPayload {
+        text,
+        html: None,
+        rtf: None,
+    };
+    for (mime, slot) in [
+        (HTML_MIME_TYPE, &mut rich.html),
+        (RTF_MIME_TYPE, &mut rich.rtf),
+    ] {
+        if mime_types.iter().any(|offered| offered == mime) {
+            let bytes = receive_bytes(conn, offer, mime)?;
+            *slot = Some(String::from_utf8_lossy(&bytes).into_owned())
+                .filter(|flavor| !flavor.trim().is_empty());
+        }
+    }
+    if rich.html.is_none() && rich.rtf.is_none() {
+        return Ok(Some(ClipboardPayload::Text(rich.text)));
+    }
+    Ok(Some(ClipboardPayload::RichText(rich)))
+}
+
+fn receive_bytes(
+    conn: &Connection,
+    offer: &SelectionOffer,
+    mime_type: &str,
+) -> Result<Vec<u8>, ClipboardError> {
+    let (mut reader, writer) = UnixStream::pair()?;
+    reader.set_read_timeout(Some(Duration::from_millis(RECEIVE_TIMEOUT_MS)))?;
+    offer.receive(mime_type, writer.as_fd());
+    conn.flush().map_err(wayland_error)?;
+    drop(writer);
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn payload_from_bytes(
+    mime_type: &str,
+    bytes: Vec<u8>,
+) -> Result<Option<ClipboardPayload>, ClipboardError> {
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+
+    if mime_type.starts_with("image/") {
+        return Ok(Some(ClipboardPayload::Image(ImagePayload::from_encoded(
+            bytes,
+        )?)));
+    }
+
+    if mime_type == FILE_LIST_MIME_TYPE {
+        let paths = paths_from_uri_list(&String::from_utf8_lossy(&bytes));
+        if paths.is_empty() {
+            return Ok(None);
+        }
+        return Ok(Some(ClipboardPayload::Files(paths)));
+    }
+
+    Ok(Some(ClipboardPayload::Text(
+        String::from_utf8_lossy(&bytes).into_owned(),
+    )))
+}
+
+/// Parses RFC 2483 `text/uri-list` content, keeping only local `file://`
+/// entries.
+fn paths_from_uri_list(list: &str) -> Vec<PathBuf> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.strip_prefix("file://"))
+        .map(|rest| rest.strip_prefix("localhost").unwrap_or(rest))
+        .filter_map(percent_decode)
+        .map(PathBuf::from)
+        .collect()
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = value.get(index + 1..index + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn wayland_error(err: impl std::fmt::Display) -> ClipboardError {
+    ClipboardError::Command(format!("wayland request failed: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn prefers_images_then_utf8_text() {
+        let offered = vec![
+            "text/plain".to_string(),
+            "image/png".to_string(),
+            "text/plain;charset=utf-8".to_string(),
+        ];
+        assert_eq!(preferred_mime_type(&offered), Some("image/png"));
+
+        let offered = vec!["image/png".to_string(), "image/jpeg".to_string()];
+        assert_eq!(preferred_mime_type(&offered), Some("image/jpeg"));
+
+        let offered = vec!["STRING".to_string(), "text/plain;charset=utf-8".to_string()];
+        assert_eq!(
+            preferred_mime_type(&offered),
+            Some("text/plain;charset=utf-8")
+        );
+
+        assert_eq!(
+            preferred_mime_type(&["application/x-custom".to_string()]),
+            None
+        );
+    }
+
+    #[test]
+    fn file_lists_win_and_decode_to_local_paths() {
+        let offered = vec![
+            "text/plain;charset=utf-8".to_string(),
+            "image/png".to_string(),
+            "text/uri-list".to_string(),
+        ];
+        assert_eq!(preferred_mime_type(&offered), Some("text/uri-list"));
+
+        let list = "# copied\r\nfile:///home/ana/My%20Notes.txt\r\nfile://localhost/tmp/dir\r\nhttps://example.com/\r\n";
+        assert_eq!(
+            payload_from_bytes("text/uri-list", list.as_bytes().to_vec()).expect("payload"),
+            Some(ClipboardPayload::Files(vec![
+                PathBuf::from("/home/ana/My Notes.txt"),
+                PathBuf::from("/tmp/dir"),
+            ]))
+        );
+        assert_eq!(
+            payload_from_bytes("text/uri-list", b"https://example.com/".to_vec()).expect("payload"),
+            None
+        );
+    }
+
+    #[test]
+    fn activated_flag_is_read_from_state_array() {
+        let mut flags = Vec::new();
+        flags.extend_from_slice(&0u32.to_ne_bytes());
+        assert!(!is_activated(&flags));
+        flags.extend_from_slice(&2u32.to_ne_bytes());
+        assert!(is_activated(&flags));
+    }
+
+    #[test]
+    fn empty_selection_bytes_yield_no_payload() {
+        assert_eq!(
+            payload_from_bytes("text/plain", Vec::new()).expect("payload"),
+            None
+        );
+        assert_eq!(
+            payload_from_bytes("text/plain", b"hello".to_vec()).expect("payload"),
+            Some(ClipboardPayload::Text("hello".to_string()))
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a data-control compositor, e.g. `WLR_BACKENDS=headless sway`"]
+    fn receives_selection_changes_from_compositor() {
+        let service = WaylandClipboard::connect().expect("connect");
+        let (tx, rx) = mpsc::channel();
+        service
+            .watch_changes(Arc::new(move |source, payload| {
+                let _ = tx.send((source, payload));
+            }))
+            .expect("watch");
+        thread::sleep(Duration::from_millis(DEBOUNCE_MS * 2));
+
+        service
+            .set_payload(&ClipboardPayload::Text("copied on wayland".to_string()))
+            .expect("set clipboard text");
+
+        let payload = rx
+            .recv_timeout(Duration::from_secs(3))
+            .expect("clipboard change");
+        assert_eq!(
+            payload,
+            (
+                SelectionSource::Clipboard,
+                ClipboardPayload::Text("copied on wayland".to_string())
+            )
+        );
+    }
+}
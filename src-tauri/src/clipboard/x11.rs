@@ -0,0 +1,796 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use arboard::{Clipboard, GetExtLinux, LinuxClipboardKind};
+
+use tracing::warn;
+use x11rb::connection::Connection;
+use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ClientMessageEvent, ConnectionExt as _, CreateWindowAux, EventMask,
+    GetPropertyReply, Keycode, Property, Window, WindowClass, KEY_PRESS_EVENT, KEY_RELEASE_EVENT,
+};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::COPY_DEPTH_FROM_PARENT;
+
+use super::macos::MacOsClipboard;
+use super::{
+    ChangeTracker, ClipCallback, ClipboardError, ClipboardPayload, ClipboardService, ImagePayload,
+    SelectionSource, NATIVE_IMAGE_MIME_TYPES,
+};
+
+const DEBOUNCE_MS: u64 = 120;
+const WM_CLASS_MAX_LEN: u32 = 256;
+const CLIENT_LIST_MAX_LEN: u32 = 4096;
+/// How long the window manager gets to move focus before the keystroke.
+const FOCUS_SETTLE: Duration = Duration::from_millis(60);
+/// How long the selection owner gets to answer each conversion request.
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(2000);
+const RECEIVE_POLL: Duration = Duration::from_millis(5);
+const XK_CONTROL_L: u32 = 0xffe3;
+const XK_SHIFT_L: u32 = 0xffe1;
+const XK_V: u32 = 0x0076;
+/// Lowercased `WM_CLASS` of terminals that read Ctrl+V as a control
+/// character and paste CLIPBOARD on Ctrl+Shift+V instead. xterm and urxvt
+/// have no CLIPBOARD paste key by default, so pasting into them does nothing.
+const CTRL_SHIFT_V_TERMINALS: [&str; 12] = [
+    "alacritty",
+    "gnome-terminal",
+    "kitty",
+    "konsole",
+    "lxterminal",
+    "mate-terminal",
+    "org.wezfurlong.wezterm",
+    "st-256color",
+    "terminator",
+    "terminology",
+    "tilix",
+    "xfce4-terminal",
+];
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        CLIPBOARD,
+        INCR,
+        TARGETS,
+        KLIPPY_SELECTION,
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST_STACKING,
+        _NET_WM_PID,
+    }
+}
+
+/// X11 backend: writes and most reads go through arboard, while change
+/// detection uses XFixes selection notifications for CLIPBOARD and PRIMARY
+/// instead of polling. Encoded images are read by `SelectionReader`.
+pub struct X11Clipboard {
+    pasteboard: MacOsClipboard,
+    conn: Arc<RustConnection>,
+    root: Window,
+    atoms: Atoms,
+    reader: Arc<Mutex<SelectionReader>>,
+    capture_primary: Arc<AtomicBool>,
+    /// Reported for our own windows in place of their `WM_CLASS`.
+    app_id: String,
+}
+
+impl X11Clipboard {
+    pub fn connect(app_id: &str) -> Result<Self, ClipboardError> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(x11_error)?;
+        let root = conn.setup().roots[screen_num].root;
+        let atoms = Atoms::new(&conn)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        conn.xfixes_query_version(5, 0)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+
+        Ok(Self {
+            pasteboard: MacOsClipboard::new(),
+            conn: Arc::new(conn),
+            root,
+            atoms,
+            reader: Arc::new(Mutex::new(SelectionReader::connect()?)),
+            capture_primary: Arc::new(AtomicBool::new(false)),
+            app_id: app_id.to_string(),
+        })
+    }
+
+    fn create_listener_window(&self) -> Result<Window, ClipboardError> {
+        let window = self.conn.generate_id().map_err(x11_error)?;
+        self.conn
+            .create_window(
+                COPY_DEPTH_FROM_PARENT,
+                window,
+                self.root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                x11rb::COPY_FROM_PARENT,
+                &CreateWindowAux::new(),
+            )
+            .map_err(x11_error)?;
+        for selection in [self.atoms.CLIPBOARD, AtomEnum::PRIMARY.into()] {
+            self.conn
+                .xfixes_select_selection_input(
+                    window,
+                    selection,
+                    SelectionEventMask::SET_SELECTION_OWNER
+                        | SelectionEventMask::SELECTION_WINDOW_DESTROY
+                        | SelectionEventMask::SELECTION_CLIENT_CLOSE,
+                )
+                .map_err(x11_error)?;
+        }
+        self.conn.flush().map_err(x11_error)?;
+        Ok(window)
+    }
+
+    fn active_window(&self) -> Result<Option<Window>, ClipboardError> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                self.root,
+                self.atoms._NET_ACTIVE_WINDOW,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        Ok(reply
+            .value32()
+            .and_then(|mut values| values.next())
+            .filter(|window| *window != x11rb::NONE))
+    }
+
+    /// The topmost managed window whose `WM_CLASS` is `class`.
+    fn window_with_class(&self, class: &str) -> Result<Option<Window>, ClipboardError> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                self.root,
+                self.atoms._NET_CLIENT_LIST_STACKING,
+                AtomEnum::WINDOW,
+                0,
+                CLIENT_LIST_MAX_LEN,
+            )
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        let stacking = reply
+            .value32()
+            .map(|values| values.collect::<Vec<_>>())
+            .unwrap_or_default();
+        for window in stacking.into_iter().rev() {
+            if self.wm_class(window)?.as_deref() == Some(class) {
+                return Ok(Some(window));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Asks the window manager to focus `window`, the way a pager would.
+    fn activate_window(&self, window: Window) -> Result<(), ClipboardError> {
+        let event = ClientMessageEvent::new(
+            32,
+            window,
+            self.atoms._NET_ACTIVE_WINDOW,
+            [2, x11rb::CURRENT_TIME, 0, 0, 0],
+        );
+        self.conn
+            .send_event(
+                false,
+                self.root,
+                EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+                event,
+            )
+            .map_err(x11_error)?;
+        self.conn.flush().map_err(x11_error)?;
+        Ok(())
+    }
+
+    fn keycode(&self, keysym: u32) -> Result<Keycode, ClipboardError> {
+        let setup = self.conn.setup();
+        let first = setup.min_keycode;
+        let count = setup.max_keycode - first + 1;
+        let mapping = self
+            .conn
+            .get_keyboard_mapping(first, count)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        keycode_for(keysym, first, mapping.keysyms_per_keycode, &mapping.keysyms)
+            .ok_or_else(|| ClipboardError::Command(format!("no keycode for keysym {keysym:#x}")))
+    }
+
+    /// Sends Ctrl+V, or Ctrl+Shift+V for terminals, through XTEST, so it
+    /// reaches whichever window has focus.
+    fn send_paste_keystroke(&self, terminal: bool) -> Result<(), ClipboardError> {
+        let mut modifiers = vec![self.keycode(XK_CONTROL_L)?];
+        if terminal {
+            modifiers.push(self.keycode(XK_SHIFT_L)?);
+        }
+        let v = self.keycode(XK_V)?;
+        let presses = modifiers
+            .iter()
+            .chain([&v])
+            .map(|key| (KEY_PRESS_EVENT, *key));
+        let releases = [&v]
+            .into_iter()
+            .chain(modifiers.iter().rev())
+            .map(|key| (KEY_RELEASE_EVENT, *key));
+        for (kind, keycode) in presses.chain(releases) {
+            self.conn
+                .xtest_fake_input(kind, keycode, x11rb::CURRENT_TIME, self.root, 0, 0, 0)
+                .map_err(x11_error)?;
+        }
+        self.conn
+            .get_input_focus()
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        Ok(())
+    }
+
+    fn window_pid(&self, window: Window) -> Result<Option<u32>, ClipboardError> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms._NET_WM_PID,
+                AtomEnum::CARDINAL,
+                0,
+                1,
+            )
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        Ok(reply.value32().and_then(|mut values| values.next()))
+    }
+
+    fn wm_class(&self, window: Window) -> Result<Option<String>, ClipboardError> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                window,
+                AtomEnum::WM_CLASS,
+                AtomEnum::STRING,
+                0,
+                WM_CLASS_MAX_LEN,
+            )
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        Ok(parse_wm_class(&reply.value))
+    }
+}
+
+impl ClipboardService for X11Clipboard {
+    fn set_payload(&self, payload: &ClipboardPayload) -> Result<(), ClipboardError> {
+        self.pasteboard.set_payload(payload)
+    }
+
+    fn current_payload(&self) -> Result<Option<ClipboardPayload>, ClipboardError> {
+        read_clipboard_payload(&self.reader, &mut MacOsClipboard::clipboard()?)
+    }
+
+    fn watch_changes(&self, callback: ClipCallback) -> Result<(), ClipboardError> {
+        self.create_listener_window()?;
+        let conn = Arc::clone(&self.conn);
+        let clipboard_atom = self.atoms.CLIPBOARD;
+        let capture_primary = Arc::clone(&self.capture_primary);
+        let reader = Arc::clone(&self.reader);
+        thread::spawn(move || {
+            let debounce = Duration::from_millis(DEBOUNCE_MS);
+            let mut clipboard_tracker = ChangeTracker::new(debounce);
+            let mut primary_tracker = ChangeTracker::new(debounce);
+            let mut clipboard = MacOsClipboard::clipboard().ok();
+
+            // Seed the baseline with current selection content so app launch
+            // does not re-ingest the last copied item immediately.
+            if let Some(handle) = clipboard.as_mut() {
+                if let Ok(Some(current)) = read_clipboard_payload(&reader, handle) {
+                    clipboard_tracker.seed(MacOsClipboard::payload_signature(&current));
+                }
+                if capture_primary.load(Ordering::Relaxed) {
+                    if let Ok(Some(current)) = read_primary_payload(handle) {
+                        primary_tracker.seed(MacOsClipboard::payload_signature(&current));
+                    }
+                }
+            }
+
+            loop {
+                let source = match conn.wait_for_event() {
+                    Ok(Event::XfixesSelectionNotify(event))
+                        if event.selection == clipboard_atom =>
+                    {
+                        SelectionSource::Clipboard
+                    }
+                    Ok(Event::XfixesSelectionNotify(event))
+                        if event.selection == Atom::from(AtomEnum::PRIMARY) =>
+                    {
+                        SelectionSource::Primary
+                    }
+                    Ok(_) => continue,
+                    Err(err) => {
+                        warn!("x11 clipboard watcher stopped: {err}");
+                        return;
+                    }
+                };
+                if source == SelectionSource::Primary && !capture_primary.load(Ordering::Relaxed) {
+                    continue;
+                }
+
+                if clipboard.is_none() {
+                    clipboard = MacOsClipboard::clipboard().ok();
+                }
+
+                if let Some(handle) = clipboard.as_mut() {
+                    let (payload, tracker) = match source {
+                        SelectionSource::Clipboard => (
+                            read_clipboard_payload(&reader, handle),
+                            &mut clipboard_tracker,
+                        ),
+                        SelectionSource::Primary => {
+                            (read_primary_payload(handle), &mut primary_tracker)
+                        }
+                    };
+                    match payload {
+                        Ok(Some(next)) => {
+                            let signature = MacOsClipboard::payload_signature(&next);
+                            if tracker.should_emit(&signature) {
+                                callback(source, next);
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(_) => {
+                            clipboard = None;
+                        }
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn set_capture_primary(&self, enabled: bool) {
+        self.capture_primary.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The `WM_CLASS` of the focused window, except that our own windows
+    /// report `app_id`, which is what the engine matches to skip its own
+    /// copies.
+    fn active_bundle_id(&self) -> Option<String> {
+        let window = self.active_window().ok().flatten()?;
+        if self.window_pid(window).ok().flatten() == Some(std::process::id()) {
+            return Some(self.app_id.clone());
+        }
+        self.wm_class(window).ok().flatten()
+    }
+
+    fn paste_into(&self, app_id: Option<&str>) -> Result<(), ClipboardError> {
+        let active = self.active_bundle_id();
+        if let Some(class) = app_id {
+            if active.as_deref() != Some(class) {
+                if let Some(window) = self.window_with_class(class)? {
+                    self.activate_window(window)?;
+                    thread::sleep(FOCUS_SETTLE);
+                }
+            }
+        }
+        let target = app_id.or(active.as_deref());
+        self.send_paste_keystroke(target.is_some_and(pastes_with_shift))
+    }
+}
+
+/// Reads CLIPBOARD targets over a connection of its own, so the owner's
+/// replies never get mixed up with the watcher's XFixes events.
+struct SelectionReader {
+    conn: RustConnection,
+    window: Window,
+    atoms: Atoms,
+    /// Interned `NATIVE_IMAGE_MIME_TYPES`, in the same order.
+    image_targets: Vec<Atom>,
+}
+
+impl SelectionReader {
+    fn connect() -> Result<Self, ClipboardError> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(x11_error)?;
+        let root = conn.setup().roots[screen_num].root;
+        let atoms = Atoms::new(&conn)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        let image_targets = NATIVE_IMAGE_MIME_TYPES
+            .iter()
+            .map(|mime| {
+                Ok(conn
+                    .intern_atom(false, mime.as_bytes())
+                    .map_err(x11_error)?
+                    .reply()
+                    .map_err(x11_error)?
+                    .atom)
+            })
+            .collect::<Result<Vec<_>, ClipboardError>>()?;
+
+        let window = conn.generate_id().map_err(x11_error)?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )
+        .map_err(x11_error)?;
+        conn.flush().map_err(x11_error)?;
+
+        Ok(Self {
+            conn,
+            window,
+            atoms,
+            image_targets,
+        })
+    }
+
+    /// The clipboard image in the first encoding from
+    /// `NATIVE_IMAGE_MIME_TYPES` the owner offers, bytes untouched.
+    fn read_encoded_image(&self) -> Option<ImagePayload> {
+        let targets = self.convert(self.atoms.TARGETS).ok().flatten()?;
+        let offered = atoms_from_bytes(&targets);
+        let target = self
+            .image_targets
+            .iter()
+            .find(|target| offered.contains(target))?;
+        let bytes = self.convert(*target).ok().flatten()?;
+        ImagePayload::from_encoded(bytes).ok()
+    }
+
+    /// Asks the CLIPBOARD owner for `target`; `None` when it refuses.
+    fn convert(&self, target: Atom) -> Result<Option<Vec<u8>>, ClipboardError> {
+        self.conn
+            .convert_selection(
+                self.window,
+                self.atoms.CLIPBOARD,
+                target,
+                self.atoms.KLIPPY_SELECTION,
+                x11rb::CURRENT_TIME,
+            )
+            .map_err(x11_error)?;
+        self.conn.flush().map_err(x11_error)?;
+
+        let window = self.window;
+        let notify = self.wait_for(|event| match event {
+            Event::SelectionNotify(notify)
+                if notify.requestor == window && notify.target == target =>
+            {
+                Some(notify)
+            }
+            _ => None,
+        })?;
+        if notify.property == x11rb::NONE {
+            return Ok(None);
+        }
+
+        let reply = self.take_property()?;
+        if reply.type_ != self.atoms.INCR {
+            return Ok(Some(reply.value));
+        }
+
+        // Large selections arrive in chunks, each one announced by a new
+        // value on our property; an empty chunk ends the transfer.
+        let property = self.atoms.KLIPPY_SELECTION;
+        let mut bytes = Vec::new();
+        loop {
+            self.wait_for(|event| match event {
+                Event::PropertyNotify(notify)
+                    if notify.window == window
+                        && notify.atom == property
+                        && notify.state == Property::NEW_VALUE =>
+                {
+                    Some(())
+                }
+                _ => None,
+            })?;
+            let chunk = self.take_property()?;
+            if chunk.value.is_empty() {
+                return Ok(Some(bytes));
+            }
+            bytes.extend_from_slice(&chunk.value);
+        }
+    }
+
+    /// Reads and deletes our property, which also tells an incremental
+    /// sender to go on with the next chunk.
+    fn take_property(&self) -> Result<GetPropertyReply, ClipboardError> {
+        self.conn
+            .get_property(
+                true,
+                self.window,
+                self.atoms.KLIPPY_SELECTION,
+                AtomEnum::ANY,
+                0,
+                u32::MAX,
+            )
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)
+    }
+
+    fn wait_for<T>(
+        &self,
+        mut matches: impl FnMut(Event) -> Option<T>,
+    ) -> Result<T, ClipboardError> {
+        let deadline = Instant::now() + RECEIVE_TIMEOUT;
+        loop {
+            match self.conn.poll_for_event().map_err(x11_error)? {
+                Some(event) => {
+                    if let Some(found) = matches(event) {
+                        return Ok(found);
+                    }
+                }
+                None if Instant::now() >= deadline => {
+                    return Err(ClipboardError::Command(
+                        "clipboard owner did not answer in time".to_string(),
+                    ))
+                }
+                None => thread::sleep(RECEIVE_POLL),
+            }
+        }
+    }
+}
+
+/// CLIPBOARD through arboard, except that images come from `reader` in
+/// their original encoding when the owner offers one.
+fn read_clipboard_payload(
+    reader: &Mutex<SelectionReader>,
+    clipboard: &mut Clipboard,
+) -> Result<Option<ClipboardPayload>, ClipboardError> {
+    MacOsClipboard::read_payload_with(clipboard, || {
+        reader
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .read_encoded_image()
+    })
+}
+
+/// `TARGETS` replies are a list of 32-bit atoms in native byte order.
+fn atoms_from_bytes(bytes: &[u8]) -> Vec<Atom> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+/// PRIMARY only ever carries text in practice, so images are not read here.
+fn read_primary_payload(
+    clipboard: &mut Clipboard,
+) -> Result<Option<ClipboardPayload>, ClipboardError> {
+    Ok(clipboard
+        .get()
+        .clipboard(LinuxClipboardKind::Primary)
+        .text()
+        .ok()
+        .filter(|text| !text.is_empty())
+        .map(ClipboardPayload::Text))
+}
+
+fn pastes_with_shift(class: &str) -> bool {
+    CTRL_SHIFT_V_TERMINALS.contains(&class.to_ascii_lowercase().as_str())
+}
+
+/// Finds the first keycode whose mapping lists `keysym` in any column.
+fn keycode_for(
+    keysym: u32,
+    first_keycode: Keycode,
+    keysyms_per_keycode: u8,
+    keysyms: &[u32],
+) -> Option<Keycode> {
+    let width = usize::from(keysyms_per_keycode).max(1);
+    keysyms
+        .chunks(width)
+        .position(|row| row.contains(&keysym))
+        .and_then(|index| u8::try_from(usize::from(first_keycode) + index).ok())
+}
+
+/// `WM_CLASS` holds two NUL-terminated strings, instance then class. The
+/// class name is the stable identifier, so prefer it over the instance.
+fn parse_wm_class(value: &[u8]) -> Option<String> {
+    let mut parts = value
+        .split(|byte| *byte == 0)
+        .map(|part| String::from_utf8_lossy(part).trim().to_string());
+    let instance = parts.next().filter(|part| !part.is_empty());
+    let class = parts.next().filter(|part| !part.is_empty());
+    class.or(instance)
+}
+
+fn x11_error(err: impl std::fmt::Display) -> ClipboardError {
+    ClipboardError::Command(format!("x11 request failed: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::sync::mpsc;
+
+    use arboard::{ImageData, SetExtLinux};
+    use x11rb::protocol::xproto::PropMode;
+    use x11rb::wrapper::ConnectionExt as _;
+
+    use super::*;
+
+    const TEST_APP_ID: &str = "com.klippy.test";
+
+    #[test]
+    fn wm_class_prefers_class_over_instance() {
+        assert_eq!(
+            parse_wm_class(b"navigator\0firefox\0"),
+            Some("firefox".to_string())
+        );
+        assert_eq!(parse_wm_class(b"kitty\0\0"), Some("kitty".to_string()));
+        assert_eq!(parse_wm_class(b""), None);
+    }
+
+    #[test]
+    fn terminals_paste_with_ctrl_shift_v() {
+        assert!(pastes_with_shift("Gnome-terminal"));
+        assert!(pastes_with_shift("kitty"));
+        assert!(!pastes_with_shift("firefox"));
+        assert!(!pastes_with_shift("XTerm"));
+    }
+
+    #[test]
+    fn finds_keycodes_in_any_mapping_column() {
+        let keysyms = [0x0061, 0x0041, XK_V, 0x0056, XK_CONTROL_L, 0];
+        assert_eq!(keycode_for(XK_V, 8, 2, &keysyms), Some(9));
+        assert_eq!(keycode_for(0x0041, 8, 2, &keysyms), Some(8));
+        assert_eq!(keycode_for(XK_CONTROL_L, 8, 2, &keysyms), Some(10));
+        assert_eq!(keycode_for(0xffff, 8, 2, &keysyms), None);
+    }
+
+    #[test]
+    fn targets_are_read_as_native_endian_atoms() {
+        let bytes = [31u32, 470]
+            .iter()
+            .flat_map(|atom| atom.to_ne_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(atoms_from_bytes(&bytes), vec![31, 470]);
+    }
+
+    #[test]
+    #[ignore = "requires an X server, run with `xvfb-run cargo test -- --ignored`"]
+    fn reads_images_in_the_encoding_the_owner_offers() {
+        let mut clipboard = Clipboard::new().expect("clipboard");
+        clipboard
+            .set_image(ImageData {
+                width: 2,
+                height: 1,
+                bytes: Cow::Owned(vec![255, 0, 0, 255, 0, 0, 255, 255]),
+            })
+            .expect("set clipboard image");
+
+        let reader = SelectionReader::connect().expect("reader");
+        let image = reader.read_encoded_image().expect("encoded image");
+        assert_eq!(image.mime, "image/png");
+        assert_eq!((image.width, image.height), (2, 1));
+    }
+
+    #[test]
+    #[ignore = "requires an X server, run with `xvfb-run cargo test -- --ignored`"]
+    fn reports_wm_class_of_active_window_and_app_id_for_our_own() {
+        let service = X11Clipboard::connect(TEST_APP_ID).expect("connect");
+        let (conn, screen_num) = x11rb::connect(None).expect("test connection");
+        let root = conn.setup().roots[screen_num].root;
+        let window = conn.generate_id().expect("window id");
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new(),
+        )
+        .expect("create window");
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            b"klippy-test\0KlippyTest\0",
+        )
+        .expect("set wm class");
+        conn.change_property32(
+            PropMode::REPLACE,
+            root,
+            service.atoms._NET_ACTIVE_WINDOW,
+            AtomEnum::WINDOW,
+            &[window],
+        )
+        .expect("set active window");
+        conn.sync().expect("sync");
+
+        assert_eq!(service.active_bundle_id(), Some("KlippyTest".to_string()));
+
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            service.atoms._NET_WM_PID,
+            AtomEnum::CARDINAL,
+            &[std::process::id()],
+        )
+        .expect("set pid");
+        conn.sync().expect("sync");
+
+        assert_eq!(service.active_bundle_id(), Some(TEST_APP_ID.to_string()));
+    }
+
+    #[test]
+    #[ignore = "requires an X server, run with `xvfb-run cargo test -- --ignored`"]
+    fn emits_clipboard_owner_changes() {
+        let service = X11Clipboard::connect(TEST_APP_ID).expect("connect");
+        let (tx, rx) = mpsc::channel();
+        service
+            .watch_changes(Arc::new(move |source, payload| {
+                let _ = tx.send((source, payload));
+            }))
+            .expect("watch");
+        thread::sleep(Duration::from_millis(DEBOUNCE_MS * 2));
+
+        let mut clipboard = Clipboard::new().expect("clipboard");
+        clipboard
+            .set_text("copied under xvfb")
+            .expect("set clipboard text");
+
+        let payload = rx
+            .recv_timeout(Duration::from_secs(3))
+            .expect("clipboard change");
+        assert_eq!(
+            payload,
+            (
+                SelectionSource::Clipboard,
+                ClipboardPayload::Text("copied under xvfb".to_string())
+            )
+        );
+
+        clipboard
+            .set()
+            .clipboard(LinuxClipboardKind::Primary)
+            .text("selected under xvfb")
+            .expect("set primary text");
+
+        let payload = rx
+            .recv_timeout(Duration::from_secs(3))
+            .expect("primary change");
+        assert_eq!(
+            payload,
+            (
+                SelectionSource::Primary,
+                ClipboardPayload::Text("selected under xvfb".to_string())
+            )
+        );
+    }
+}
@@ -1,46 +1,110 @@
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
 use serde::Serialize;
-use tauri::{AppHandle, Emitter, State};
+use tauri::{AppHandle, Manager, State};
 
-use crate::db::ClipPage;
+use crate::db::{ClipListRequest, ClipPage, ClipSort, SearchMode, Settings, SettingsUpdate, Tag};
 use crate::services::clip_engine::ClipEngine;
+use crate::services::encryption::{self, EnableEncryption, EncryptionStatus};
+use crate::services::events::ClipEvent;
+use crate::services::media_store::MediaStore;
+use crate::services::pause::PauseDuration;
+use crate::services::prune::RetentionReport;
 
 pub struct AppState {
     pub engine: Arc<ClipEngine>,
+    /// Serves decrypted originals and thumbnails to the webview.
+    pub media_store: Arc<MediaStore>,
+    pub app_data_dir: PathBuf,
+    /// Where a generated key file goes on systems without a keychain.
+    pub key_dir: PathBuf,
 }
 
-#[derive(Clone, Debug, Serialize)]
-struct DeletedPayload {
-    id: i64,
+/// How far startup has got with the history. Commands that take `AppState`
+/// fail until it is `Open`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HistoryState {
+    Opening,
+    Locked,
+    Open,
+}
+
+/// Holds the `HistoryState`, so the history is opened at most once.
+pub struct HistoryGate(Mutex<HistoryState>);
+
+impl HistoryGate {
+    pub fn new(state: HistoryState) -> Self {
+        Self(Mutex::new(state))
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, HistoryState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub fn list_clips(
     state: State<'_, AppState>,
     query: Option<String>,
+    source: Option<String>,
+    mode: Option<SearchMode>,
+    sort: Option<ClipSort>,
+    cursor: Option<String>,
     limit: i64,
-    offset: i64,
+    include_total: Option<bool>,
 ) -> Result<ClipPage, String> {
     state
         .engine
         .db()
-        .list_clips(query.as_deref(), limit, offset)
+        .list_clips(ClipListRequest {
+            query: query.as_deref(),
+            source: source.as_deref(),
+            mode: mode.unwrap_or_default(),
+            sort: sort.unwrap_or_default(),
+            cursor: cursor.as_deref(),
+            limit,
+            include_total: include_total.unwrap_or(false),
+        })
         .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
-pub fn copy_clip(state: State<'_, AppState>, id: i64) -> Result<(), String> {
-    state.engine.copy_clip(id).map_err(|err| err.to_string())
+pub fn copy_clip(
+    state: State<'_, AppState>,
+    id: i64,
+    plain_text: Option<bool>,
+) -> Result<(), String> {
+    let result = if plain_text.unwrap_or(false) {
+        state.engine.copy_clip_plain_text(id)
+    } else {
+        state.engine.copy_clip(id)
+    };
+    result.map_err(|err| err.to_string())
 }
 
-#[tauri::command]
-pub fn set_pinned(
+/// Hides the window and pastes a clip into the app it was opened from.
+/// Runs off the main thread because the paste waits for focus to settle.
+#[tauri::command(async)]
+pub fn paste_clip(
     app: AppHandle,
     state: State<'_, AppState>,
     id: i64,
-    pinned: bool,
+    plain_text: Option<bool>,
 ) -> Result<(), String> {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.hide();
+    }
+    state
+        .engine
+        .paste_clip(id, !plain_text.unwrap_or(false))
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn set_pinned(state: State<'_, AppState>, id: i64, pinned: bool) -> Result<(), String> {
     let clip = state
         .engine
         .db()
@@ -48,15 +112,32 @@ pub fn set_pinned(
         .map_err(|err| err.to_string())?;
 
     if let Some(clip) = clip {
-        let _ = app.emit("clips://updated", clip);
+        if clip.expires_at.is_some() && !pinned {
+            state.engine.wake_reaper();
+        }
+        state.engine.events().emit(ClipEvent::Updated(clip));
         Ok(())
     } else {
         Err("clip not found".to_string())
     }
 }
 
+/// Orders the pinned section by `ids`, top first.
+#[tauri::command]
+pub fn reorder_pinned(state: State<'_, AppState>, ids: Vec<i64>) -> Result<(), String> {
+    let pinned = state
+        .engine
+        .db()
+        .reorder_pinned(&ids)
+        .map_err(|err| err.to_string())?;
+    state.engine.events().emit(ClipEvent::PinsReordered(
+        pinned.into_iter().map(|clip| clip.id).collect(),
+    ));
+    Ok(())
+}
+
 #[tauri::command]
-pub fn delete_clip(app: AppHandle, state: State<'_, AppState>, id: i64) -> Result<(), String> {
+pub fn delete_clip(state: State<'_, AppState>, id: i64) -> Result<(), String> {
     let deleted = state
         .engine
         .db()
@@ -68,7 +149,7 @@ pub fn delete_clip(app: AppHandle, state: State<'_, AppState>, id: i64) -> Resul
             .engine
             .cleanup_clip_media(&clip)
             .map_err(|err| err.to_string())?;
-        let _ = app.emit("clips://deleted", DeletedPayload { id });
+        state.engine.events().emit(ClipEvent::Deleted(id));
         Ok(())
     } else {
         Err("clip not found".to_string())
@@ -76,7 +157,7 @@ pub fn delete_clip(app: AppHandle, state: State<'_, AppState>, id: i64) -> Resul
 }
 
 #[tauri::command]
-pub fn clear_all_clips(app: AppHandle, state: State<'_, AppState>) -> Result<usize, String> {
+pub fn clear_all_clips(state: State<'_, AppState>) -> Result<usize, String> {
     let deleted = state
         .engine
         .db()
@@ -86,10 +167,183 @@ pub fn clear_all_clips(app: AppHandle, state: State<'_, AppState>) -> Result<usi
         .engine
         .cleanup_media_for_clips(&deleted)
         .map_err(|err| err.to_string())?;
-    let _ = app.emit("clips://updated", true);
+    state.engine.events().emit(ClipEvent::Cleared);
     Ok(deleted.len())
 }
 
+/// Tells the webview the tag list changed, including clip counts.
+fn emit_tags_updated(state: &AppState) -> Result<(), String> {
+    let tags = state
+        .engine
+        .db()
+        .list_tags()
+        .map_err(|err| err.to_string())?;
+    state.engine.events().emit(ClipEvent::TagsUpdated(tags));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_tags(state: State<'_, AppState>) -> Result<Vec<Tag>, String> {
+    state.engine.db().list_tags().map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn create_tag(
+    state: State<'_, AppState>,
+    name: String,
+    protected: Option<bool>,
+) -> Result<Tag, String> {
+    let tag = state
+        .engine
+        .db()
+        .create_tag(&name, protected.unwrap_or(false))
+        .map_err(|err| err.to_string())?;
+    emit_tags_updated(&state)?;
+    Ok(tag)
+}
+
+#[tauri::command]
+pub fn rename_tag(state: State<'_, AppState>, id: i64, name: String) -> Result<Tag, String> {
+    let tag = state
+        .engine
+        .db()
+        .rename_tag(id, &name)
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "tag not found".to_string())?;
+    emit_tags_updated(&state)?;
+    Ok(tag)
+}
+
+#[tauri::command]
+pub fn set_tag_protected(
+    state: State<'_, AppState>,
+    id: i64,
+    protected: bool,
+) -> Result<Tag, String> {
+    let tag = state
+        .engine
+        .db()
+        .set_tag_protected(id, protected)
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "tag not found".to_string())?;
+    emit_tags_updated(&state)?;
+    Ok(tag)
+}
+
+#[tauri::command]
+pub fn delete_tag(state: State<'_, AppState>, id: i64) -> Result<(), String> {
+    state
+        .engine
+        .db()
+        .delete_tag(id)
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "tag not found".to_string())?;
+    emit_tags_updated(&state)
+}
+
+#[tauri::command]
+pub fn tag_clip(state: State<'_, AppState>, clip_id: i64, tag_id: i64) -> Result<(), String> {
+    let clip = state
+        .engine
+        .db()
+        .tag_clip(clip_id, tag_id)
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "clip or tag not found".to_string())?;
+    state.engine.events().emit(ClipEvent::Updated(clip));
+    emit_tags_updated(&state)
+}
+
+#[tauri::command]
+pub fn untag_clip(state: State<'_, AppState>, clip_id: i64, tag_id: i64) -> Result<(), String> {
+    let clip = state
+        .engine
+        .db()
+        .untag_clip(clip_id, tag_id)
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "clip not found".to_string())?;
+    state.engine.events().emit(ClipEvent::Updated(clip));
+    emit_tags_updated(&state)
+}
+
+#[tauri::command]
+pub fn get_settings(state: State<'_, AppState>) -> Result<Settings, String> {
+    state
+        .engine
+        .db()
+        .get_settings()
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn update_settings(
+    state: State<'_, AppState>,
+    update: SettingsUpdate,
+) -> Result<Settings, String> {
+    state
+        .engine
+        .update_settings(update)
+        .map_err(|err| err.to_string())
+}
+
+/// Dry run of pruning with `update` applied, for previewing a settings
+/// change before saving it.
+#[tauri::command]
+pub fn preview_retention(
+    state: State<'_, AppState>,
+    update: Option<SettingsUpdate>,
+) -> Result<RetentionReport, String> {
+    state
+        .engine
+        .preview_retention(update.unwrap_or_default())
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn get_encryption_status(state: State<'_, AppState>) -> Result<EncryptionStatus, String> {
+    encryption::status(&state.app_data_dir, state.media_store.encrypted())
+        .map_err(|err| err.to_string())
+}
+
+/// Turns on encryption at rest from the next launch, which also encrypts
+/// the existing history.
+#[tauri::command]
+pub fn enable_encryption(
+    state: State<'_, AppState>,
+    request: EnableEncryption,
+) -> Result<EncryptionStatus, String> {
+    encryption::enable(&state.app_data_dir, &state.key_dir, request)
+        .map_err(|err| err.to_string())?;
+    get_encryption_status(state)
+}
+
+/// `Locked` while the history waits on its passphrase. `history://opened`
+/// follows once it is open.
+#[tauri::command]
+pub fn history_state(gate: State<'_, HistoryGate>) -> HistoryState {
+    *gate.lock()
+}
+
+/// Runs off the main thread: deriving the key takes a moment, and the first
+/// unlock after turning encryption on encrypts the whole existing history
+/// and its media, which would freeze the window meanwhile.
+#[tauri::command(async)]
+pub fn unlock_history(app: AppHandle, passphrase: String) -> Result<(), String> {
+    crate::unlock_history(&app, &passphrase)
+}
+
+#[tauri::command]
+pub fn pause_tracking(
+    state: State<'_, AppState>,
+    duration: PauseDuration,
+) -> Result<Settings, String> {
+    state.engine.pause(duration).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn resume_tracking(state: State<'_, AppState>) -> Result<Settings, String> {
+    state.engine.resume().map_err(|err| err.to_string())
+}
+
 #[tauri::command]
 pub fn stop_app(app: AppHandle) -> Result<(), String> {
     app.exit(0);
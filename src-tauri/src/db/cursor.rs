@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Clip, DbError};
+
+/// Sort key of the last clip on a page, handed to the webview as an opaque
+/// token. The next page starts strictly after it, so clips captured or
+/// pruned in the meantime never shift what comes next.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageCursor {
+    #[serde(rename = "p")]
+    pub pinned: bool,
+    #[serde(rename = "o", default)]
+    pub pin_order: i64,
+    #[serde(rename = "c")]
+    pub sort_at: String,
+    #[serde(rename = "i")]
+    pub id: i64,
+    /// Match score of the last clip, for search results ordered by relevance.
+    #[serde(rename = "s", default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// `julianday('now')` of the first search page, so the recency boost
+    /// doesn't drift from one page to the next.
+    #[serde(rename = "n", default, skip_serializing_if = "Option::is_none")]
+    pub now: Option<f64>,
+}
+
+impl PageCursor {
+    pub fn after(clip: &Clip, score: Option<f64>, now: Option<f64>) -> Self {
+        Self {
+            pinned: clip.pinned,
+            pin_order: clip.pin_order,
+            sort_at: clip.sort_at.clone(),
+            id: clip.id,
+            score,
+            now,
+        }
+    }
+
+    pub fn encode(&self) -> Result<String, DbError> {
+        Ok(serde_json::to_vec(self)?
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect())
+    }
+
+    pub fn decode(token: &str) -> Result<Self, DbError> {
+        if token.len() % 2 != 0 || !token.is_ascii() {
+            return Err(DbError::InvalidCursor);
+        }
+        let bytes = (0..token.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&token[index..index + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| DbError::InvalidCursor)?;
+        serde_json::from_slice(&bytes).map_err(|_| DbError::InvalidCursor)
+    }
+
+    /// The score a ranked page resumes from; browse cursors don't carry one.
+    pub fn ranked(&self) -> Result<(f64, f64), DbError> {
+        match (self.score, self.now) {
+            (Some(score), Some(now)) => Ok((score, now)),
+            _ => Err(DbError::InvalidCursor),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_an_opaque_token() {
+        let cursor = PageCursor {
+            pinned: true,
+            pin_order: 3,
+            sort_at: "2026-10-01 12:00:00".to_string(),
+            id: 42,
+            score: Some(-1.25e-6),
+            now: Some(2_461_315.5),
+        };
+        let token = cursor.encode().expect("encode");
+        assert!(token.chars().all(|ch| ch.is_ascii_hexdigit()));
+        assert_eq!(PageCursor::decode(&token).expect("decode"), cursor);
+    }
+
+    #[test]
+    fn scores_survive_the_token_bit_for_bit() {
+        // Pages resume on exact score equality, so a lossy float parse would
+        // repeat or skip clips at page boundaries.
+        for score in [
+            1.0 / 3.0,
+            0.999_999_880_790_710_4,
+            -7.105_427_357_601_002e-15,
+        ] {
+            let cursor = PageCursor {
+                pinned: false,
+                pin_order: 0,
+                sort_at: "2026-10-01 12:00:00.123".to_string(),
+                id: 7,
+                score: Some(score),
+                now: Some(2_461_315.512_345_678),
+            };
+            let token = cursor.encode().expect("encode");
+            assert_eq!(PageCursor::decode(&token).expect("decode"), cursor);
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_tokens() {
+        for token in ["", "zz", "abc", "7b7d", "é1"] {
+            assert!(
+                matches!(PageCursor::decode(token), Err(DbError::InvalidCursor)),
+                "{token}"
+            );
+        }
+    }
+}
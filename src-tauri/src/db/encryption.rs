@@ -0,0 +1,173 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, DatabaseName, ErrorCode};
+
+use super::DbError;
+use crate::utils::crypto::Cipher;
+
+/// The first bytes of every unencrypted SQLite file. SQLCipher files start
+/// with random salt instead.
+const PLAINTEXT_HEADER: &[u8; 16] = b"SQLite format 3\0";
+const EXPORT_SCHEMA: &str = "encrypted";
+
+/// Keys a freshly opened connection and checks that the key fits.
+pub fn unlock(conn: &Connection, cipher: &Cipher) -> Result<(), DbError> {
+    conn.execute_batch(&format!("PRAGMA key = \"{}\";", cipher.sqlcipher_key()))?;
+    match conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    }) {
+        Ok(_) => Ok(()),
+        Err(err) if err.sqlite_error_code() == Some(ErrorCode::NotADatabase) => {
+            Err(DbError::WrongKey)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Encrypts a plaintext database at `path`, and the migration backups next
+/// to it, with `cipher`. Files that are already encrypted are left alone,
+/// so this runs once per history. Returns how many files were encrypted.
+pub fn encrypt_existing(path: &Path, cipher: &Cipher) -> Result<usize, DbError> {
+    let mut encrypted = 0;
+    for file in std::iter::once(path.to_path_buf()).chain(backups(path)?) {
+        if is_plaintext(&file)? {
+            encrypt_file(&file, cipher)?;
+            encrypted += 1;
+        }
+    }
+    Ok(encrypted)
+}
+
+fn is_plaintext(path: &Path) -> Result<bool, DbError> {
+    let mut header = [0; PLAINTEXT_HEADER.len()];
+    match fs::File::open(path) {
+        Ok(mut file) => Ok(file.read_exact(&mut header).is_ok() && &header == PLAINTEXT_HEADER),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Exports into a sibling file that replaces `path` only once complete, so
+/// an interrupted run leaves the plaintext file to start over from.
+fn encrypt_file(path: &Path, cipher: &Cipher) -> Result<(), DbError> {
+    let encrypted_path = with_suffix(path, ".encrypting");
+    remove_if_exists(&encrypted_path)?;
+    {
+        let conn = Connection::open(path)?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        conn.execute(
+            &format!("ATTACH DATABASE ?1 AS {EXPORT_SCHEMA} KEY ?2"),
+            params![encrypted_path.to_string_lossy(), cipher.sqlcipher_key()],
+        )?;
+        conn.query_row(
+            "SELECT sqlcipher_export(?1)",
+            params![EXPORT_SCHEMA],
+            |_| Ok(()),
+        )?;
+        // sqlcipher_export copies schema and rows but not the header fields.
+        conn.pragma_update(
+            Some(DatabaseName::Attached(EXPORT_SCHEMA)),
+            "user_version",
+            version,
+        )?;
+        conn.execute(&format!("DETACH DATABASE {EXPORT_SCHEMA}"), [])?;
+    }
+    remove_if_exists(&with_suffix(path, "-wal"))?;
+    remove_if_exists(&with_suffix(path, "-shm"))?;
+    fs::rename(&encrypted_path, path)?;
+    Ok(())
+}
+
+/// Copies migrations wrote before upgrading, named `<name>.v<version>.bak`.
+fn backups(path: &Path) -> Result<Vec<PathBuf>, DbError> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let prefix = format!("{}.v", name.to_string_lossy());
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if file_name.starts_with(&prefix) && file_name.ends_with(".bak") {
+            backups.push(entry.path());
+        }
+    }
+    Ok(backups)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), DbError> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::db::migrations::backup_path;
+    use crate::db::Database;
+
+    fn contains(path: &Path, needle: &[u8]) -> bool {
+        let bytes = fs::read(path).expect("read database file");
+        bytes.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[test]
+    fn encrypts_plaintext_histories_once_and_rejects_other_keys() {
+        let dir = env::temp_dir().join(format!("klippy-encrypt-{}", Uuid::new_v4()));
+        let db_path = dir.join("klippy.sqlite3");
+        let backup = backup_path(&db_path, 16);
+        let clip = {
+            let db = Database::new(&db_path).expect("plaintext db");
+            let clip = db
+                .insert_clip("quarterly revenue draft", "text")
+                .expect("insert");
+            db.conn()
+                .expect("conn")
+                .execute("VACUUM INTO ?1", params![backup.to_string_lossy()])
+                .expect("backup");
+            clip
+        };
+        assert!(contains(&db_path, b"quarterly") && contains(&backup, b"quarterly"));
+
+        let cipher = Cipher::new([3; 32]);
+        assert_eq!(encrypt_existing(&db_path, &cipher).expect("encrypt"), 2);
+        assert_eq!(encrypt_existing(&db_path, &cipher).expect("again"), 0);
+        assert!(!contains(&db_path, b"quarterly") && !contains(&backup, b"quarterly"));
+
+        let db = Database::new_encrypted(&db_path, &cipher).expect("encrypted db");
+        assert_eq!(db.get_clip(clip.id).expect("get"), Some(clip));
+        let matches: i64 = db
+            .conn()
+            .expect("conn")
+            .query_row(
+                "SELECT COUNT(*) FROM clips_fts WHERE clips_fts MATCH 'quarterly'",
+                [],
+                |row| row.get(0),
+            )
+            .expect("search");
+        assert_eq!(matches, 1);
+        drop(db);
+
+        assert!(matches!(
+            Database::new_encrypted(&db_path, &Cipher::new([4; 32])),
+            Err(DbError::WrongKey)
+        ));
+        assert!(Database::new(&db_path).is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,313 @@
+use super::query::Term;
+use super::MatchRange;
+
+/// Only the start of a clip is scored, so a multi-megabyte paste costs the
+/// same as a short one.
+pub const MAX_FUZZY_CHARS: usize = 4096;
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CONSECUTIVE: i64 = 6;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 1;
+const PENALTY_TYPO: i64 = 20;
+/// Words shorter than this must match as a subsequence; typo tolerance on
+/// them matches nearly everything.
+const MIN_TYPO_WORD_CHARS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// Matched character indices into the candidate, ascending.
+    pub positions: Vec<usize>,
+}
+
+/// Scores `candidate` against every term, command-palette style: words match
+/// as case-insensitive subsequences (`kubctl` finds `kubectl`), or failing
+/// that within one or two typos of a whole word; phrases must appear
+/// verbatim. Returns `None` unless every term matches.
+pub fn match_terms(terms: &[Term], candidate: &str) -> Option<FuzzyMatch> {
+    let original = candidate.chars().take(MAX_FUZZY_CHARS).collect::<Vec<_>>();
+    let text = original.iter().map(|ch| fold(*ch)).collect::<Vec<_>>();
+    let boundaries = word_boundaries(&original);
+
+    let mut score = 0;
+    let mut positions = Vec::new();
+    for term in terms {
+        let found = match term {
+            Term::Word(word) => {
+                let pattern = word.chars().map(fold).collect::<Vec<_>>();
+                match_subsequence(&pattern, &text, &boundaries)
+                    .or_else(|| match_typo(&pattern, &text, &boundaries))?
+            }
+            Term::Phrase(phrase) => {
+                let pattern = phrase.chars().map(fold).collect::<Vec<_>>();
+                match_phrase(&pattern, &text, &boundaries)?
+            }
+        };
+        score += found.score;
+        positions.extend(found.positions);
+    }
+    positions.sort_unstable();
+    positions.dedup();
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Collapses matched character indices into UTF-16 ranges over `content`.
+pub fn highlight_ranges(content: &str, positions: &[usize]) -> Vec<MatchRange> {
+    let mut ranges: Vec<MatchRange> = Vec::new();
+    let mut wanted = positions.iter().peekable();
+    let mut offset = 0;
+    for (index, ch) in content.chars().enumerate() {
+        let Some(&&next) = wanted.peek() else {
+            break;
+        };
+        let end = offset + ch.len_utf16();
+        if index == next {
+            wanted.next();
+            match ranges.last_mut() {
+                Some(last) if last.end == offset => last.end = end,
+                _ => ranges.push(MatchRange { start: offset, end }),
+            }
+        }
+        offset = end;
+    }
+    ranges
+}
+
+/// Case folding that keeps one char per char, so indices stay aligned with
+/// the original text.
+fn fold(ch: char) -> char {
+    ch.to_lowercase().next().unwrap_or(ch)
+}
+
+fn word_boundaries(text: &[char]) -> Vec<bool> {
+    text.iter()
+        .enumerate()
+        .map(|(index, ch)| {
+            if !ch.is_alphanumeric() {
+                return false;
+            }
+            match index.checked_sub(1).map(|prev| text[prev]) {
+                None => true,
+                Some(prev) => !prev.is_alphanumeric() || (prev.is_lowercase() && ch.is_uppercase()),
+            }
+        })
+        .collect()
+}
+
+/// Finds the shortest window ending at the first complete subsequence match,
+/// then scores the earliest alignment inside it. Matches whose gaps outweigh
+/// their hits are rejected so short words don't match every long clip.
+fn match_subsequence(pattern: &[char], text: &[char], boundaries: &[bool]) -> Option<FuzzyMatch> {
+    if pattern.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+
+    let mut matched = 0;
+    let mut end = None;
+    for (index, ch) in text.iter().enumerate() {
+        if *ch == pattern[matched] {
+            matched += 1;
+            if matched == pattern.len() {
+                end = Some(index);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    let mut remaining = pattern.len();
+    let mut start = end;
+    for index in (0..=end).rev() {
+        if text[index] == pattern[remaining - 1] {
+            remaining -= 1;
+            if remaining == 0 {
+                start = index;
+                break;
+            }
+        }
+    }
+
+    let mut score = 0;
+    let mut positions = Vec::with_capacity(pattern.len());
+    let mut next = 0;
+    let mut gap = 0;
+    for index in start..=end {
+        if next < pattern.len() && text[index] == pattern[next] {
+            score += SCORE_MATCH;
+            if boundaries[index] {
+                score += BONUS_BOUNDARY;
+            }
+            if gap == 0 && next > 0 {
+                score += BONUS_CONSECUTIVE;
+            } else if gap > 0 {
+                score -= PENALTY_GAP_START + PENALTY_GAP_EXTENSION * (gap - 1);
+            }
+            positions.push(index);
+            next += 1;
+            gap = 0;
+        } else {
+            gap += 1;
+        }
+    }
+
+    (score > 0).then_some(FuzzyMatch { score, positions })
+}
+
+/// Compares the pattern against each word of the text (and that word's
+/// prefix of the same length, for half-typed words), allowing one typo, or
+/// two for long words.
+fn match_typo(pattern: &[char], text: &[char], boundaries: &[bool]) -> Option<FuzzyMatch> {
+    if pattern.len() < MIN_TYPO_WORD_CHARS {
+        return None;
+    }
+    let max_typos = if pattern.len() >= 8 { 2 } else { 1 };
+
+    let mut best: Option<(usize, usize, usize)> = None;
+    let mut index = 0;
+    while index < text.len() {
+        if !boundaries[index] {
+            index += 1;
+            continue;
+        }
+        let word_end = (index..text.len())
+            .find(|&end| end > index && (boundaries[end] || !text[end].is_alphanumeric()))
+            .unwrap_or(text.len());
+        let word = &text[index..word_end];
+
+        let prefix_lens = [pattern.len(), pattern.len() + 1].map(|len| len.min(word.len()));
+        for len in [word.len(), prefix_lens[0], prefix_lens[1]] {
+            let typos = edit_distance(pattern, &word[..len]);
+            let better = match best {
+                Some((best_typos, ..)) => typos < best_typos,
+                None => true,
+            };
+            if typos <= max_typos && better {
+                best = Some((typos, index, len));
+            }
+        }
+        index = word_end;
+    }
+
+    let (typos, start, len) = best?;
+    Some(FuzzyMatch {
+        score: SCORE_MATCH * len as i64 + BONUS_BOUNDARY - PENALTY_TYPO * typos as i64,
+        positions: (start..start + len).collect(),
+    })
+}
+
+fn match_phrase(pattern: &[char], text: &[char], boundaries: &[bool]) -> Option<FuzzyMatch> {
+    if pattern.is_empty() || pattern.len() > text.len() {
+        return None;
+    }
+    let start = text
+        .windows(pattern.len())
+        .position(|window| window == pattern)?;
+    let bonus = if boundaries[start] { BONUS_BOUNDARY } else { 0 };
+    Some(FuzzyMatch {
+        score: (SCORE_MATCH + BONUS_CONSECUTIVE) * pattern.len() as i64 + bonus,
+        positions: (start..start + pattern.len()).collect(),
+    })
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions
+/// and adjacent transpositions each count as one typo.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let width = b.len() + 1;
+    let mut rows = vec![vec![0; width]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(query: &str) -> Vec<Term> {
+        query
+            .split_whitespace()
+            .map(|word| Term::Word(word.to_string()))
+            .collect()
+    }
+
+    fn score(query: &str, candidate: &str) -> Option<i64> {
+        match_terms(&words(query), candidate).map(|found| found.score)
+    }
+
+    #[test]
+    fn matches_subsequences_and_reports_positions() {
+        let found = match_terms(&words("kubctl apply"), "kubectl apply -f deploy.yaml")
+            .expect("subsequence match");
+        assert_eq!(found.positions, vec![0, 1, 2, 4, 5, 6, 8, 9, 10, 11, 12]);
+        assert_eq!(
+            highlight_ranges("kubectl apply -f deploy.yaml", &found.positions),
+            vec![
+                MatchRange { start: 0, end: 3 },
+                MatchRange { start: 4, end: 7 },
+                MatchRange { start: 8, end: 13 },
+            ]
+        );
+        assert_eq!(score("kubctl delete", "kubectl apply -f deploy.yaml"), None);
+    }
+
+    #[test]
+    fn tolerates_transpositions_and_substitutions() {
+        assert!(score("kuebctl", "kubectl get pods").is_some());
+        assert!(score("deplyo", "kubectl apply -f deploy.yaml").is_some());
+        assert!(score("promethues", "grafana and prometheus").is_some());
+        assert_eq!(score("kxyzctl", "kubectl get pods"), None);
+        assert_eq!(score("tpo", "top"), None, "short words need a subsequence");
+    }
+
+    #[test]
+    fn ranks_boundaries_and_runs_above_scattered_hits() {
+        let tight = score("gst", "git status").expect("git status");
+        let scattered = score("gst", "biggest").expect("biggest");
+        assert!(tight > scattered);
+
+        let exact = score("deploy", "deploy the app").expect("exact");
+        let typo = score("deplyo", "deploy the app").expect("typo");
+        assert!(exact > typo);
+
+        assert_eq!(
+            score("ab", &format!("a{}b", " ".repeat(200))),
+            None,
+            "far-apart hits are not a match"
+        );
+    }
+
+    #[test]
+    fn phrases_and_highlights_handle_unicode() {
+        let terms = vec![Term::Phrase("Grüße aus".to_string())];
+        let found = match_terms(&terms, "🎉 grüße aus Köln").expect("phrase");
+        assert_eq!(
+            highlight_ranges("🎉 grüße aus Köln", &found.positions),
+            vec![MatchRange { start: 3, end: 12 }]
+        );
+        assert_eq!(
+            match_terms(&[Term::Phrase("aus grüße".to_string())], "grüße aus"),
+            None
+        );
+    }
+}
@@ -0,0 +1,867 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::DbError;
+
+/// One schema step, applied in its own transaction and recorded in
+/// `PRAGMA user_version`. Released steps are frozen: change the schema by
+/// appending a new one, never by editing an old one.
+#[derive(Clone, Copy)]
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub up: fn(&Connection) -> Result<(), DbError>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "text clips and settings",
+        up: v1_text_clips,
+    },
+    Migration {
+        version: 2,
+        description: "image clips",
+        up: v2_image_clips,
+    },
+    Migration {
+        version: 3,
+        description: "selection source",
+        up: v3_selection_source,
+    },
+    Migration {
+        version: 4,
+        description: "rich text flavors",
+        up: v4_rich_text,
+    },
+    Migration {
+        version: 5,
+        description: "file list clips",
+        up: v5_file_lists,
+    },
+    Migration {
+        version: 6,
+        description: "full-text search index",
+        up: v6_full_text_search,
+    },
+    Migration {
+        version: 7,
+        description: "source app",
+        up: v7_source_app,
+    },
+    Migration {
+        version: 8,
+        description: "list order index",
+        up: v8_list_order_index,
+    },
+    Migration {
+        version: 9,
+        description: "timed pause",
+        up: v9_timed_pause,
+    },
+    Migration {
+        version: 10,
+        description: "unique history",
+        up: v10_unique_history,
+    },
+    Migration {
+        version: 11,
+        description: "usage tracking",
+        up: v11_usage_tracking,
+    },
+    Migration {
+        version: 12,
+        description: "tags",
+        up: v12_tags,
+    },
+    Migration {
+        version: 13,
+        description: "pin order",
+        up: v13_pin_order,
+    },
+    Migration {
+        version: 14,
+        description: "secret detection",
+        up: v14_secret_detection,
+    },
+    Migration {
+        version: 15,
+        description: "default ttl per content type",
+        up: v15_default_ttls,
+    },
+    Migration {
+        version: 16,
+        description: "retention policy",
+        up: v16_retention_policy,
+    },
+    Migration {
+        version: 17,
+        description: "shared media lookups",
+        up: v17_media_path_indexes,
+    },
+    Migration {
+        version: 18,
+        description: "list position apart from capture time",
+        up: v18_sort_at,
+    },
+];
+
+/// Brings the database up to the latest version. Before the first pending
+/// step of an existing database, a copy is written next to `db_path` as
+/// `<name>.v<version>.bak`.
+pub fn migrate(conn: &mut Connection, db_path: Option<&Path>) -> Result<(), DbError> {
+    run_migrations(conn, MIGRATIONS, db_path)
+}
+
+fn run_migrations(
+    conn: &mut Connection,
+    migrations: &[Migration],
+    db_path: Option<&Path>,
+) -> Result<(), DbError> {
+    let mut version = user_version(conn)?;
+    if version == 0 {
+        version = adopt_unversioned(conn)?;
+    }
+    let latest = migrations.last().map_or(0, |migration| migration.version);
+    if version > latest {
+        return Err(DbError::UnsupportedVersion { version, latest });
+    }
+
+    let pending = migrations
+        .iter()
+        .filter(|migration| migration.version > version)
+        .collect::<Vec<_>>();
+    if pending.is_empty() {
+        return Ok(());
+    }
+    if version > 0 {
+        if let Some(db_path) = db_path {
+            backup(conn, &backup_path(db_path, version))?;
+        }
+    }
+
+    // Table rebuilds drop the old table, which with foreign keys on would
+    // cascade into every table referencing it. SQLite ignores this pragma
+    // inside a transaction, so it is switched off around all the steps and
+    // each step checks the references itself before committing.
+    let foreign_keys: bool = conn.pragma_query_value(None, "foreign_keys", |row| row.get(0))?;
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let result = pending
+        .into_iter()
+        .try_for_each(|migration| apply(conn, migration));
+    conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+    result
+}
+
+fn apply(conn: &mut Connection, migration: &Migration) -> Result<(), DbError> {
+    let tx = conn.transaction()?;
+    (migration.up)(&tx)
+        .and_then(|()| check_foreign_keys(&tx))
+        .map_err(|err| DbError::Migration {
+            version: migration.version,
+            description: migration.description,
+            source: Box::new(err),
+        })?;
+    tx.pragma_update(None, "user_version", migration.version)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn check_foreign_keys(conn: &Connection) -> Result<(), DbError> {
+    let violation = conn
+        .query_row("PRAGMA foreign_key_check", [], |row| {
+            row.get::<_, String>(0)
+        })
+        .optional()?;
+    match violation {
+        Some(table) => Err(DbError::ForeignKeyViolation(table)),
+        None => Ok(()),
+    }
+}
+
+pub fn backup_path(db_path: &Path, version: i64) -> PathBuf {
+    let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{version}.bak"));
+    db_path.with_file_name(name)
+}
+
+fn backup(conn: &Connection, path: &Path) -> Result<(), DbError> {
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()])?;
+    Ok(())
+}
+
+fn user_version(conn: &Connection) -> Result<i64, DbError> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// Databases written before versioning have `user_version` 0. Works out
+/// which migration their shape corresponds to and stamps it, once.
+fn adopt_unversioned(conn: &mut Connection) -> Result<i64, DbError> {
+    let Some(clips_sql) = table_sql(conn, "clips")? else {
+        return Ok(0);
+    };
+    let columns = table_columns(conn, "clips")?;
+    let version = if !clips_sql.contains("'image'") {
+        1
+    } else if !columns.contains("source") {
+        2
+    } else if !columns.contains("html_content") {
+        3
+    } else if !clips_sql.contains("'files'") {
+        4
+    } else if table_sql(conn, "clips_fts")?.is_none() {
+        5
+    } else if !columns.contains("source_app") {
+        6
+    } else if !index_exists(conn, "idx_list_order")? {
+        7
+    } else {
+        8
+    };
+
+    let tx = conn.transaction()?;
+    // Older builds created `settings` lazily on every start, so a file
+    // that was never fully opened may not have it.
+    if table_sql(&tx, "settings")?.is_none() {
+        tx.execute_batch(V1_SETTINGS)?;
+        if version >= 3 {
+            tx.execute_batch(V3_SETTINGS_CAPTURE_PRIMARY)?;
+        }
+    }
+    tx.pragma_update(None, "user_version", version)?;
+    tx.commit()?;
+    Ok(version)
+}
+
+fn table_sql(conn: &Connection, table: &str) -> Result<Option<String>, DbError> {
+    Ok(conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            params![table],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+fn index_exists(conn: &Connection, index: &str) -> Result<bool, DbError> {
+    Ok(conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = ?1)",
+        params![index],
+        |row| row.get(0),
+    )?)
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<HashSet<String>, DbError> {
+    let mut columns = HashSet::new();
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let rows = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    for row in rows {
+        columns.insert(row?);
+    }
+    Ok(columns)
+}
+
+/// Swaps `clips` for the table `create_sql` builds as `clips_next`, for
+/// changes `ALTER TABLE` can't make (CHECK constraints). Shared columns are
+/// copied, `derived` fills new ones from an expression over the old row, and
+/// the old table's indexes and triggers are recreated. Follows SQLite's
+/// table-rebuild procedure, so it relies on `run_migrations` having turned
+/// foreign keys off; ids are kept, and `clip_tags` rows stay attached.
+fn rebuild_clips(
+    conn: &Connection,
+    create_sql: &str,
+    derived: &[(&str, &str)],
+) -> Result<(), DbError> {
+    let dependents = {
+        let mut stmt = conn.prepare(
+            "
+            SELECT sql FROM sqlite_master
+            WHERE tbl_name = 'clips' AND type IN ('index', 'trigger') AND sql IS NOT NULL
+            ",
+        )?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.collect::<Result<Vec<_>, _>>()?
+    };
+
+    conn.execute_batch(create_sql)?;
+    let old_columns = table_columns(conn, "clips")?;
+    let mut targets = Vec::new();
+    let mut sources = Vec::new();
+    for column in table_columns(conn, "clips_next")? {
+        if old_columns.contains(&column) {
+            sources.push(column.clone());
+            targets.push(column);
+        } else if let Some((_, expression)) = derived.iter().find(|(name, _)| *name == column) {
+            sources.push(expression.to_string());
+            targets.push(column);
+        }
+    }
+    conn.execute_batch(&format!(
+        "
+        INSERT INTO clips_next ({}) SELECT {} FROM clips;
+        DROP TABLE clips;
+        ALTER TABLE clips_next RENAME TO clips;
+        ",
+        targets.join(", "),
+        sources.join(", ")
+    ))?;
+    for sql in dependents {
+        conn.execute_batch(&sql)?;
+    }
+    Ok(())
+}
+
+const V1_SETTINGS: &str = r#"
+CREATE TABLE settings (
+  id INTEGER PRIMARY KEY CHECK (id = 1),
+  history_limit INTEGER NOT NULL DEFAULT 200,
+  tracking_paused INTEGER NOT NULL DEFAULT 0 CHECK (tracking_paused IN (0, 1)),
+  max_clip_bytes INTEGER NOT NULL DEFAULT 10485760,
+  restore_clipboard_after_paste INTEGER NOT NULL DEFAULT 1 CHECK (restore_clipboard_after_paste IN (0, 1)),
+  denylist_bundle_ids TEXT NOT NULL
+);
+"#;
+
+fn v1_text_clips(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE clips (
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          content TEXT NOT NULL CHECK (length(content) > 0),
+          content_type TEXT NOT NULL CHECK (content_type IN ('text', 'url', 'code')),
+          pinned INTEGER NOT NULL DEFAULT 0 CHECK (pinned IN (0, 1)),
+          hash TEXT NOT NULL,
+          created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX idx_created_at ON clips(created_at DESC);
+        CREATE INDEX idx_pinned ON clips(pinned);
+        CREATE INDEX idx_hash ON clips(hash);
+        "#,
+    )?;
+    conn.execute_batch(V1_SETTINGS)?;
+    Ok(())
+}
+
+fn v2_image_clips(conn: &Connection) -> Result<(), DbError> {
+    rebuild_clips(
+        conn,
+        r#"
+        CREATE TABLE clips_next (
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          content TEXT NOT NULL CHECK (length(content) > 0),
+          content_type TEXT NOT NULL CHECK (content_type IN ('text', 'url', 'code', 'image')),
+          pinned INTEGER NOT NULL DEFAULT 0 CHECK (pinned IN (0, 1)),
+          hash TEXT NOT NULL,
+          media_path TEXT,
+          thumb_path TEXT,
+          mime_type TEXT,
+          byte_size INTEGER NOT NULL DEFAULT 0,
+          pixel_width INTEGER,
+          pixel_height INTEGER,
+          created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        "#,
+        &[("byte_size", "length(CAST(content AS BLOB))")],
+    )?;
+    // Images need room; the text-only builds defaulted to 2 MB.
+    conn.execute(
+        "UPDATE settings SET max_clip_bytes = 10485760 WHERE max_clip_bytes < 10485760",
+        [],
+    )?;
+    Ok(())
+}
+
+const V3_SETTINGS_CAPTURE_PRIMARY: &str = r#"
+ALTER TABLE settings
+  ADD COLUMN capture_primary_selection INTEGER NOT NULL DEFAULT 0 CHECK (capture_primary_selection IN (0, 1));
+"#;
+
+fn v3_selection_source(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        r#"
+        ALTER TABLE clips
+          ADD COLUMN source TEXT NOT NULL DEFAULT 'clipboard' CHECK (source IN ('clipboard', 'primary'));
+        "#,
+    )?;
+    conn.execute_batch(V3_SETTINGS_CAPTURE_PRIMARY)?;
+    Ok(())
+}
+
+fn v4_rich_text(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        "
+        ALTER TABLE clips ADD COLUMN html_content TEXT;
+        ALTER TABLE clips ADD COLUMN rtf_content TEXT;
+        ",
+    )?;
+    Ok(())
+}
+
+fn v5_file_lists(conn: &Connection) -> Result<(), DbError> {
+    rebuild_clips(
+        conn,
+        r#"
+        CREATE TABLE clips_next (
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          content TEXT NOT NULL CHECK (length(content) > 0),
+          content_type TEXT NOT NULL CHECK (content_type IN ('text', 'url', 'code', 'image', 'files')),
+          pinned INTEGER NOT NULL DEFAULT 0 CHECK (pinned IN (0, 1)),
+          hash TEXT NOT NULL,
+          media_path TEXT,
+          thumb_path TEXT,
+          mime_type TEXT,
+          byte_size INTEGER NOT NULL DEFAULT 0,
+          pixel_width INTEGER,
+          pixel_height INTEGER,
+          source TEXT NOT NULL DEFAULT 'clipboard' CHECK (source IN ('clipboard', 'primary')),
+          html_content TEXT,
+          rtf_content TEXT,
+          file_entries TEXT,
+          created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        "#,
+        &[],
+    )
+}
+
+/// External-content FTS5 index over `clips.content`. The triggers keep it in
+/// sync, so inserts, deletes and prunes never touch it directly.
+fn v6_full_text_search(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        r#"
+        CREATE VIRTUAL TABLE clips_fts USING fts5(
+          content,
+          content = 'clips',
+          content_rowid = 'id',
+          tokenize = 'unicode61 remove_diacritics 2'
+        );
+        INSERT INTO clips_fts(clips_fts) VALUES ('rebuild');
+        CREATE TRIGGER clips_fts_ai AFTER INSERT ON clips BEGIN
+          INSERT INTO clips_fts(rowid, content) VALUES (new.id, new.content);
+        END;
+        CREATE TRIGGER clips_fts_ad AFTER DELETE ON clips BEGIN
+          INSERT INTO clips_fts(clips_fts, rowid, content) VALUES ('delete', old.id, old.content);
+        END;
+        CREATE TRIGGER clips_fts_au AFTER UPDATE OF content ON clips BEGIN
+          INSERT INTO clips_fts(clips_fts, rowid, content) VALUES ('delete', old.id, old.content);
+          INSERT INTO clips_fts(rowid, content) VALUES (new.id, new.content);
+        END;
+        "#,
+    )?;
+    Ok(())
+}
+
+fn v7_source_app(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch("ALTER TABLE clips ADD COLUMN source_app TEXT;")?;
+    Ok(())
+}
+
+/// Matches the `list_clips` order, so cursor pages are index range scans.
+fn v8_list_order_index(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        "CREATE INDEX idx_list_order ON clips(pinned DESC, created_at DESC, id DESC);",
+    )?;
+    Ok(())
+}
+
+fn v9_timed_pause(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch("ALTER TABLE settings ADD COLUMN tracking_paused_until INTEGER;")?;
+    Ok(())
+}
+
+fn v10_unique_history(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        "
+        ALTER TABLE clips ADD COLUMN copy_count INTEGER NOT NULL DEFAULT 1;
+        ALTER TABLE clips ADD COLUMN last_used_at DATETIME;
+        ALTER TABLE settings
+          ADD COLUMN unique_history INTEGER NOT NULL DEFAULT 0 CHECK (unique_history IN (0, 1));
+        ",
+    )?;
+    Ok(())
+}
+
+fn v11_usage_tracking(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch("ALTER TABLE clips ADD COLUMN use_count INTEGER NOT NULL DEFAULT 0;")?;
+    Ok(())
+}
+
+fn v12_tags(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        "
+        CREATE TABLE tags (
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+          protected INTEGER NOT NULL DEFAULT 0 CHECK (protected IN (0, 1)),
+          created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE clip_tags (
+          clip_id INTEGER NOT NULL REFERENCES clips(id) ON DELETE CASCADE,
+          tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+          PRIMARY KEY (clip_id, tag_id)
+        ) WITHOUT ROWID;
+        CREATE INDEX idx_clip_tags_tag ON clip_tags(tag_id, clip_id);
+        ",
+    )?;
+    Ok(())
+}
+
+/// Numbers existing pins in their current newest-first order, and moves the
+/// list order index onto the new sort key.
+fn v13_pin_order(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        "
+        ALTER TABLE clips ADD COLUMN pin_order INTEGER NOT NULL DEFAULT 0;
+        UPDATE clips
+        SET pin_order = 1 + (
+          SELECT COUNT(*) FROM clips AS older
+          WHERE older.pinned = 1 AND (older.created_at, older.id) < (clips.created_at, clips.id)
+        )
+        WHERE pinned = 1;
+        DROP INDEX IF EXISTS idx_list_order;
+        CREATE INDEX idx_list_order ON clips(pinned DESC, pin_order DESC, created_at DESC, id DESC);
+        ",
+    )?;
+    Ok(())
+}
+
+/// `expires_at` is in epoch milliseconds. Stored secret actions start empty
+/// so every kind gets its default.
+fn v14_secret_detection(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        "
+        ALTER TABLE clips ADD COLUMN expires_at INTEGER;
+        CREATE INDEX idx_clips_expires_at ON clips(expires_at) WHERE expires_at IS NOT NULL;
+        ALTER TABLE settings ADD COLUMN secret_actions TEXT NOT NULL DEFAULT '{}';
+        ALTER TABLE settings ADD COLUMN secret_expiry_seconds INTEGER NOT NULL DEFAULT 60;
+        ",
+    )?;
+    Ok(())
+}
+
+fn v15_default_ttls(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        "ALTER TABLE settings ADD COLUMN default_ttl_seconds TEXT NOT NULL DEFAULT '{}';",
+    )?;
+    Ok(())
+}
+
+/// Age and storage limits start off (0); quotas start empty.
+fn v16_retention_policy(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        "
+        ALTER TABLE settings ADD COLUMN max_age_days INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE settings ADD COLUMN max_storage_bytes INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE settings ADD COLUMN type_quotas TEXT NOT NULL DEFAULT '{}';
+        ",
+    )?;
+    Ok(())
+}
+
+/// Deleting a clip checks whether other clips still share its image files.
+fn v17_media_path_indexes(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        "
+        CREATE INDEX idx_clips_media_path ON clips(media_path) WHERE media_path IS NOT NULL;
+        CREATE INDEX idx_clips_thumb_path ON clips(thumb_path) WHERE thumb_path IS NOT NULL;
+        ",
+    )?;
+    Ok(())
+}
+
+/// Re-copies used to move a clip up by overwriting `created_at`; they now
+/// move `sort_at` instead, which starts out as the capture time.
+fn v18_sort_at(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(
+        "
+        ALTER TABLE clips ADD COLUMN sort_at DATETIME NOT NULL DEFAULT '';
+        UPDATE clips SET sort_at = created_at;
+        DROP INDEX IF EXISTS idx_list_order;
+        CREATE INDEX idx_list_order ON clips(pinned DESC, pin_order DESC, sort_at DESC, id DESC);
+        ",
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn latest_version() -> i64 {
+        MIGRATIONS.last().map_or(0, |migration| migration.version)
+    }
+
+    /// A database as the build that shipped `version` left it, holding one
+    /// clip of every kind that version could store.
+    fn fixture(version: i64) -> Connection {
+        let mut conn = Connection::open_in_memory().expect("open");
+        let steps = MIGRATIONS
+            .iter()
+            .position(|migration| migration.version > version)
+            .unwrap_or(MIGRATIONS.len());
+        run_migrations(&mut conn, &MIGRATIONS[..steps], None).expect("migrate fixture");
+        if version == 0 {
+            return conn;
+        }
+
+        conn.execute_batch(
+            "
+            INSERT INTO settings (id, history_limit, tracking_paused, max_clip_bytes,
+                                  restore_clipboard_after_paste, denylist_bundle_ids)
+            VALUES (1, 50, 1, 2097152, 0, '[]');
+            INSERT INTO clips (content, content_type, pinned, hash)
+            VALUES ('legacy note', 'text', 1, 'text-hash');
+            ",
+        )
+        .expect("seed text");
+        if version >= 2 {
+            conn.execute_batch(
+                "
+                INSERT INTO clips (content, content_type, hash, media_path, byte_size)
+                VALUES ('Image | PNG | 1x1 | 0.0 MB', 'image', 'image-hash', '/tmp/a.png', 64);
+                ",
+            )
+            .expect("seed image");
+        }
+        if version >= 3 {
+            conn.execute_batch(
+                "
+                INSERT INTO clips (content, content_type, hash, source)
+                VALUES ('selected words', 'text', 'primary-hash', 'primary');
+                ",
+            )
+            .expect("seed primary");
+        }
+        if version >= 5 {
+            conn.execute_batch(
+                "
+                INSERT INTO clips (content, content_type, hash, file_entries)
+                VALUES ('/tmp/report.pdf', 'files', 'files-hash', '[]');
+                ",
+            )
+            .expect("seed files");
+        }
+        conn
+    }
+
+    fn schema(conn: &Connection) -> Vec<(String, String, Option<String>)> {
+        let mut stmt = conn
+            .prepare("SELECT type, name, sql FROM sqlite_master ORDER BY type, name")
+            .expect("prepare");
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .expect("query");
+        rows.collect::<Result<Vec<_>, _>>().expect("schema")
+    }
+
+    fn contents(conn: &Connection) -> Vec<(String, String, bool)> {
+        let mut stmt = conn
+            .prepare("SELECT content, content_type, pinned FROM clips ORDER BY id")
+            .expect("prepare");
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .expect("query");
+        rows.collect::<Result<Vec<_>, _>>().expect("contents")
+    }
+
+    #[test]
+    fn versions_are_numbered_in_order() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(
+                migration.version,
+                index as i64 + 1,
+                "{}",
+                migration.description
+            );
+        }
+    }
+
+    #[test]
+    fn upgrades_fixtures_from_every_past_version() {
+        let fresh = fixture(latest_version());
+        let latest_schema = schema(&fresh);
+
+        for version in 0..=latest_version() {
+            let mut conn = fixture(version);
+            let before = if version == 0 {
+                Vec::new()
+            } else {
+                contents(&conn)
+            };
+            migrate(&mut conn, None).expect("upgrade");
+
+            assert_eq!(user_version(&conn).expect("version"), latest_version());
+            assert_eq!(schema(&conn), latest_schema, "schema after v{version}");
+            assert_eq!(contents(&conn), before, "rows after v{version}");
+            if version >= 1 {
+                let indexed: i64 = conn
+                    .query_row(
+                        "SELECT COUNT(*) FROM clips_fts WHERE clips_fts MATCH 'legacy'",
+                        [],
+                        |row| row.get(0),
+                    )
+                    .expect("search");
+                assert_eq!(indexed, 1, "search index after v{version}");
+                let max_clip_bytes: i64 = conn
+                    .query_row("SELECT max_clip_bytes FROM settings", [], |row| row.get(0))
+                    .expect("settings");
+                let expected = if version < 2 { 10_485_760 } else { 2_097_152 };
+                assert_eq!(max_clip_bytes, expected, "settings after v{version}");
+            }
+            if version >= 2 {
+                let media_path: Option<String> = conn
+                    .query_row(
+                        "SELECT media_path FROM clips WHERE content_type = 'image'",
+                        [],
+                        |row| row.get(0),
+                    )
+                    .expect("image row");
+                assert_eq!(media_path.as_deref(), Some("/tmp/a.png"));
+            }
+        }
+    }
+
+    #[test]
+    fn adopts_unversioned_databases_by_shape() {
+        let latest_schema = schema(&fixture(latest_version()));
+        // v8 was the last schema shipped before `user_version` was stamped.
+        for version in 1..=8 {
+            let mut conn = fixture(version);
+            conn.pragma_update(None, "user_version", 0)
+                .expect("clear version");
+
+            assert_eq!(adopt_unversioned(&mut conn).expect("adopt"), version);
+            migrate(&mut conn, None).expect("upgrade");
+            assert_eq!(schema(&conn), latest_schema, "schema after v{version}");
+        }
+    }
+
+    #[test]
+    fn failed_migration_rolls_back_and_leaves_a_backup() {
+        fn broken(conn: &Connection) -> Result<(), DbError> {
+            conn.execute_batch("CREATE TABLE half_done (id INTEGER);")?;
+            conn.execute_batch("INSERT INTO missing_table VALUES (1);")?;
+            Ok(())
+        }
+
+        let db_path = env::temp_dir().join(format!("klippy-migrate-{}.sqlite3", Uuid::new_v4()));
+        let mut conn = Connection::open(&db_path).expect("open");
+        run_migrations(&mut conn, &MIGRATIONS[..2], None).expect("v2");
+        conn.execute_batch(
+            "INSERT INTO clips (content, content_type, hash) VALUES ('kept', 'text', 'h');",
+        )
+        .expect("seed");
+
+        let mut steps = MIGRATIONS[..2].to_vec();
+        steps.push(Migration {
+            version: 3,
+            description: "broken",
+            up: broken,
+        });
+        let err = run_migrations(&mut conn, &steps, Some(&db_path)).expect_err("broken step");
+        assert!(matches!(err, DbError::Migration { version: 3, .. }));
+        assert_eq!(
+            err.to_string(),
+            "migration to schema v3 (broken) failed: sqlite error: no such table: missing_table"
+        );
+
+        assert_eq!(user_version(&conn).expect("version"), 2);
+        assert_eq!(table_sql(&conn, "half_done").expect("lookup"), None);
+
+        let backup = backup_path(&db_path, 2);
+        let copy = Connection::open(&backup).expect("open backup");
+        assert_eq!(user_version(&copy).expect("backup version"), 2);
+        assert_eq!(
+            contents(&copy),
+            vec![("kept".to_string(), "text".to_string(), false)]
+        );
+
+        drop(copy);
+        drop(conn);
+        let _ = fs::remove_file(&backup);
+        let _ = fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn rebuilding_clips_keeps_tag_assignments() {
+        fn rebuild(conn: &Connection) -> Result<(), DbError> {
+            let create_sql = table_sql(conn, "clips")?
+                .expect("clips table")
+                .replacen("\"clips\"", "clips", 1)
+                .replacen("CREATE TABLE clips", "CREATE TABLE clips_next", 1);
+            rebuild_clips(conn, &create_sql, &[])
+        }
+
+        fn orphan_tag(conn: &Connection) -> Result<(), DbError> {
+            conn.execute_batch("INSERT INTO clip_tags (clip_id, tag_id) VALUES (999, 1);")?;
+            Ok(())
+        }
+
+        let mut conn = fixture(latest_version());
+        conn.pragma_update(None, "foreign_keys", true)
+            .expect("foreign keys on");
+        conn.execute_batch(
+            "
+            INSERT INTO tags (name) VALUES ('work');
+            INSERT INTO clip_tags (clip_id, tag_id) SELECT id, 1 FROM clips;
+            ",
+        )
+        .expect("tag clips");
+        let tagged = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM clip_tags", [], |row| row.get(0))
+                .expect("count tags")
+        };
+        let before = tagged(&conn);
+        assert_eq!(before, 4);
+
+        let mut steps = MIGRATIONS.to_vec();
+        steps.push(Migration {
+            version: latest_version() + 1,
+            description: "rebuild",
+            up: rebuild,
+        });
+        run_migrations(&mut conn, &steps, None).expect("rebuild");
+        assert_eq!(tagged(&conn), before);
+        let foreign_keys: bool = conn
+            .pragma_query_value(None, "foreign_keys", |row| row.get(0))
+            .expect("foreign keys");
+        assert!(foreign_keys);
+
+        steps.push(Migration {
+            version: latest_version() + 2,
+            description: "orphan",
+            up: orphan_tag,
+        });
+        let err = run_migrations(&mut conn, &steps, None).expect_err("orphan step");
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "migration to schema v{} (orphan) failed: rows in clip_tags reference rows that no longer exist",
+                latest_version() + 2
+            )
+        );
+        assert_eq!(tagged(&conn), before);
+        assert_eq!(user_version(&conn).expect("version"), latest_version() + 1);
+    }
+
+    #[test]
+    fn refuses_schemas_from_newer_builds() {
+        let mut conn = fixture(latest_version());
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .expect("bump version");
+        assert!(matches!(
+            migrate(&mut conn, None),
+            Err(DbError::UnsupportedVersion { .. })
+        ));
+    }
+}
@@ -1,19 +1,43 @@
-mod schema;
+mod cursor;
+mod encryption;
+mod fuzzy;
+mod migrations;
+mod query;
+mod tags;
 
 use std::collections::HashSet;
 use std::fs;
+use std::ops::RangeInclusive;
 use std::path::Path;
-use std::sync::{Mutex, MutexGuard};
+use std::sync::{Arc, Mutex, MutexGuard};
 
-use rusqlite::{params, Connection, OptionalExtension, Row};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use self::cursor::PageCursor;
+use self::fuzzy::FuzzyMatch;
+pub use self::query::QueryError;
+use self::query::{ClipQuery, SqlQuery};
+pub use self::tags::{ClipTag, Tag};
+
+use crate::utils::crypto::Cipher;
 #[cfg(test)]
 use crate::utils::hash::sha256_hex;
+use crate::utils::secrets::SecretActions;
 
 const DEFAULT_HISTORY_LIMIT: i64 = 200;
 const DEFAULT_MAX_CLIP_BYTES: i64 = 10_485_760;
+const HISTORY_LIMIT_RANGE: RangeInclusive<i64> = 10..=10_000;
+const MAX_CLIP_BYTES_RANGE: RangeInclusive<i64> = 1_024..=104_857_600;
+const SECRET_EXPIRY_SECONDS_RANGE: RangeInclusive<i64> = 10..=86_400;
+const DEFAULT_TTL_SECONDS_RANGE: RangeInclusive<i64> = 10..=31_536_000;
+const TYPE_QUOTA_RANGE: RangeInclusive<i64> = 1..=10_000;
+const MAX_AGE_DAYS_RANGE: RangeInclusive<i64> = 0..=3_650;
+const MAX_STORAGE_BYTES_RANGE: RangeInclusive<i64> = 0..=1_099_511_627_776;
+const MAX_DENYLIST_ENTRIES: usize = 256;
+const MAX_DENYLIST_ENTRY_CHARS: usize = 255;
 
 fn default_denylist() -> Vec<String> {
     vec![
@@ -21,6 +45,9 @@ fn default_denylist() -> Vec<String> {
         "com.agilebits.onepassword7".to_string(),
         "com.bitwarden.desktop".to_string(),
         "com.lastpass.LastPass".to_string(),
+        "1Password".to_string(),
+        "Bitwarden".to_string(),
+        "KeePassXC".to_string(),
     ]
 }
 
@@ -34,6 +61,28 @@ pub enum DbError {
     Io(#[from] std::io::Error),
     #[error("database lock poisoned")]
     LockPoisoned,
+    #[error("invalid search query: {0}")]
+    Query(#[from] QueryError),
+    #[error("invalid page cursor")]
+    InvalidCursor,
+    #[error("invalid {field}: {reason}")]
+    InvalidSetting { field: &'static str, reason: String },
+    #[error("invalid tag: {0}")]
+    InvalidTag(String),
+    #[error("invalid pin order: {0}")]
+    InvalidPinOrder(String),
+    #[error("migration to schema v{version} ({description}) failed: {source}")]
+    Migration {
+        version: i64,
+        description: &'static str,
+        source: Box<DbError>,
+    },
+    #[error("rows in {0} reference rows that no longer exist")]
+    ForeignKeyViolation(String),
+    #[error("database schema v{version} is newer than this build supports (v{latest})")]
+    UnsupportedVersion { version: i64, latest: i64 },
+    #[error("database cannot be opened with this encryption key")]
+    WrongKey,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -43,21 +92,105 @@ pub struct Clip {
     pub content: String,
     pub content_type: String,
     pub pinned: bool,
+    /// Position among pinned clips, highest first; 0 when not pinned.
+    pub pin_order: i64,
+    /// When the clip was captured. Re-copies never change it.
     pub created_at: String,
+    /// When the clip last moved to the top of the history: its capture, or
+    /// its latest re-copy in unique history mode. The history is ordered by
+    /// it.
+    pub sort_at: String,
     pub media_path: Option<String>,
     pub thumb_path: Option<String>,
     pub mime_type: Option<String>,
     pub byte_size: i64,
     pub pixel_width: Option<i64>,
     pub pixel_height: Option<i64>,
+    pub source: String,
+    /// Bundle id (or window class) of the app that was frontmost at capture.
+    pub source_app: Option<String>,
+    pub has_html: bool,
+    pub has_rtf: bool,
+    pub files: Option<Vec<ClipFile>>,
+    /// How many times this content was copied; re-copies only add to it in
+    /// unique history mode.
+    pub copy_count: i64,
+    /// How many times the clip was copied or pasted back from the history.
+    pub use_count: i64,
+    /// Latest re-copy or copy-back; `None` until either happens.
+    pub last_used_at: Option<String>,
+    /// Epoch milliseconds the clip deletes itself at, unless pinned by then.
+    pub expires_at: Option<i64>,
+    pub tags: Vec<ClipTag>,
+    /// Search hits in `content`; empty outside full-text search results.
+    pub highlights: Vec<MatchRange>,
+}
+
+/// Half-open range into a clip's `content`, in UTF-16 code units so the
+/// webview can slice the string directly.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// One entry of a `files` clip, as it was at capture time. `byte_size` is
+/// `None` for folders; whether the path still exists is only checked when
+/// the clip is copied back.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipFile {
+    pub path: String,
+    pub is_dir: bool,
+    pub byte_size: Option<i64>,
+}
+
+/// How the free-text part of a `list_clips` query is matched. Filters such
+/// as `type:url` apply the same way in both modes.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SearchMode {
+    /// Word-prefix search over the FTS5 index.
+    #[default]
+    FullText,
+    /// Typo-tolerant subsequence matching, ranked by match quality.
+    Fuzzy,
+}
+
+/// Order of clips listed without search terms; matches of a search are
+/// always ordered by relevance.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ClipSort {
+    /// Newest first.
+    #[default]
+    Recent,
+    /// Clips used often and lately first, see [`FRECENCY_SCORE`].
+    Frecency,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ClipPage {
     pub items: Vec<Clip>,
-    pub total: i64,
-    pub next_offset: Option<i64>,
+    /// Only counted when the request asks for it.
+    pub total: Option<i64>,
+    /// Pass back as `cursor` for the next page; `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// Arguments of [`Database::list_clips`]. `cursor` is the `next_cursor` of
+/// the previous page for the same query, source, mode and sort.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClipListRequest<'a> {
+    pub query: Option<&'a str>,
+    pub source: Option<&'a str>,
+    pub mode: SearchMode,
+    pub sort: ClipSort,
+    pub cursor: Option<&'a str>,
+    pub limit: i64,
+    pub include_total: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -65,9 +198,226 @@ pub struct ClipPage {
 pub struct Settings {
     pub history_limit: i64,
     pub tracking_paused: bool,
+    /// Epoch milliseconds a timed pause ends at; `None` while paused means
+    /// until resumed.
+    pub tracking_paused_until: Option<i64>,
     pub max_clip_bytes: i64,
     pub restore_clipboard_after_paste: bool,
     pub denylist_bundle_ids: Vec<String>,
+    pub capture_primary_selection: bool,
+    /// Re-copying content already in the history moves that clip to the top
+    /// instead of adding a second one.
+    pub unique_history: bool,
+    /// What capture does with each kind of detected secret.
+    pub secret_actions: SecretActions,
+    /// How long clips kept with the `expire` secret action last.
+    pub secret_expiry_seconds: i64,
+    /// How long newly captured clips of each content type last, in seconds;
+    /// types without one keep their clips. Pinned clips never expire.
+    pub default_ttl_seconds: ContentTypeLimits,
+    /// Pruning removes clips older than this; 0 turns the limit off.
+    pub max_age_days: i64,
+    /// Pruning keeps database plus media storage under this; 0 turns the
+    /// limit off.
+    pub max_storage_bytes: i64,
+    /// Most clips pruning keeps of each content type.
+    pub type_quotas: ContentTypeLimits,
+}
+
+/// An optional number per content type; `None` means no limit.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ContentTypeLimits {
+    pub text: Option<i64>,
+    pub url: Option<i64>,
+    pub code: Option<i64>,
+    pub image: Option<i64>,
+    pub files: Option<i64>,
+}
+
+impl ContentTypeLimits {
+    pub fn for_type(&self, content_type: &str) -> Option<i64> {
+        match content_type {
+            "text" => self.text,
+            "url" => self.url,
+            "code" => self.code,
+            "image" => self.image,
+            "files" => self.files,
+            _ => None,
+        }
+    }
+
+    fn checked(self, field: &'static str, range: &RangeInclusive<i64>) -> Result<Self, DbError> {
+        for value in [self.text, self.url, self.code, self.image, self.files]
+            .into_iter()
+            .flatten()
+        {
+            check_range(field, value, range)?;
+        }
+        Ok(self)
+    }
+}
+
+/// A partial settings change from the webview; `None` keeps the stored value.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SettingsUpdate {
+    pub history_limit: Option<i64>,
+    pub tracking_paused: Option<bool>,
+    pub max_clip_bytes: Option<i64>,
+    pub restore_clipboard_after_paste: Option<bool>,
+    pub denylist_bundle_ids: Option<Vec<String>>,
+    pub capture_primary_selection: Option<bool>,
+    pub unique_history: Option<bool>,
+    /// Replaces every action; kinds left out get their default.
+    pub secret_actions: Option<SecretActions>,
+    pub secret_expiry_seconds: Option<i64>,
+    /// Replaces every content type's lifetime; types left out never expire.
+    pub default_ttl_seconds: Option<ContentTypeLimits>,
+    pub max_age_days: Option<i64>,
+    pub max_storage_bytes: Option<i64>,
+    /// Replaces every quota; types left out have none.
+    pub type_quotas: Option<ContentTypeLimits>,
+}
+
+impl SettingsUpdate {
+    /// Validates every provided field and writes it into `settings`. Nothing
+    /// is written unless the whole update is valid.
+    pub fn apply(self, settings: &mut Settings) -> Result<(), DbError> {
+        let history_limit = self
+            .history_limit
+            .map(|value| check_range("historyLimit", value, &HISTORY_LIMIT_RANGE))
+            .transpose()?;
+        let max_clip_bytes = self
+            .max_clip_bytes
+            .map(|value| check_range("maxClipBytes", value, &MAX_CLIP_BYTES_RANGE))
+            .transpose()?;
+        let denylist = self
+            .denylist_bundle_ids
+            .map(normalize_denylist)
+            .transpose()?;
+        let secret_expiry_seconds = self
+            .secret_expiry_seconds
+            .map(|value| check_range("secretExpirySeconds", value, &SECRET_EXPIRY_SECONDS_RANGE))
+            .transpose()?;
+        let default_ttl_seconds = self
+            .default_ttl_seconds
+            .map(|ttls| ttls.checked("defaultTtlSeconds", &DEFAULT_TTL_SECONDS_RANGE))
+            .transpose()?;
+        let max_age_days = self
+            .max_age_days
+            .map(|value| check_range("maxAgeDays", value, &MAX_AGE_DAYS_RANGE))
+            .transpose()?;
+        let max_storage_bytes = self
+            .max_storage_bytes
+            .map(|value| check_range("maxStorageBytes", value, &MAX_STORAGE_BYTES_RANGE))
+            .transpose()?;
+        let type_quotas = self
+            .type_quotas
+            .map(|quotas| quotas.checked("typeQuotas", &TYPE_QUOTA_RANGE))
+            .transpose()?;
+
+        if let Some(value) = history_limit {
+            settings.history_limit = value;
+        }
+        if let Some(value) = self.tracking_paused {
+            settings.tracking_paused = value;
+            settings.tracking_paused_until = None;
+        }
+        if let Some(value) = max_clip_bytes {
+            settings.max_clip_bytes = value;
+        }
+        if let Some(value) = self.restore_clipboard_after_paste {
+            settings.restore_clipboard_after_paste = value;
+        }
+        if let Some(value) = denylist {
+            settings.denylist_bundle_ids = value;
+        }
+        if let Some(value) = self.capture_primary_selection {
+            settings.capture_primary_selection = value;
+        }
+        if let Some(value) = self.unique_history {
+            settings.unique_history = value;
+        }
+        if let Some(value) = self.secret_actions {
+            settings.secret_actions = value;
+        }
+        if let Some(value) = secret_expiry_seconds {
+            settings.secret_expiry_seconds = value;
+        }
+        if let Some(value) = default_ttl_seconds {
+            settings.default_ttl_seconds = value;
+        }
+        if let Some(value) = max_age_days {
+            settings.max_age_days = value;
+        }
+        if let Some(value) = max_storage_bytes {
+            settings.max_storage_bytes = value;
+        }
+        if let Some(value) = type_quotas {
+            settings.type_quotas = value;
+        }
+        Ok(())
+    }
+}
+
+fn check_range(
+    field: &'static str,
+    value: i64,
+    range: &RangeInclusive<i64>,
+) -> Result<i64, DbError> {
+    if range.contains(&value) {
+        Ok(value)
+    } else {
+        Err(DbError::InvalidSetting {
+            field,
+            reason: format!("{value} is outside {}..={}", range.start(), range.end()),
+        })
+    }
+}
+
+/// Trims entries and drops blanks and repeats, keeping the user's order.
+fn normalize_denylist(entries: Vec<String>) -> Result<Vec<String>, DbError> {
+    let invalid = |reason: String| DbError::InvalidSetting {
+        field: "denylistBundleIds",
+        reason,
+    };
+    let mut normalized: Vec<String> = Vec::new();
+    for entry in entries {
+        let entry = entry.trim();
+        if entry.is_empty() || normalized.iter().any(|existing| existing == entry) {
+            continue;
+        }
+        if entry.chars().count() > MAX_DENYLIST_ENTRY_CHARS {
+            return Err(invalid(format!(
+                "entries are limited to {MAX_DENYLIST_ENTRY_CHARS} characters"
+            )));
+        }
+        normalized.push(entry.to_string());
+    }
+    if normalized.len() > MAX_DENYLIST_ENTRIES {
+        return Err(invalid(format!(
+            "at most {MAX_DENYLIST_ENTRIES} entries are allowed"
+        )));
+    }
+    Ok(normalized)
+}
+
+/// What retention needs to know about a clip, listed from the bottom of the
+/// history up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionCandidate {
+    pub id: i64,
+    pub content_type: String,
+    /// Capture time in epoch milliseconds, which re-copies leave alone.
+    pub created_at_ms: i64,
+    /// Pinned, or carrying a protected tag.
+    pub protected: bool,
+    /// Stored text, flavors and file entries, in bytes.
+    pub content_bytes: i64,
+    /// The original media file, which other clips of the same image share.
+    pub media_path: Option<String>,
+    pub media_bytes: i64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -77,23 +427,94 @@ pub struct LatestClip {
     pub hash: String,
 }
 
+/// Formatted flavors stored alongside a text clip's plain `content`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RichTextFlavors {
+    pub html: Option<String>,
+    pub rtf: Option<String>,
+}
+
 pub struct Database {
     conn: Mutex<Connection>,
+    /// The latest fuzzy query's matches, so its later pages do not rescore
+    /// the whole history.
+    fuzzy_cache: Mutex<Option<FuzzyResults>>,
+}
+
+/// A fuzzy match and the clip's pinned-then-newest sort key.
+type ScoredClip = (FuzzyMatch, (bool, i64, String, i64));
+
+/// Scored fuzzy matches for one query and source. Any write to the
+/// connection moves `total_changes` on, which makes them stale.
+struct FuzzyResults {
+    query: String,
+    source: Option<String>,
+    total_changes: u64,
+    matches: Arc<Vec<ScoredClip>>,
 }
 
 const CLIP_COLUMNS: &str = "
-    id,
-    content,
-    content_type,
-    pinned,
-    created_at,
-    media_path,
-    thumb_path,
-    mime_type,
-    byte_size,
-    pixel_width,
-    pixel_height
+    clips.id,
+    clips.content,
+    clips.content_type,
+    clips.pinned,
+    clips.created_at,
+    clips.media_path,
+    clips.thumb_path,
+    clips.mime_type,
+    clips.byte_size,
+    clips.pixel_width,
+    clips.pixel_height,
+    clips.source,
+    clips.source_app,
+    clips.html_content IS NOT NULL,
+    clips.rtf_content IS NOT NULL,
+    clips.file_entries,
+    clips.copy_count,
+    clips.use_count,
+    clips.last_used_at,
+    (
+        SELECT json_group_array(json_object('id', tags.id, 'name', tags.name) ORDER BY tags.name)
+        FROM clip_tags JOIN tags ON tags.id = clip_tags.tag_id
+        WHERE clip_tags.clip_id = clips.id
+    ),
+    clips.pin_order,
+    clips.expires_at,
+    clips.sort_at
+";
+const CLIP_COLUMN_COUNT: usize = 23;
+
+/// A `sort_at` later than every stored clip's, with millisecond precision,
+/// so new and bumped clips always sort first even within the same second.
+const NEXT_SORT_AT: &str = "(
+    SELECT CASE
+        WHEN now > IFNULL(latest, '') THEN now
+        ELSE strftime('%Y-%m-%d %H:%M:%f', julianday(latest) + 1.0 / 86400000)
+    END
+    FROM (
+        SELECT strftime('%Y-%m-%d %H:%M:%f', 'now') AS now, MAX(sort_at) AS latest
+        FROM clips
+    )
+)";
+
+/// Search score: bm25 relevance (negative, lower is better) scaled up by up to
+/// 2x for clips captured or re-copied lately and 1.5x for pinned ones. Binds
+/// `julianday('now')`; results are ordered by it, newest first on ties.
+const SEARCH_RANK: &str = "
+    bm25(clips_fts)
+        * (1.0 + 1.0 / (1.0 + ? - julianday(clips.sort_at)))
+        * (CASE clips.pinned WHEN 1 THEN 1.5 ELSE 1.0 END)
 ";
+/// Frecency score: every copy and copy-back counts once, divided down by the
+/// weeks since the clip was last used (or captured). Binds `julianday('now')`;
+/// higher is better.
+const FRECENCY_SCORE: &str = "
+    (clips.copy_count + clips.use_count)
+        / (1.0 + (? - julianday(COALESCE(clips.last_used_at, clips.created_at))) / 7.0)
+";
+const HIGHLIGHT_OPEN: char = '\u{E000}';
+const HIGHLIGHT_CLOSE: char = '\u{E001}';
+const HIGHLIGHT_COLUMN: &str = "highlight(clips_fts, 0, char(57344), char(57345))";
 
 struct NewClip<'a> {
     content: &'a str,
@@ -105,6 +526,11 @@ struct NewClip<'a> {
     byte_size: i64,
     pixel_width: Option<i64>,
     pixel_height: Option<i64>,
+    source: &'a str,
+    source_app: Option<&'a str>,
+    html_content: Option<&'a str>,
+    rtf_content: Option<&'a str>,
+    file_entries: Option<String>,
 }
 
 pub struct ImageClipInsert<'a> {
@@ -116,6 +542,27 @@ pub struct ImageClipInsert<'a> {
     pub byte_size: i64,
     pub pixel_width: i64,
     pub pixel_height: i64,
+    pub source: &'a str,
+    pub source_app: Option<&'a str>,
+}
+
+pub struct RichTextClipInsert<'a> {
+    pub content: &'a str,
+    pub content_type: &'a str,
+    pub hash: &'a str,
+    pub html_content: Option<&'a str>,
+    pub rtf_content: Option<&'a str>,
+    pub byte_size: i64,
+    pub source: &'a str,
+    pub source_app: Option<&'a str>,
+}
+
+pub struct FileListClipInsert<'a> {
+    pub content: &'a str,
+    pub hash: &'a str,
+    pub files: &'a [ClipFile],
+    pub source: &'a str,
+    pub source_app: Option<&'a str>,
 }
 
 impl Database {
@@ -124,26 +571,44 @@ impl Database {
     }
 
     pub fn new(path: &Path) -> Result<Self, DbError> {
+        Self::open(path, None)
+    }
+
+    /// Opens the database at `path` with SQLCipher. A plaintext database
+    /// from before encryption was turned on is encrypted first.
+    pub fn new_encrypted(path: &Path, cipher: &Cipher) -> Result<Self, DbError> {
+        Self::open(path, Some(cipher))
+    }
+
+    fn open(path: &Path, cipher: Option<&Cipher>) -> Result<Self, DbError> {
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let conn = Connection::open(path)?;
-        Self::initialize(&conn)?;
+        if let Some(cipher) = cipher {
+            encryption::encrypt_existing(path, cipher)?;
+        }
+        let mut conn = Connection::open(path)?;
+        if let Some(cipher) = cipher {
+            encryption::unlock(&conn, cipher)?;
+        }
+        Self::initialize(&mut conn, Some(path))?;
         Ok(Self {
             conn: Mutex::new(conn),
+            fuzzy_cache: Mutex::new(None),
         })
     }
 
     #[cfg(test)]
     pub fn new_in_memory() -> Result<Self, DbError> {
-        let conn = Connection::open_in_memory()?;
-        Self::initialize(&conn)?;
+        let mut conn = Connection::open_in_memory()?;
+        Self::initialize(&mut conn, None)?;
         Ok(Self {
             conn: Mutex::new(conn),
+            fuzzy_cache: Mutex::new(None),
         })
     }
 
-    fn initialize(conn: &Connection) -> Result<(), DbError> {
+    fn initialize(conn: &mut Connection, path: Option<&Path>) -> Result<(), DbError> {
         conn.execute_batch(
             "
             PRAGMA journal_mode = WAL;
@@ -153,12 +618,7 @@ impl Database {
             ",
         )?;
 
-        ensure_clips_schema(conn)?;
-
-        conn.execute_batch(schema::CREATE_SETTINGS_TABLE)?;
-        conn.execute_batch(schema::CREATE_INDEX_CREATED_AT)?;
-        conn.execute_batch(schema::CREATE_INDEX_PINNED)?;
-        conn.execute_batch(schema::CREATE_INDEX_HASH)?;
+        migrations::migrate(conn, path)?;
 
         let denylist_json = serde_json::to_string(&default_denylist())?;
         conn.execute(
@@ -175,82 +635,187 @@ impl Database {
             params![DEFAULT_HISTORY_LIMIT, DEFAULT_MAX_CLIP_BYTES, denylist_json],
         )?;
 
-        conn.execute(
-            "UPDATE settings SET max_clip_bytes = ?1 WHERE id = 1 AND max_clip_bytes < ?1",
-            params![DEFAULT_MAX_CLIP_BYTES],
-        )?;
-
         Ok(())
     }
 
-    pub fn list_clips(
+    pub fn list_clips(&self, request: ClipListRequest<'_>) -> Result<ClipPage, DbError> {
+        let limit = request.limit.max(1);
+        let cursor = request.cursor.map(PageCursor::decode).transpose()?;
+        let query = ClipQuery::parse(request.query.unwrap_or_default())?;
+        if request.mode == SearchMode::Fuzzy && !query.terms.is_empty() {
+            return self.list_clips_fuzzy(&query, &request, cursor, limit);
+        }
+
+        let SqlQuery {
+            match_expression,
+            mut conditions,
+            mut values,
+        } = query.to_sql();
+        if let Some(source) = request.source {
+            conditions.push("clips.source = ?".to_string());
+            values.push(Value::Text(source.to_string()));
+        }
+        let conn = self.conn()?;
+        match match_expression {
+            Some(expression) => {
+                conditions.insert(0, "clips_fts MATCH ?".to_string());
+                values.insert(0, Value::Text(expression));
+                list_ranked_clips(
+                    &conn,
+                    conditions,
+                    values,
+                    cursor,
+                    limit,
+                    request.include_total,
+                )
+            }
+            None if request.sort == ClipSort::Frecency => list_frecent_clips(
+                &conn,
+                conditions,
+                values,
+                cursor,
+                limit,
+                request.include_total,
+            ),
+            None => list_recent_clips(
+                &conn,
+                conditions,
+                values,
+                cursor,
+                limit,
+                request.include_total,
+            ),
+        }
+    }
+
+    fn list_clips_fuzzy(
         &self,
-        query: Option<&str>,
+        query: &ClipQuery,
+        request: &ClipListRequest<'_>,
+        cursor: Option<PageCursor>,
         limit: i64,
-        offset: i64,
     ) -> Result<ClipPage, DbError> {
-        let limit = limit.max(1);
-        let offset = offset.max(0);
+        let resume_score = cursor
+            .as_ref()
+            .map(|cursor| cursor.score.ok_or(DbError::InvalidCursor))
+            .transpose()?;
         let conn = self.conn()?;
+        let matches = self.fuzzy_matches(&conn, query, request)?;
+
+        let total = request.include_total.then_some(matches.len() as i64);
+        let resume_key = cursor
+            .as_ref()
+            .map(|cursor| {
+                (
+                    cursor.pinned,
+                    cursor.pin_order,
+                    cursor.sort_at.clone(),
+                    cursor.id,
+                )
+            })
+            .zip(resume_score);
+        let mut items = Vec::new();
+        let mut scores = Vec::new();
+        for (found, (.., id)) in matches
+            .iter()
+            .filter(|(found, key)| {
+                resume_key.as_ref().map_or(true, |(resume_key, score)| {
+                    let found_score = found.score as f64;
+                    found_score < *score || (found_score == *score && key < resume_key)
+                })
+            })
+            .take(limit as usize + 1)
+        {
+            let mut clip = self.get_clip_internal(&conn, *id)?;
+            clip.highlights = fuzzy::highlight_ranges(&clip.content, &found.positions);
+            items.push(clip);
+            scores.push(found.score as f64);
+        }
 
-        let search = query
-            .map(str::trim)
-            .filter(|q| !q.is_empty())
-            .map(|q| q.to_string());
-
-        let (items, total) = if let Some(search) = search {
-            let like = format!("%{}%", search.to_lowercase());
-            let mut stmt = conn.prepare(&format!(
-                "
-                SELECT {CLIP_COLUMNS}
-                FROM clips
-                WHERE LOWER(content) LIKE ?1
-                ORDER BY pinned DESC, created_at DESC, id DESC
-                LIMIT ?2 OFFSET ?3
-                "
-            ))?;
-            let rows = stmt.query_map(params![like, limit, offset], clip_from_row)?;
-
-            let items = rows.collect::<Result<Vec<_>, _>>()?;
-            let total: i64 = conn.query_row(
-                "SELECT COUNT(*) FROM clips WHERE LOWER(content) LIKE ?1",
-                params![format!("%{}%", search.to_lowercase())],
-                |row| row.get(0),
-            )?;
-            (items, total)
-        } else {
-            let mut stmt = conn.prepare(&format!(
-                "
-                SELECT {CLIP_COLUMNS}
-                FROM clips
-                ORDER BY pinned DESC, created_at DESC, id DESC
-                LIMIT ?1 OFFSET ?2
-                "
-            ))?;
-            let rows = stmt.query_map(params![limit, offset], clip_from_row)?;
-
-            let items = rows.collect::<Result<Vec<_>, _>>()?;
-            let total: i64 = conn.query_row("SELECT COUNT(*) FROM clips", [], |row| row.get(0))?;
-            (items, total)
-        };
-
-        let next_offset = if offset + limit < total {
-            Some(offset + limit)
-        } else {
-            None
-        };
-
+        let next_cursor = next_page_cursor(&mut items, limit, |last, index| {
+            PageCursor::after(last, Some(scores[index]), None)
+        })?;
         Ok(ClipPage {
             items,
             total,
-            next_offset,
+            next_cursor,
         })
     }
 
+    /// Scores every clip that passes the query's filters in Rust, since
+    /// neither FTS5 nor LIKE can rank typo-tolerant matches. Ties keep the
+    /// usual pinned-then-newest order. Paging through the same query reuses
+    /// the previous scores until something is written.
+    fn fuzzy_matches(
+        &self,
+        conn: &Connection,
+        query: &ClipQuery,
+        request: &ClipListRequest<'_>,
+    ) -> Result<Arc<Vec<ScoredClip>>, DbError> {
+        let text = request.query.unwrap_or_default();
+        let total_changes = conn.total_changes();
+        let mut cache = self.fuzzy_cache.lock().map_err(|_| DbError::LockPoisoned)?;
+        if let Some(cached) = cache.as_ref().filter(|cached| {
+            cached.query == text
+                && cached.source.as_deref() == request.source
+                && cached.total_changes == total_changes
+        }) {
+            return Ok(Arc::clone(&cached.matches));
+        }
+
+        let SqlQuery {
+            mut conditions,
+            mut values,
+            ..
+        } = query.filter_sql();
+        if let Some(source) = request.source {
+            conditions.push("clips.source = ?".to_string());
+            values.push(Value::Text(source.to_string()));
+        }
+        let mut stmt = conn.prepare(&format!(
+            "
+            SELECT clips.id, clips.pinned, clips.pin_order, clips.sort_at,
+                substr(clips.content, 1, {})
+            FROM clips
+            {}
+            ORDER BY clips.pinned DESC, clips.pin_order DESC, clips.sort_at DESC, clips.id DESC
+            ",
+            fuzzy::MAX_FUZZY_CHARS,
+            where_clause(&conditions)
+        ))?;
+        let rows = stmt.query_map(params_from_iter(values.iter()), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, bool>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?;
+        let mut matches = Vec::new();
+        for row in rows {
+            let (id, pinned, pin_order, sort_at, content) = row?;
+            if let Some(found) = fuzzy::match_terms(&query.terms, &content) {
+                matches.push((found, (pinned, pin_order, sort_at, id)));
+            }
+        }
+        // Stable, so equal scores stay in the SQL order above.
+        matches.sort_by_key(|(found, _)| std::cmp::Reverse(found.score));
+
+        let matches = Arc::new(matches);
+        *cache = Some(FuzzyResults {
+            query: text.to_string(),
+            source: request.source.map(str::to_string),
+            total_changes,
+            matches: Arc::clone(&matches),
+        });
+        Ok(matches)
+    }
+
     pub fn latest_clip(&self) -> Result<Option<LatestClip>, DbError> {
         let conn = self.conn()?;
         conn.query_row(
-            "SELECT content, content_type, hash FROM clips ORDER BY created_at DESC, id DESC LIMIT 1",
+            "SELECT content, content_type, hash FROM clips ORDER BY sort_at DESC, id DESC LIMIT 1",
             [],
             |row| {
                 Ok(LatestClip {
@@ -272,7 +837,7 @@ impl Database {
             SELECT {CLIP_COLUMNS}
             FROM clips
             WHERE content_type = 'image'
-            ORDER BY created_at DESC, id DESC
+            ORDER BY sort_at DESC, id DESC
             LIMIT ?1
             "
         ))?;
@@ -293,6 +858,11 @@ impl Database {
             byte_size: content.len() as i64,
             pixel_width: None,
             pixel_height: None,
+            source: "clipboard",
+            source_app: None,
+            html_content: None,
+            rtf_content: None,
+            file_entries: None,
         })
     }
 
@@ -301,6 +871,8 @@ impl Database {
         content: &str,
         content_type: &str,
         hash: &str,
+        source: &str,
+        source_app: Option<&str>,
     ) -> Result<Clip, DbError> {
         self.insert_new_clip(NewClip {
             content,
@@ -312,6 +884,49 @@ impl Database {
             byte_size: content.len() as i64,
             pixel_width: None,
             pixel_height: None,
+            source,
+            source_app,
+            html_content: None,
+            rtf_content: None,
+            file_entries: None,
+        })
+    }
+
+    pub fn insert_rich_text_clip(&self, rich: RichTextClipInsert<'_>) -> Result<Clip, DbError> {
+        self.insert_new_clip(NewClip {
+            content: rich.content,
+            content_type: rich.content_type,
+            hash: rich.hash,
+            media_path: None,
+            thumb_path: None,
+            mime_type: None,
+            byte_size: rich.byte_size,
+            pixel_width: None,
+            pixel_height: None,
+            source: rich.source,
+            source_app: rich.source_app,
+            html_content: rich.html_content,
+            rtf_content: rich.rtf_content,
+            file_entries: None,
+        })
+    }
+
+    pub fn insert_file_list_clip(&self, files: FileListClipInsert<'_>) -> Result<Clip, DbError> {
+        self.insert_new_clip(NewClip {
+            content: files.content,
+            content_type: "files",
+            hash: files.hash,
+            media_path: None,
+            thumb_path: None,
+            mime_type: None,
+            byte_size: files.content.len() as i64,
+            pixel_width: None,
+            pixel_height: None,
+            source: files.source,
+            source_app: files.source_app,
+            html_content: None,
+            rtf_content: None,
+            file_entries: Some(serde_json::to_string(files.files)?),
         })
     }
 
@@ -326,26 +941,44 @@ impl Database {
             byte_size: image.byte_size,
             pixel_width: Some(image.pixel_width),
             pixel_height: Some(image.pixel_height),
+            source: image.source,
+            source_app: image.source_app,
+            html_content: None,
+            rtf_content: None,
+            file_entries: None,
         })
     }
 
     fn insert_new_clip(&self, new_clip: NewClip<'_>) -> Result<Clip, DbError> {
         let conn = self.conn()?;
         conn.execute(
-            "
-            INSERT INTO clips (
-                content,
-                content_type,
-                pinned,
-                hash,
-                media_path,
-                thumb_path,
-                mime_type,
-                byte_size,
-                pixel_width,
-                pixel_height
-            ) VALUES (?1, ?2, 0, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
-            ",
+            &format!(
+                "
+                INSERT INTO clips (
+                    content,
+                    content_type,
+                    pinned,
+                    hash,
+                    media_path,
+                    thumb_path,
+                    mime_type,
+                    byte_size,
+                    pixel_width,
+                    pixel_height,
+                    source,
+                    source_app,
+                    html_content,
+                    rtf_content,
+                    file_entries,
+                    created_at,
+                    sort_at
+                ) VALUES (
+                    ?1, ?2, 0, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14,
+                    strftime('%Y-%m-%d %H:%M:%f', 'now'),
+                    {NEXT_SORT_AT}
+                )
+                "
+            ),
             params![
                 new_clip.content,
                 new_clip.content_type,
@@ -356,6 +989,11 @@ impl Database {
                 new_clip.byte_size,
                 new_clip.pixel_width,
                 new_clip.pixel_height,
+                new_clip.source,
+                new_clip.source_app,
+                new_clip.html_content,
+                new_clip.rtf_content,
+                new_clip.file_entries,
             ],
         )?;
         let id = conn.last_insert_rowid();
@@ -369,6 +1007,22 @@ impl Database {
             .map_err(DbError::from)
     }
 
+    pub fn get_rich_text(&self, id: i64) -> Result<Option<RichTextFlavors>, DbError> {
+        let conn = self.conn()?;
+        conn.query_row(
+            "SELECT html_content, rtf_content FROM clips WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok(RichTextFlavors {
+                    html: row.get(0)?,
+                    rtf: row.get(1)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(DbError::from)
+    }
+
     fn get_clip_internal(&self, conn: &Connection, id: i64) -> Result<Clip, rusqlite::Error> {
         conn.query_row(
             &format!("SELECT {CLIP_COLUMNS} FROM clips WHERE id = ?1"),
@@ -377,17 +1031,122 @@ impl Database {
         )
     }
 
-    pub fn set_pinned(&self, id: i64, pinned: bool) -> Result<Option<Clip>, DbError> {
+    /// Moves the newest clip with `hash` to the top of the history and
+    /// counts the copy, keeping its id, pin, capture time and metadata.
+    /// `None` when no clip has that hash.
+    pub fn bump_clip(&self, hash: &str) -> Result<Option<Clip>, DbError> {
+        let conn = self.conn()?;
+        let id: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM clips WHERE hash = ?1 ORDER BY sort_at DESC, id DESC LIMIT 1",
+                params![hash],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        conn.execute(
+            &format!(
+                "
+                UPDATE clips
+                SET sort_at = {NEXT_SORT_AT},
+                    last_used_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+                    copy_count = copy_count + 1
+                WHERE id = ?1
+                "
+            ),
+            params![id],
+        )?;
+        Ok(Some(self.get_clip_internal(&conn, id)?))
+    }
+
+    /// Counts a copy-back of the clip for frecency ordering.
+    pub fn record_use(&self, id: i64) -> Result<Option<Clip>, DbError> {
         let conn = self.conn()?;
         conn.execute(
-            "UPDATE clips SET pinned = ?1 WHERE id = ?2",
-            params![if pinned { 1 } else { 0 }, id],
+            "
+            UPDATE clips
+            SET use_count = use_count + 1,
+                last_used_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+            WHERE id = ?1
+            ",
+            params![id],
         )?;
         self.get_clip_internal(&conn, id)
             .optional()
             .map_err(DbError::from)
     }
 
+    /// New pins go to the top of the pinned section; pinning an already
+    /// pinned clip keeps its place.
+    pub fn set_pinned(&self, id: i64, pinned: bool) -> Result<Option<Clip>, DbError> {
+        let conn = self.conn()?;
+        if pinned {
+            conn.execute(
+                "
+                UPDATE clips
+                SET pinned = 1,
+                    pin_order = (SELECT IFNULL(MAX(pin_order), 0) + 1 FROM clips WHERE pinned = 1)
+                WHERE id = ?1 AND pinned = 0
+                ",
+                params![id],
+            )?;
+        } else {
+            conn.execute(
+                "UPDATE clips SET pinned = 0, pin_order = 0 WHERE id = ?1",
+                params![id],
+            )?;
+        }
+        self.get_clip_internal(&conn, id)
+            .optional()
+            .map_err(DbError::from)
+    }
+
+    /// Puts the pinned section in the order of `ids`, top first. Pinned
+    /// clips left out keep their relative order below the listed ones.
+    /// Returns every pinned clip in the new order.
+    pub fn reorder_pinned(&self, ids: &[i64]) -> Result<Vec<Clip>, DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let current = {
+            let mut stmt = tx.prepare(
+                "
+                SELECT id FROM clips
+                WHERE pinned = 1
+                ORDER BY pin_order DESC, sort_at DESC, id DESC
+                ",
+            )?;
+            let rows = stmt.query_map([], |row| row.get::<_, i64>(0))?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut order = Vec::with_capacity(current.len());
+        for &id in ids {
+            if !current.contains(&id) {
+                return Err(DbError::InvalidPinOrder(format!("clip {id} is not pinned")));
+            }
+            if order.contains(&id) {
+                return Err(DbError::InvalidPinOrder(format!(
+                    "clip {id} is listed twice"
+                )));
+            }
+            order.push(id);
+        }
+        order.extend(current.into_iter().filter(|id| !ids.contains(id)));
+
+        let mut clips = Vec::with_capacity(order.len());
+        for (index, id) in order.iter().enumerate() {
+            tx.execute(
+                "UPDATE clips SET pin_order = ?1 WHERE id = ?2",
+                params![(order.len() - index) as i64, id],
+            )?;
+            clips.push(self.get_clip_internal(&tx, *id)?);
+        }
+        tx.commit()?;
+        Ok(clips)
+    }
+
     pub fn delete_clip(&self, id: i64) -> Result<Option<Clip>, DbError> {
         let conn = self.conn()?;
         let clip = self
@@ -437,79 +1196,212 @@ impl Database {
         Ok(deleted)
     }
 
-    pub fn get_settings(&self) -> Result<Settings, DbError> {
+    /// Sets or clears when a clip deletes itself, in epoch milliseconds.
+    pub fn set_clip_expiry(
+        &self,
+        id: i64,
+        expires_at: Option<i64>,
+    ) -> Result<Option<Clip>, DbError> {
         let conn = self.conn()?;
-        let row: (i64, i64, i64, i64, String) = conn.query_row(
-            "
-            SELECT history_limit, tracking_paused, max_clip_bytes, restore_clipboard_after_paste, denylist_bundle_ids
-            FROM settings
-            WHERE id = 1
-            ",
-            [],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+        conn.execute(
+            "UPDATE clips SET expires_at = ?1 WHERE id = ?2",
+            params![expires_at, id],
         )?;
-
-        Ok(Settings {
-            history_limit: row.0,
-            tracking_paused: row.1 == 1,
-            max_clip_bytes: row.2,
-            restore_clipboard_after_paste: row.3 == 1,
-            denylist_bundle_ids: serde_json::from_str(&row.4)
-                .unwrap_or_else(|_| default_denylist()),
-        })
+        self.get_clip_internal(&conn, id)
+            .optional()
+            .map_err(DbError::from)
     }
 
-    pub fn prune_excess(&self, history_limit: i64) -> Result<Vec<Clip>, DbError> {
-        let history_limit = history_limit.max(1);
+    /// Deletes unpinned clips whose expiry is at or before `now` and returns
+    /// them.
+    pub fn delete_expired_clips(&self, now: i64) -> Result<Vec<Clip>, DbError> {
         let mut conn = self.conn()?;
-
-        let total: i64 = conn.query_row("SELECT COUNT(*) FROM clips", [], |row| row.get(0))?;
-        let overflow = total - history_limit;
-        if overflow <= 0 {
-            return Ok(Vec::new());
-        }
-
+        let tx = conn.transaction()?;
         let clips = {
-            let mut stmt = conn.prepare(&format!(
+            let mut stmt = tx.prepare(&format!(
                 "
                 SELECT {CLIP_COLUMNS}
                 FROM clips
-                WHERE pinned = 0
-                ORDER BY created_at ASC, id ASC
-                LIMIT ?1
+                WHERE expires_at <= ?1 AND pinned = 0
                 "
             ))?;
-            let rows = stmt.query_map(params![overflow], clip_from_row)?;
+            let rows = stmt.query_map(params![now], clip_from_row)?;
             rows.collect::<Result<Vec<_>, _>>()?
         };
-
-        if clips.is_empty() {
-            return Ok(Vec::new());
-        }
-
-        let tx = conn.transaction()?;
-        for clip in &clips {
-            tx.execute("DELETE FROM clips WHERE id = ?1", params![clip.id])?;
-        }
+        tx.execute(
+            "DELETE FROM clips WHERE expires_at <= ?1 AND pinned = 0",
+            params![now],
+        )?;
         tx.commit()?;
-
         Ok(clips)
     }
 
-    pub fn referenced_media_paths(&self) -> Result<HashSet<String>, DbError> {
+    /// The earliest expiry among unpinned clips.
+    pub fn next_expiry(&self) -> Result<Option<i64>, DbError> {
         let conn = self.conn()?;
-        let mut referenced = HashSet::new();
-        let mut stmt = conn.prepare("SELECT media_path, thumb_path FROM clips")?;
-        let rows = stmt.query_map([], |row| {
-            Ok((
-                row.get::<_, Option<String>>(0)?,
-                row.get::<_, Option<String>>(1)?,
-            ))
-        })?;
-
-        for row in rows {
-            let (media_path, thumb_path) = row?;
-            if let Some(path) = media_path {
+        conn.query_row(
+            "SELECT MIN(expires_at) FROM clips WHERE pinned = 0",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(DbError::from)
+    }
+
+    pub fn get_settings(&self) -> Result<Settings, DbError> {
+        let conn = self.conn()?;
+        read_settings(&conn)
+    }
+
+    /// Applies a validated partial update and returns the stored result.
+    pub fn update_settings(&self, update: SettingsUpdate) -> Result<Settings, DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let mut settings = read_settings(&tx)?;
+        update.apply(&mut settings)?;
+        tx.execute(
+            "
+            UPDATE settings
+            SET history_limit = ?1,
+                tracking_paused = ?2,
+                tracking_paused_until = ?7,
+                max_clip_bytes = ?3,
+                restore_clipboard_after_paste = ?4,
+                denylist_bundle_ids = ?5,
+                capture_primary_selection = ?6,
+                unique_history = ?8,
+                secret_actions = ?9,
+                secret_expiry_seconds = ?10,
+                default_ttl_seconds = ?11,
+                max_age_days = ?12,
+                max_storage_bytes = ?13,
+                type_quotas = ?14
+            WHERE id = 1
+            ",
+            params![
+                settings.history_limit,
+                settings.tracking_paused,
+                settings.max_clip_bytes,
+                settings.restore_clipboard_after_paste,
+                serde_json::to_string(&settings.denylist_bundle_ids)?,
+                settings.capture_primary_selection,
+                settings.tracking_paused_until,
+                settings.unique_history,
+                serde_json::to_string(&settings.secret_actions)?,
+                settings.secret_expiry_seconds,
+                serde_json::to_string(&settings.default_ttl_seconds)?,
+                settings.max_age_days,
+                settings.max_storage_bytes,
+                serde_json::to_string(&settings.type_quotas)?,
+            ],
+        )?;
+        tx.commit()?;
+        Ok(settings)
+    }
+
+    /// Pauses or resumes capture. `until` is only kept while paused.
+    pub fn set_tracking_paused(
+        &self,
+        paused: bool,
+        until: Option<i64>,
+    ) -> Result<Settings, DbError> {
+        let conn = self.conn()?;
+        conn.execute(
+            "UPDATE settings SET tracking_paused = ?1, tracking_paused_until = ?2 WHERE id = 1",
+            params![paused, until.filter(|_| paused)],
+        )?;
+        read_settings(&conn)
+    }
+
+    pub fn retention_candidates(&self) -> Result<Vec<RetentionCandidate>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "
+            SELECT id,
+                   content_type,
+                   CAST(ROUND((julianday(created_at) - 2440587.5) * 86400000) AS INTEGER),
+                   NOT ({}),
+                   length(CAST(content AS BLOB))
+                     + IFNULL(length(CAST(html_content AS BLOB)), 0)
+                     + IFNULL(length(CAST(rtf_content AS BLOB)), 0)
+                     + IFNULL(length(CAST(file_entries AS BLOB)), 0),
+                   media_path,
+                   CASE WHEN media_path IS NULL THEN 0 ELSE byte_size END
+            FROM clips
+            ORDER BY sort_at ASC, id ASC
+            ",
+            tags::UNPROTECTED_CLIP
+        ))?;
+        let rows = stmt.query_map([], |row| {
+            Ok(RetentionCandidate {
+                id: row.get(0)?,
+                content_type: row.get(1)?,
+                created_at_ms: row.get(2)?,
+                protected: row.get(3)?,
+                content_bytes: row.get(4)?,
+                media_path: row.get(5)?,
+                media_bytes: row.get(6)?,
+            })
+        })?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(DbError::from)
+    }
+
+    /// Bytes the database pages in use take up, leaving out free pages.
+    pub fn storage_bytes(&self) -> Result<i64, DbError> {
+        let conn = self.conn()?;
+        let pages = conn.query_row(
+            "
+            SELECT page_count - freelist_count
+            FROM pragma_page_count(), pragma_freelist_count()
+            ",
+            [],
+            |row| row.get::<_, i64>(0),
+        )?;
+        let page_size: i64 = conn.pragma_query_value(None, "page_size", |row| row.get(0))?;
+        Ok(pages * page_size)
+    }
+
+    /// Points every clip that uses `old` as its original or thumbnail at
+    /// `new` instead.
+    pub fn replace_media_path(&self, old: &str, new: &str) -> Result<usize, DbError> {
+        let conn = self.conn()?;
+        Ok(conn.execute(
+            "
+            UPDATE clips SET
+              media_path = CASE WHEN media_path = ?1 THEN ?2 ELSE media_path END,
+              thumb_path = CASE WHEN thumb_path = ?1 THEN ?2 ELSE thumb_path END
+            WHERE media_path = ?1 OR thumb_path = ?1
+            ",
+            params![old, new],
+        )?)
+    }
+
+    /// Whether any clip still points at `path` as its original or thumbnail.
+    /// Clips of the same image share files, so these outlive single clips.
+    pub fn media_file_referenced(&self, path: &str) -> Result<bool, DbError> {
+        let conn = self.conn()?;
+        let referenced = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM clips WHERE media_path = ?1)
+                 OR EXISTS(SELECT 1 FROM clips WHERE thumb_path = ?1)",
+            params![path],
+            |row| row.get(0),
+        )?;
+        Ok(referenced)
+    }
+
+    pub fn referenced_media_paths(&self) -> Result<HashSet<String>, DbError> {
+        let conn = self.conn()?;
+        let mut referenced = HashSet::new();
+        let mut stmt = conn.prepare("SELECT media_path, thumb_path FROM clips")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?,
+                row.get::<_, Option<String>>(1)?,
+            ))
+        })?;
+
+        for row in rows {
+            let (media_path, thumb_path) = row?;
+            if let Some(path) = media_path {
                 referenced.insert(path);
             }
             if let Some(path) = thumb_path {
@@ -521,6 +1413,38 @@ impl Database {
     }
 }
 
+fn read_settings(conn: &Connection) -> Result<Settings, DbError> {
+    let json = |row: &Row<'_>, index| row.get::<_, String>(index);
+    let settings = conn.query_row(
+        "
+        SELECT history_limit, tracking_paused, max_clip_bytes, restore_clipboard_after_paste, denylist_bundle_ids, capture_primary_selection, tracking_paused_until, unique_history, secret_actions, secret_expiry_seconds, default_ttl_seconds, max_age_days, max_storage_bytes, type_quotas
+        FROM settings
+        WHERE id = 1
+        ",
+        [],
+        |row| {
+            Ok(Settings {
+                history_limit: row.get(0)?,
+                tracking_paused: row.get::<_, i64>(1)? == 1,
+                tracking_paused_until: row.get(6)?,
+                max_clip_bytes: row.get(2)?,
+                restore_clipboard_after_paste: row.get::<_, i64>(3)? == 1,
+                denylist_bundle_ids: serde_json::from_str(&json(row, 4)?)
+                    .unwrap_or_else(|_| default_denylist()),
+                capture_primary_selection: row.get::<_, i64>(5)? == 1,
+                unique_history: row.get::<_, i64>(7)? == 1,
+                secret_actions: serde_json::from_str(&json(row, 8)?).unwrap_or_default(),
+                secret_expiry_seconds: row.get(9)?,
+                default_ttl_seconds: serde_json::from_str(&json(row, 10)?).unwrap_or_default(),
+                max_age_days: row.get(11)?,
+                max_storage_bytes: row.get(12)?,
+                type_quotas: serde_json::from_str(&json(row, 13)?).unwrap_or_default(),
+            })
+        },
+    )?;
+    Ok(settings)
+}
+
 fn clip_from_row(row: &Row<'_>) -> Result<Clip, rusqlite::Error> {
     Ok(Clip {
         id: row.get(0)?,
@@ -534,83 +1458,320 @@ fn clip_from_row(row: &Row<'_>) -> Result<Clip, rusqlite::Error> {
         byte_size: row.get(8)?,
         pixel_width: row.get(9)?,
         pixel_height: row.get(10)?,
+        source: row.get(11)?,
+        source_app: row.get(12)?,
+        has_html: row.get(13)?,
+        has_rtf: row.get(14)?,
+        files: row
+            .get::<_, Option<String>>(15)?
+            .and_then(|json| serde_json::from_str::<Vec<ClipFile>>(&json).ok()),
+        copy_count: row.get(16)?,
+        use_count: row.get(17)?,
+        last_used_at: row.get(18)?,
+        tags: serde_json::from_str(&row.get::<_, String>(19)?).unwrap_or_default(),
+        pin_order: row.get(20)?,
+        expires_at: row.get(21)?,
+        sort_at: row.get(22)?,
+        highlights: Vec::new(),
     })
 }
 
-fn ensure_clips_schema(conn: &Connection) -> Result<(), DbError> {
-    let table_sql: Option<String> = conn
-        .query_row(
-            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'clips'",
-            [],
-            |row| row.get(0),
-        )
-        .optional()?;
-
-    if table_sql.is_none() {
-        conn.execute_batch(schema::CREATE_CLIPS_TABLE)?;
-        return Ok(());
+/// Newest first with pinned clips on top in their manual order, resuming
+/// strictly after the cursor's `(pinned, pin_order, sort_at, id)`.
+fn list_recent_clips(
+    conn: &Connection,
+    mut conditions: Vec<String>,
+    mut values: Vec<Value>,
+    cursor: Option<PageCursor>,
+    limit: i64,
+    include_total: bool,
+) -> Result<ClipPage, DbError> {
+    let total = if include_total {
+        Some(count_clips(conn, "clips", &conditions, &values)?)
+    } else {
+        None
+    };
+    if let Some(cursor) = cursor {
+        conditions.push(
+            "(clips.pinned, clips.pin_order, clips.sort_at, clips.id) < (?, ?, ?, ?)".to_string(),
+        );
+        values.push(Value::Integer(i64::from(cursor.pinned)));
+        values.push(Value::Integer(cursor.pin_order));
+        values.push(Value::Text(cursor.sort_at));
+        values.push(Value::Integer(cursor.id));
     }
+    values.push(Value::Integer(limit + 1));
 
-    if clips_schema_is_current(conn, table_sql.as_deref().unwrap_or_default())? {
-        return Ok(());
+    let mut stmt = conn.prepare(&format!(
+        "
+        SELECT {CLIP_COLUMNS}
+        FROM clips
+        {}
+        ORDER BY clips.pinned DESC, clips.pin_order DESC, clips.sort_at DESC, clips.id DESC
+        LIMIT ?
+        ",
+        where_clause(&conditions)
+    ))?;
+    let mut items = stmt
+        .query_map(params_from_iter(values.iter()), clip_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let next_cursor = next_page_cursor(&mut items, limit, |last, _| {
+        PageCursor::after(last, None, None)
+    })?;
+    Ok(ClipPage {
+        items,
+        total,
+        next_cursor,
+    })
+}
+
+/// Full-text results by rank. The first page pins `julianday('now')` into
+/// the cursor so later pages compute the same scores and resume after the
+/// last one; `conditions` must start with the `MATCH`.
+fn list_ranked_clips(
+    conn: &Connection,
+    conditions: Vec<String>,
+    mut values: Vec<Value>,
+    cursor: Option<PageCursor>,
+    limit: i64,
+    include_total: bool,
+) -> Result<ClipPage, DbError> {
+    const FROM_FTS: &str = "clips_fts JOIN clips ON clips.id = clips_fts.rowid";
+    let total = if include_total {
+        Some(count_clips(conn, FROM_FTS, &conditions, &values)?)
+    } else {
+        None
+    };
+    let (resume, now) = match &cursor {
+        Some(cursor) => {
+            let (score, now) = cursor.ranked()?;
+            (Some((score, cursor)), now)
+        }
+        None => (
+            None,
+            conn.query_row("SELECT julianday('now')", [], |row| row.get(0))?,
+        ),
+    };
+
+    // The rank's `?` comes first in the statement, so its value does too.
+    values.insert(0, Value::Real(now));
+    let mut resume_clause = String::new();
+    if let Some((score, cursor)) = resume {
+        resume_clause = "WHERE search_score > ?
+            OR (search_score = ? AND (sort_time, sort_id) < (?, ?))"
+            .to_string();
+        values.push(Value::Real(score));
+        values.push(Value::Real(score));
+        values.push(Value::Text(cursor.sort_at.clone()));
+        values.push(Value::Integer(cursor.id));
     }
+    values.push(Value::Integer(limit + 1));
 
-    migrate_clips_table(conn)?;
-    Ok(())
+    let mut stmt = conn.prepare(&format!(
+        "
+        SELECT * FROM (
+            SELECT
+                {CLIP_COLUMNS},
+                {HIGHLIGHT_COLUMN} AS highlight,
+                {SEARCH_RANK} AS search_score,
+                clips.sort_at AS sort_time,
+                clips.id AS sort_id
+            FROM {FROM_FTS}
+            {}
+        )
+        {resume_clause}
+        ORDER BY search_score, sort_time DESC, sort_id DESC
+        LIMIT ?
+        ",
+        where_clause(&conditions)
+    ))?;
+    let rows = stmt.query_map(params_from_iter(values.iter()), |row| {
+        let mut clip = clip_from_row(row)?;
+        if let Some(marked) = row.get::<_, Option<String>>(CLIP_COLUMN_COUNT)? {
+            clip.highlights = highlight_ranges(&marked, &clip.content);
+        }
+        Ok((clip, row.get::<_, f64>(CLIP_COLUMN_COUNT + 1)?))
+    })?;
+    let (mut items, scores): (Vec<_>, Vec<_>) =
+        rows.collect::<Result<Vec<_>, _>>()?.into_iter().unzip();
+
+    let next_cursor = next_page_cursor(&mut items, limit, |last, index| {
+        PageCursor::after(last, Some(scores[index]), Some(now))
+    })?;
+    Ok(ClipPage {
+        items,
+        total,
+        next_cursor,
+    })
 }
 
-fn clips_schema_is_current(conn: &Connection, table_sql: &str) -> Result<bool, DbError> {
-    if !table_sql.contains("'image'") {
-        return Ok(false);
+/// Pinned clips first in their manual order, then by [`FRECENCY_SCORE`]. Like ranked search, the
+/// first page pins `julianday('now')` into the cursor so scores stay put
+/// while paging.
+fn list_frecent_clips(
+    conn: &Connection,
+    conditions: Vec<String>,
+    mut values: Vec<Value>,
+    cursor: Option<PageCursor>,
+    limit: i64,
+    include_total: bool,
+) -> Result<ClipPage, DbError> {
+    let total = if include_total {
+        Some(count_clips(conn, "clips", &conditions, &values)?)
+    } else {
+        None
+    };
+    let (resume, now) = match &cursor {
+        Some(cursor) => {
+            let (score, now) = cursor.ranked()?;
+            (Some((score, cursor)), now)
+        }
+        None => (
+            None,
+            conn.query_row("SELECT julianday('now')", [], |row| row.get(0))?,
+        ),
+    };
+
+    values.insert(0, Value::Real(now));
+    let mut resume_clause = String::new();
+    if let Some((score, cursor)) = resume {
+        resume_clause = "WHERE (sort_pinned, sort_pin_order, frecency_score, sort_time, sort_id)
+                < (?, ?, ?, ?, ?)"
+            .to_string();
+        values.push(Value::Integer(i64::from(cursor.pinned)));
+        values.push(Value::Integer(cursor.pin_order));
+        values.push(Value::Real(score));
+        values.push(Value::Text(cursor.sort_at.clone()));
+        values.push(Value::Integer(cursor.id));
     }
+    values.push(Value::Integer(limit + 1));
 
-    let mut columns = HashSet::new();
-    let mut stmt = conn.prepare("PRAGMA table_info(clips)")?;
-    let rows = stmt.query_map([], |row| row.get::<_, String>(1))?;
-    for row in rows {
-        columns.insert(row?);
-    }
+    let mut stmt = conn.prepare(&format!(
+        "
+        SELECT * FROM (
+            SELECT
+                {CLIP_COLUMNS},
+                {FRECENCY_SCORE} AS frecency_score,
+                clips.pinned AS sort_pinned,
+                clips.pin_order AS sort_pin_order,
+                clips.sort_at AS sort_time,
+                clips.id AS sort_id
+            FROM clips
+            {}
+        )
+        {resume_clause}
+        ORDER BY
+            sort_pinned DESC,
+            sort_pin_order DESC,
+            frecency_score DESC,
+            sort_time DESC,
+            sort_id DESC
+        LIMIT ?
+        ",
+        where_clause(&conditions)
+    ))?;
+    let rows = stmt.query_map(params_from_iter(values.iter()), |row| {
+        Ok((clip_from_row(row)?, row.get::<_, f64>(CLIP_COLUMN_COUNT)?))
+    })?;
+    let (mut items, scores): (Vec<_>, Vec<_>) =
+        rows.collect::<Result<Vec<_>, _>>()?.into_iter().unzip();
+
+    let next_cursor = next_page_cursor(&mut items, limit, |last, index| {
+        PageCursor::after(last, Some(scores[index]), Some(now))
+    })?;
+    Ok(ClipPage {
+        items,
+        total,
+        next_cursor,
+    })
+}
 
-    let required = [
-        "media_path",
-        "thumb_path",
-        "mime_type",
-        "byte_size",
-        "pixel_width",
-        "pixel_height",
-    ];
+fn count_clips(
+    conn: &Connection,
+    from_clause: &str,
+    conditions: &[String],
+    values: &[Value],
+) -> Result<i64, DbError> {
+    conn.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM {from_clause} {}",
+            where_clause(conditions)
+        ),
+        params_from_iter(values.iter()),
+        |row| row.get(0),
+    )
+    .map_err(DbError::from)
+}
 
-    Ok(required.iter().all(|column| columns.contains(*column)))
+/// Pages are fetched one row long; the extra row only proves there is a
+/// next page, whose cursor points after the last row kept.
+fn next_page_cursor(
+    items: &mut Vec<Clip>,
+    limit: i64,
+    cursor_after: impl Fn(&Clip, usize) -> PageCursor,
+) -> Result<Option<String>, DbError> {
+    let limit = limit as usize;
+    if items.len() <= limit {
+        return Ok(None);
+    }
+    items.truncate(limit);
+    cursor_after(&items[limit - 1], limit - 1)
+        .encode()
+        .map(Some)
 }
 
-fn migrate_clips_table(conn: &Connection) -> Result<(), DbError> {
-    conn.execute_batch("DROP TABLE IF EXISTS clips_v2;")?;
-    conn.execute_batch(schema::CREATE_CLIPS_TABLE_V2)?;
-    conn.execute_batch(
-        "
-        INSERT INTO clips_v2 (
-            id,
-            content,
-            content_type,
-            pinned,
-            hash,
-            byte_size,
-            created_at
-        )
-        SELECT
-            id,
-            content,
-            content_type,
-            pinned,
-            hash,
-            length(CAST(content AS BLOB)),
-            created_at
-        FROM clips;
-        ",
-    )?;
-    conn.execute_batch("DROP TABLE clips;")?;
-    conn.execute_batch("ALTER TABLE clips_v2 RENAME TO clips;")?;
-    Ok(())
+fn where_clause(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    }
+}
+
+/// Reads the marker-delimited output of FTS5 `highlight()` back into ranges
+/// over `content`. A clip may contain the marker characters itself, so each
+/// run of one is compared with the same run in `content` and the extra
+/// character is taken as the marker: the last of the run when it opens a
+/// match, the first when it closes one.
+fn highlight_ranges(marked: &str, content: &str) -> Vec<MatchRange> {
+    let marked = marked.chars().collect::<Vec<_>>();
+    let content = content.chars().collect::<Vec<_>>();
+    let mut ranges = Vec::new();
+    let (mut index, mut content_index, mut offset) = (0, 0, 0);
+    let mut start = None;
+    while let Some(&ch) = marked.get(index) {
+        if ch != HIGHLIGHT_OPEN && ch != HIGHLIGHT_CLOSE {
+            offset += ch.len_utf16();
+            index += 1;
+            content_index += 1;
+            continue;
+        }
+        let marked_run = marked[index..]
+            .iter()
+            .take_while(|&&next| next == ch)
+            .count();
+        let content_run = content
+            .get(content_index..)
+            .unwrap_or_default()
+            .iter()
+            .take_while(|&&next| next == ch)
+            .count()
+            .min(marked_run);
+        // Both markers are a single UTF-16 unit.
+        let run_end = offset + content_run;
+        if marked_run > content_run {
+            if ch == HIGHLIGHT_OPEN {
+                start = Some(run_end);
+            } else if let Some(start) = start.take() {
+                ranges.push(MatchRange { start, end: offset });
+            }
+        }
+        offset = run_end;
+        index += marked_run;
+        content_index += content_run;
+    }
+    ranges
 }
 
 #[cfg(test)]
@@ -630,24 +1791,727 @@ mod tests {
         let second = db.insert_clip("second", "text").expect("insert second");
         db.set_pinned(first.id, true).expect("pin first");
 
-        let page = db.list_clips(None, 10, 0).expect("list clips");
+        let page = db
+            .list_clips(ClipListRequest {
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("list clips");
         assert_eq!(page.items.first().map(|x| x.id), Some(first.id));
         assert!(page.items.iter().any(|x| x.id == second.id));
     }
 
     #[test]
-    fn prune_keeps_pinned() {
+    fn pinned_clips_keep_their_manual_order_across_pages() {
+        let db = Database::new_in_memory().expect("db init");
+        let ids = ["a", "b", "c", "d"]
+            .iter()
+            .map(|content| db.insert_clip(content, "text").expect("insert").id)
+            .collect::<Vec<_>>();
+        for &id in &ids[..3] {
+            db.set_pinned(id, true).expect("pin");
+        }
+        // Re-pinning keeps a clip's place; new pins go on top.
+        db.set_pinned(ids[0], true).expect("pin again");
+
+        let list = |cursor: Option<&str>, sort| {
+            db.list_clips(ClipListRequest {
+                cursor,
+                sort,
+                limit: 2,
+                ..Default::default()
+            })
+            .expect("list")
+        };
+        let all = |sort| {
+            let first = list(None, sort);
+            let second = list(first.next_cursor.as_deref(), sort);
+            first
+                .items
+                .iter()
+                .chain(&second.items)
+                .map(|clip| clip.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(all(ClipSort::Recent), vec![ids[2], ids[1], ids[0], ids[3]]);
+
+        let reordered = db.reorder_pinned(&[ids[0], ids[2]]).expect("reorder");
+        assert_eq!(
+            reordered.iter().map(|clip| clip.id).collect::<Vec<_>>(),
+            vec![ids[0], ids[2], ids[1]]
+        );
+        assert_eq!(all(ClipSort::Recent), vec![ids[0], ids[2], ids[1], ids[3]]);
+        assert_eq!(
+            all(ClipSort::Frecency),
+            vec![ids[0], ids[2], ids[1], ids[3]]
+        );
+
+        db.set_pinned(ids[3], true).expect("pin d");
+        assert_eq!(all(ClipSort::Recent)[0], ids[3]);
+        db.set_pinned(ids[2], false).expect("unpin c");
+        assert_eq!(
+            db.get_clip(ids[2]).expect("get").map(|clip| clip.pin_order),
+            Some(0)
+        );
+
+        for invalid in [vec![ids[2]], vec![ids[0], ids[0]]] {
+            assert!(matches!(
+                db.reorder_pinned(&invalid),
+                Err(DbError::InvalidPinOrder(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn retention_candidates_list_oldest_first_and_protect_pins() {
         let db = Database::new_in_memory().expect("db init");
         let pinned = db.insert_clip("pinned", "text").expect("insert pinned");
         db.set_pinned(pinned.id, true).expect("set pin");
-        db.insert_clip("a", "text").expect("insert a");
-        db.insert_clip("b", "text").expect("insert b");
+        let rich = db
+            .insert_rich_text_clip(RichTextClipInsert {
+                content: "bold",
+                content_type: "text",
+                hash: "rich-hash",
+                html_content: Some("<b>bold</b>"),
+                rtf_content: None,
+                byte_size: 15,
+                source: "clipboard",
+                source_app: None,
+            })
+            .expect("insert rich");
+        let image = db
+            .insert_image_clip(ImageClipInsert {
+                content: "Image",
+                hash: "image-hash",
+                media_path: "/tmp/a.png",
+                thumb_path: "/tmp/a-thumb.png",
+                mime_type: "image/png",
+                byte_size: 2_048,
+                pixel_width: 1,
+                pixel_height: 1,
+                source: "clipboard",
+                source_app: None,
+            })
+            .expect("insert image");
+
+        let candidates = db.retention_candidates().expect("candidates");
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|clip| (
+                    clip.id,
+                    clip.protected,
+                    clip.content_bytes,
+                    clip.media_bytes
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (pinned.id, true, 6, 0),
+                (rich.id, false, 15, 0),
+                (image.id, false, 5, 2_048)
+            ]
+        );
+        assert_eq!(candidates[2].media_path.as_deref(), Some("/tmp/a.png"));
+        assert!(candidates[0].created_at_ms <= candidates[2].created_at_ms);
+        assert!(db.storage_bytes().expect("storage") > 0);
+    }
+
+    #[test]
+    fn bumping_moves_a_clip_to_the_top_and_counts_the_copy() {
+        let db = Database::new_in_memory().expect("db init");
+        let first = db.insert_clip("first", "text").expect("insert first");
+        let second = db.insert_clip("second", "text").expect("insert second");
+        assert_eq!((first.copy_count, first.last_used_at.as_deref()), (1, None));
+        assert!(second.sort_at > first.sort_at);
+
+        let bumped = db
+            .bump_clip(&sha256_hex("first"))
+            .expect("bump")
+            .expect("clip with hash");
+        assert_eq!(bumped.id, first.id);
+        assert_eq!(bumped.copy_count, 2);
+        assert!(bumped.last_used_at.is_some());
+        assert!(bumped.sort_at > second.sort_at);
+        assert_eq!(bumped.created_at, first.created_at);
+
+        let page = db
+            .list_clips(ClipListRequest {
+                limit: 10,
+                ..Default::default()
+            })
+            .expect("list");
+        let ids = page.items.iter().map(|clip| clip.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![first.id, second.id]);
+        assert_eq!(db.bump_clip(&sha256_hex("missing")).expect("bump"), None);
+    }
+
+    #[test]
+    fn frecency_favors_clips_used_often_and_lately() {
+        let db = Database::new_in_memory().expect("db init");
+        let daily = db.insert_clip("daily", "text").expect("insert daily");
+        let stale = db.insert_clip("stale", "text").expect("insert stale");
+        let fresh = db.insert_clip("fresh", "text").expect("insert fresh");
+        for _ in 0..3 {
+            db.record_use(daily.id).expect("use daily");
+            db.record_use(stale.id).expect("use stale");
+        }
+        db.conn()
+            .expect("conn")
+            .execute(
+                "UPDATE clips SET last_used_at = '2020-01-01 00:00:00' WHERE id = ?1",
+                params![stale.id],
+            )
+            .expect("age stale");
+        assert_eq!(
+            db.record_use(fresh.id).expect("use").map(|c| c.use_count),
+            Some(1)
+        );
+
+        let list = |cursor: Option<&str>| {
+            db.list_clips(ClipListRequest {
+                sort: ClipSort::Frecency,
+                cursor,
+                limit: 2,
+                ..Default::default()
+            })
+            .expect("list")
+        };
+        let first = list(None);
+        let second = list(first.next_cursor.as_deref());
+        let ids = first
+            .items
+            .iter()
+            .chain(&second.items)
+            .map(|clip| clip.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![daily.id, fresh.id, stale.id]);
+        assert_eq!(second.next_cursor, None);
+
+        let recent_cursor = db
+            .list_clips(ClipListRequest {
+                limit: 1,
+                ..Default::default()
+            })
+            .expect("recent")
+            .next_cursor;
+        assert!(matches!(
+            db.list_clips(ClipListRequest {
+                sort: ClipSort::Frecency,
+                cursor: recent_cursor.as_deref(),
+                limit: 1,
+                ..Default::default()
+            }),
+            Err(DbError::InvalidCursor)
+        ));
+    }
 
-        let deleted = db.prune_excess(1).expect("prune");
-        assert_eq!(deleted.len(), 2);
-        let page = db.list_clips(None, 10, 0).expect("list");
-        assert_eq!(page.items.len(), 1);
-        assert_eq!(page.items[0].id, pinned.id);
+    #[test]
+    fn filters_clips_by_selection_source() {
+        let db = Database::new_in_memory().expect("db init");
+        let copied = db
+            .insert_text_clip("copied", "text", "hash-a", "clipboard", None)
+            .expect("insert copied");
+        let selected = db
+            .insert_text_clip("selected", "text", "hash-b", "primary", None)
+            .expect("insert selected");
+
+        let page = db
+            .list_clips(ClipListRequest {
+                source: Some("primary"),
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("list primary");
+        assert_eq!(page.total, Some(1));
+        assert_eq!(page.items[0].id, selected.id);
+        assert_eq!(page.items[0].source, "primary");
+
+        let page = db
+            .list_clips(ClipListRequest {
+                query: Some("COP"),
+                source: Some("clipboard"),
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("list clipboard");
+        assert_eq!(
+            page.items.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![copied.id]
+        );
+    }
+
+    #[test]
+    fn full_text_search_matches_word_prefixes_with_highlights() {
+        let db = Database::new_in_memory().expect("db init");
+        db.insert_clip("Deploy the Kübernetes cluster", "text")
+            .expect("insert deploy");
+        db.insert_clip("unrelated note", "text")
+            .expect("insert note");
+        db.insert_clip("kubernetes docs: kubernetes.io", "url")
+            .expect("insert docs");
+
+        let page = db
+            .list_clips(ClipListRequest {
+                query: Some("kube"),
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("search");
+        assert_eq!(page.total, Some(2));
+        assert!(page
+            .items
+            .iter()
+            .all(|clip| clip.content != "unrelated note"));
+
+        let deploy = page
+            .items
+            .iter()
+            .find(|clip| clip.content.starts_with("Deploy"))
+            .expect("deploy hit");
+        assert_eq!(deploy.highlights, vec![MatchRange { start: 11, end: 21 }]);
+
+        let docs = page
+            .items
+            .iter()
+            .find(|clip| clip.content.starts_with("kubernetes docs"))
+            .expect("docs hit");
+        assert_eq!(docs.highlights.len(), 2);
+        assert_eq!(page.items[0].id, docs.id, "more hits rank higher");
+
+        let unhighlighted = db
+            .list_clips(ClipListRequest {
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("list");
+        assert!(unhighlighted
+            .items
+            .iter()
+            .all(|clip| clip.highlights.is_empty()));
+    }
+
+    #[test]
+    fn highlights_skip_marker_characters_in_content() {
+        let db = Database::new_in_memory().expect("db init");
+        db.insert_clip(
+            "a\u{E001} \u{E000}\u{E000} kubectl \u{E000}\u{E001}",
+            "text",
+        )
+        .expect("insert");
+
+        let page = db
+            .list_clips(ClipListRequest {
+                query: Some("kubectl"),
+                limit: 10,
+                ..Default::default()
+            })
+            .expect("search");
+        assert_eq!(
+            page.items[0].highlights,
+            vec![MatchRange { start: 6, end: 13 }]
+        );
+    }
+
+    #[test]
+    fn search_index_follows_deletes_and_prunes() {
+        let db = Database::new_in_memory().expect("db init");
+        let deleted = db.insert_clip("alpha one", "text").expect("insert one");
+        let pruned = db.insert_clip("alpha two", "text").expect("insert two");
+        db.insert_clip("alpha three", "text").expect("insert three");
+
+        db.delete_clip(deleted.id).expect("delete");
+        db.delete_clips_by_ids(&[pruned.id]).expect("prune");
+
+        let page = db
+            .list_clips(ClipListRequest {
+                query: Some("alpha"),
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("search");
+        assert_eq!(page.total, Some(1));
+        assert_eq!(page.items[0].content, "alpha three");
+    }
+
+    #[test]
+    fn search_ranking_boosts_pinned_clips() {
+        let db = Database::new_in_memory().expect("db init");
+        let older = db
+            .insert_clip("release notes", "text")
+            .expect("insert older");
+        db.insert_clip("release notes", "text")
+            .expect("insert newer");
+        db.set_pinned(older.id, true).expect("pin older");
+
+        let page = db
+            .list_clips(ClipListRequest {
+                query: Some("release"),
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("search");
+        assert_eq!(page.items[0].id, older.id);
+    }
+
+    fn page_ids(page: &ClipPage) -> Vec<i64> {
+        page.items.iter().map(|clip| clip.id).collect()
+    }
+
+    #[test]
+    fn cursor_pages_survive_inserts_and_deletes_while_scrolling() {
+        let db = Database::new_in_memory().expect("db init");
+        let ids = (0..5)
+            .map(|n| {
+                db.insert_clip(&format!("clip {n}"), "text")
+                    .expect("insert")
+                    .id
+            })
+            .collect::<Vec<_>>();
+        db.set_pinned(ids[1], true).expect("pin");
+
+        let request = ClipListRequest {
+            limit: 2,
+            ..Default::default()
+        };
+        let first = db.list_clips(request).expect("first page");
+        assert_eq!(page_ids(&first), vec![ids[1], ids[4]]);
+        assert_eq!(first.total, None);
+
+        db.insert_clip("arrived while scrolling", "text")
+            .expect("insert new");
+        db.delete_clip(ids[4]).expect("delete seen");
+
+        let second = db
+            .list_clips(ClipListRequest {
+                cursor: first.next_cursor.as_deref(),
+                ..request
+            })
+            .expect("second page");
+        assert_eq!(page_ids(&second), vec![ids[3], ids[2]]);
+
+        let last = db
+            .list_clips(ClipListRequest {
+                cursor: second.next_cursor.as_deref(),
+                ..request
+            })
+            .expect("last page");
+        assert_eq!(page_ids(&last), vec![ids[0]]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn ranked_search_pages_resume_after_the_last_score() {
+        let db = Database::new_in_memory().expect("db init");
+        for content in ["alpha", "alpha alpha", "alpha beta", "alpha alpha alpha"] {
+            db.insert_clip(content, "text").expect("insert");
+        }
+        let all = db
+            .list_clips(ClipListRequest {
+                query: Some("alpha"),
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("single page");
+        assert_eq!(all.total, Some(4));
+
+        let mut paged = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = db
+                .list_clips(ClipListRequest {
+                    query: Some("alpha"),
+                    cursor: cursor.as_deref(),
+                    limit: 1,
+                    ..Default::default()
+                })
+                .expect("page");
+            paged.extend(page_ids(&page));
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(paged, page_ids(&all));
+    }
+
+    #[test]
+    fn rejects_malformed_and_mismatched_cursors() {
+        let db = Database::new_in_memory().expect("db init");
+        db.insert_clip("alpha one", "text").expect("insert one");
+        db.insert_clip("alpha two", "text").expect("insert two");
+        let browse = db
+            .list_clips(ClipListRequest {
+                limit: 1,
+                ..Default::default()
+            })
+            .expect("browse");
+
+        for (query, cursor) in [
+            (None, "not-a-cursor"),
+            (
+                Some("alpha"),
+                browse.next_cursor.as_deref().expect("cursor"),
+            ),
+        ] {
+            let result = db.list_clips(ClipListRequest {
+                query,
+                cursor: Some(cursor),
+                limit: 1,
+                ..Default::default()
+            });
+            assert!(matches!(result, Err(DbError::InvalidCursor)), "{cursor}");
+        }
+    }
+
+    #[test]
+    fn punctuation_only_queries_match_literally() {
+        let db = Database::new_in_memory().expect("db init");
+        db.insert_clip("fn main() {}", "code").expect("insert code");
+        db.insert_clip("plain words", "text").expect("insert text");
+
+        let page = db
+            .list_clips(ClipListRequest {
+                query: Some("{"),
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("search");
+        assert_eq!(page.total, Some(1));
+        assert_eq!(page.items[0].content, "fn main() {}");
+    }
+
+    #[test]
+    fn structured_queries_filter_on_clip_metadata() {
+        let db = Database::new_in_memory().expect("db init");
+        let safari = db
+            .insert_text_clip(
+                "https://example.com/draft",
+                "url",
+                "hash-a",
+                "clipboard",
+                Some("com.apple.Safari"),
+            )
+            .expect("insert safari");
+        let pinned = db
+            .insert_text_clip(
+                "https://example.com/exact phrase",
+                "url",
+                "hash-b",
+                "clipboard",
+                Some("com.apple.Safari"),
+            )
+            .expect("insert pinned");
+        db.set_pinned(pinned.id, true).expect("pin");
+        let old = db
+            .insert_text_clip("an exact phrase", "text", "hash-c", "primary", None)
+            .expect("insert old");
+        db.conn()
+            .expect("conn")
+            .execute(
+                "UPDATE clips SET created_at = '2026-09-30 23:59:59' WHERE id = ?1",
+                params![old.id],
+            )
+            .expect("backdate");
+
+        let ids = |query: &str| {
+            db.list_clips(ClipListRequest {
+                query: Some(query),
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect(query)
+            .items
+            .iter()
+            .map(|clip| clip.id)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(
+                r#"type:url pinned:yes app:com.apple.safari after:2026-10-01 "exact phrase" -draft"#
+            ),
+            vec![pinned.id]
+        );
+        assert_eq!(ids("type:url -draft"), vec![pinned.id]);
+        assert_eq!(ids("before:2026-10-01"), vec![old.id]);
+        assert_eq!(ids("-app:com.apple.Safari"), vec![old.id]);
+        assert_eq!(ids("source:primary exact"), vec![old.id]);
+        assert_eq!(ids("type:text,url size:>20"), vec![pinned.id, safari.id]);
+        assert!(ids("width:>0").is_empty());
+    }
+
+    #[test]
+    fn fuzzy_mode_forgives_typos_and_keeps_filters() {
+        let db = Database::new_in_memory().expect("db init");
+        let apply = db
+            .insert_clip("kubectl apply -f deploy.yaml", "code")
+            .expect("insert apply");
+        db.insert_clip("kubectl apply notes", "text")
+            .expect("insert notes");
+        db.insert_clip("grocery list", "text").expect("insert list");
+
+        let full_text = db
+            .list_clips(ClipListRequest {
+                query: Some("kubctl apply"),
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("full text");
+        assert_eq!(full_text.total, Some(0));
+
+        let fuzzy = db
+            .list_clips(ClipListRequest {
+                query: Some("kubctl apply"),
+                mode: SearchMode::Fuzzy,
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("fuzzy");
+        assert_eq!(fuzzy.total, Some(2));
+        assert_eq!(
+            fuzzy.items[0].highlights,
+            vec![
+                MatchRange { start: 0, end: 3 },
+                MatchRange { start: 4, end: 7 },
+                MatchRange { start: 8, end: 13 },
+            ]
+        );
+
+        let filtered = db
+            .list_clips(ClipListRequest {
+                query: Some("type:code kuebctl"),
+                mode: SearchMode::Fuzzy,
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("fuzzy with filter");
+        assert_eq!(
+            filtered
+                .items
+                .iter()
+                .map(|clip| clip.id)
+                .collect::<Vec<_>>(),
+            vec![apply.id]
+        );
+
+        let request = ClipListRequest {
+            query: Some("apply"),
+            mode: SearchMode::Fuzzy,
+            limit: 1,
+            ..Default::default()
+        };
+        let first = db.list_clips(request).expect("first page");
+        let cursor = first.next_cursor.as_deref().expect("more pages");
+        let second = db
+            .list_clips(ClipListRequest {
+                cursor: Some(cursor),
+                ..request
+            })
+            .expect("second page");
+        assert_eq!((first.total, second.next_cursor), (None, None));
+        assert_eq!(second.items.len(), 1);
+        assert_ne!(second.items[0].id, first.items[0].id);
+
+        // Pages reuse the scores above; a write has to invalidate them.
+        db.insert_clip("apply the patch", "text")
+            .expect("insert patch");
+        let rescored = db
+            .list_clips(ClipListRequest {
+                include_total: true,
+                ..request
+            })
+            .expect("after insert");
+        assert_eq!(rescored.total, Some(3));
+    }
+
+    #[test]
+    fn invalid_queries_are_reported_instead_of_matched_literally() {
+        let db = Database::new_in_memory().expect("db init");
+        db.insert_clip("pinned:maybe", "text").expect("insert");
+
+        let err = db
+            .list_clips(ClipListRequest {
+                query: Some("pinned:maybe"),
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect_err("invalid pinned value");
+        assert_eq!(
+            err.to_string(),
+            "invalid search query: invalid value `maybe` for `pinned:`, expected yes or no"
+        );
+    }
+
+    #[test]
+    fn unknown_keys_with_colons_are_searched_as_text() {
+        let db = Database::new_in_memory().expect("db init");
+        let server = db
+            .insert_clip("dev server on localhost:3000", "text")
+            .expect("insert server");
+        let todo = db
+            .insert_clip("TODO:fix the flaky test", "text")
+            .expect("insert todo");
+        db.insert_clip("unrelated", "text").expect("insert other");
+
+        for (query, expected) in [("localhost:3000", server.id), ("TODO:fix", todo.id)] {
+            let page = db
+                .list_clips(ClipListRequest {
+                    query: Some(query),
+                    limit: 10,
+                    ..Default::default()
+                })
+                .expect(query);
+            assert_eq!(
+                page.items.iter().map(|clip| clip.id).collect::<Vec<_>>(),
+                vec![expected],
+                "{query}"
+            );
+        }
+    }
+
+    #[test]
+    fn percent_and_underscore_are_searched_literally() {
+        let db = Database::new_in_memory().expect("db init");
+        let percent = db.insert_clip("100% done", "text").expect("insert percent");
+        let underscore = db
+            .insert_clip("name it snake_case", "text")
+            .expect("insert underscore");
+        let plain = db.insert_clip("plain words", "text").expect("insert other");
+
+        for (query, expected) in [("%", percent.id), ("_", underscore.id), ("-% -_", plain.id)] {
+            let page = db
+                .list_clips(ClipListRequest {
+                    query: Some(query),
+                    limit: 10,
+                    ..Default::default()
+                })
+                .expect(query);
+            assert_eq!(
+                page.items.iter().map(|clip| clip.id).collect::<Vec<_>>(),
+                vec![expected],
+                "{query}"
+            );
+        }
+    }
+
+    #[test]
+    fn primary_capture_is_opt_in() {
+        let db = Database::new_in_memory().expect("db init");
+        let settings = db.get_settings().expect("settings");
+        assert!(!settings.capture_primary_selection);
     }
 
     #[test]
@@ -657,6 +2521,52 @@ mod tests {
         assert_eq!(settings.max_clip_bytes, 10_485_760);
     }
 
+    #[test]
+    fn updates_settings_partially_and_rejects_out_of_range_values() {
+        let db = Database::new_in_memory().expect("db init");
+        let updated = db
+            .update_settings(SettingsUpdate {
+                history_limit: Some(50),
+                denylist_bundle_ids: Some(vec![
+                    " com.bitwarden.desktop ".to_string(),
+                    String::new(),
+                    "com.bitwarden.desktop".to_string(),
+                    "KeePassXC".to_string(),
+                ]),
+                ..Default::default()
+            })
+            .expect("update");
+        assert_eq!(updated.history_limit, 50);
+        assert_eq!(
+            updated.denylist_bundle_ids,
+            vec!["com.bitwarden.desktop".to_string(), "KeePassXC".to_string()]
+        );
+        assert_eq!(updated.max_clip_bytes, DEFAULT_MAX_CLIP_BYTES);
+        assert_eq!(db.get_settings().expect("settings"), updated);
+
+        for update in [
+            SettingsUpdate {
+                history_limit: Some(0),
+                ..Default::default()
+            },
+            SettingsUpdate {
+                tracking_paused: Some(true),
+                max_clip_bytes: Some(1_000_000_000),
+                ..Default::default()
+            },
+            SettingsUpdate {
+                denylist_bundle_ids: Some(vec!["x".repeat(300)]),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(
+                db.update_settings(update),
+                Err(DbError::InvalidSetting { .. })
+            ));
+        }
+        assert_eq!(db.get_settings().expect("settings"), updated);
+    }
+
     #[test]
     fn inserts_image_clip_with_metadata() {
         let db = Database::new_in_memory().expect("db init");
@@ -670,6 +2580,8 @@ mod tests {
                 byte_size: 1234,
                 pixel_width: 20,
                 pixel_height: 10,
+                source: "clipboard",
+                source_app: None,
             })
             .expect("insert image");
 
@@ -681,6 +2593,40 @@ mod tests {
         assert_eq!(clip.pixel_height, Some(10));
     }
 
+    #[test]
+    fn stores_rich_text_flavors_beside_plain_content() {
+        let db = Database::new_in_memory().expect("db init");
+        let plain = db.insert_clip("plain", "text").expect("insert plain");
+        let rich = db
+            .insert_rich_text_clip(RichTextClipInsert {
+                content: "bold",
+                content_type: "text",
+                hash: "rich-hash",
+                html_content: Some("<b>bold</b>"),
+                rtf_content: None,
+                byte_size: 15,
+                source: "clipboard",
+                source_app: None,
+            })
+            .expect("insert rich");
+
+        assert!(rich.has_html);
+        assert!(!rich.has_rtf);
+        assert_eq!(rich.byte_size, 15);
+        assert_eq!(
+            db.get_rich_text(rich.id).expect("flavors"),
+            Some(RichTextFlavors {
+                html: Some("<b>bold</b>".to_string()),
+                rtf: None,
+            })
+        );
+        assert!(!plain.has_html && !plain.has_rtf);
+        assert_eq!(
+            db.get_rich_text(plain.id).expect("flavors"),
+            Some(RichTextFlavors::default())
+        );
+    }
+
     #[test]
     fn migrates_v1_schema_to_v2() {
         let db_path = env::temp_dir().join(format!("klippy-migrate-{}.sqlite3", Uuid::new_v4()));
@@ -722,17 +2668,112 @@ mod tests {
                 byte_size: 128,
                 pixel_width: 10,
                 pixel_height: 10,
+                source: "clipboard",
+                source_app: None,
             })
             .expect("insert image after migration");
 
         assert_eq!(inserted.content_type, "image");
-        let list = db.list_clips(None, 10, 0).expect("list migrated rows");
+        let list = db
+            .list_clips(ClipListRequest {
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("list migrated rows");
         assert!(list.items.iter().any(|clip| clip.content == "legacy text"));
+        assert!(list.items.iter().all(|clip| clip.source == "clipboard"));
         assert!(list.items.iter().any(|clip| clip.content_type == "image"));
+        let found = db
+            .list_clips(ClipListRequest {
+                query: Some("legacy"),
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("search migrated rows");
+        assert_eq!(found.total, Some(1));
 
         let settings = db.get_settings().expect("settings after migration");
         assert_eq!(settings.max_clip_bytes, 10_485_760);
 
         let _ = fs::remove_file(&db_path);
     }
+
+    #[test]
+    fn rebuilds_image_era_schema_for_file_lists_keeping_media() {
+        let mut conn = Connection::open_in_memory().expect("open");
+        conn.execute_batch(
+            r#"
+            CREATE TABLE clips (
+              id INTEGER PRIMARY KEY AUTOINCREMENT,
+              content TEXT NOT NULL CHECK (length(content) > 0),
+              content_type TEXT NOT NULL CHECK (content_type IN ('text', 'url', 'code', 'image')),
+              pinned INTEGER NOT NULL DEFAULT 0 CHECK (pinned IN (0, 1)),
+              hash TEXT NOT NULL,
+              media_path TEXT,
+              thumb_path TEXT,
+              mime_type TEXT,
+              byte_size INTEGER NOT NULL DEFAULT 0,
+              pixel_width INTEGER,
+              pixel_height INTEGER,
+              source TEXT NOT NULL DEFAULT 'clipboard' CHECK (source IN ('clipboard', 'primary')),
+              created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            );
+            INSERT INTO clips (content, content_type, pinned, hash, media_path, byte_size, source)
+            VALUES ('Image | PNG | 1x1 | 0.0 MB', 'image', 1, 'img', '/tmp/a.png', 64, 'primary');
+            "#,
+        )
+        .expect("seed image-era schema");
+        Database::initialize(&mut conn, None).expect("migrate");
+        let db = Database {
+            conn: Mutex::new(conn),
+            fuzzy_cache: Mutex::new(None),
+        };
+
+        let page = db
+            .list_clips(ClipListRequest {
+                limit: 10,
+                include_total: true,
+                ..Default::default()
+            })
+            .expect("list");
+        let image = &page.items[0];
+        assert!(image.pinned);
+        assert_eq!(image.media_path.as_deref(), Some("/tmp/a.png"));
+        assert_eq!(image.byte_size, 64);
+        assert_eq!(image.source, "primary");
+
+        let existing = env::temp_dir().to_string_lossy().into_owned();
+        let missing = format!("{existing}/klippy-missing-{}", Uuid::new_v4());
+        let files = [
+            ClipFile {
+                path: existing.clone(),
+                is_dir: true,
+                byte_size: None,
+            },
+            ClipFile {
+                path: missing.clone(),
+                is_dir: false,
+                byte_size: Some(12),
+            },
+        ];
+        let content = format!("{existing}\n{missing}");
+        let clip = db
+            .insert_file_list_clip(FileListClipInsert {
+                content: &content,
+                hash: "files-hash",
+                files: &files,
+                source: "clipboard",
+                source_app: None,
+            })
+            .expect("insert file list");
+
+        assert_eq!(clip.content_type, "files");
+        let stored = clip.files.expect("file entries");
+        assert_eq!(stored.len(), 2);
+        assert!(stored[0].is_dir);
+        assert_eq!(stored[1].path, missing);
+        assert_eq!(stored[1].byte_size, Some(12));
+    }
 }
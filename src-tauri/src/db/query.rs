@@ -0,0 +1,672 @@
+use rusqlite::types::Value;
+use thiserror::Error;
+
+const CONTENT_TYPES: [&str; 5] = ["text", "url", "code", "image", "files"];
+const SOURCES: [&str; 2] = ["clipboard", "primary"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QueryError {
+    #[error("unterminated quote in search query")]
+    UnterminatedQuote,
+    #[error("filter `{0}:` needs a value")]
+    MissingValue(String),
+    #[error("invalid value `{value}` for `{key}:`, expected {expected}")]
+    InvalidValue {
+        key: String,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    /// A bare word, matched as a word prefix.
+    Word(String),
+    /// A quoted phrase, matched as consecutive whole words.
+    Phrase(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn as_sql(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Predicate {
+    ContentType(Vec<String>),
+    Pinned(bool),
+    /// Carries the tag with this name, ignoring case.
+    Tag(String),
+    App(String),
+    Source(String),
+    /// Inclusive `YYYY-MM-DD` lower bound on `created_at`.
+    CreatedAfter(String),
+    /// Exclusive `YYYY-MM-DD` upper bound on `created_at`.
+    CreatedBefore(String),
+    ByteSize(Comparison, i64),
+    Width(Comparison, i64),
+    Height(Comparison, i64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub predicate: Predicate,
+    pub negated: bool,
+}
+
+/// A parsed `list_clips` query such as
+/// `type:url pinned:yes app:com.apple.Safari after:2026-10-01 "exact phrase" -draft`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClipQuery {
+    pub terms: Vec<Term>,
+    pub excluded: Vec<Term>,
+    pub filters: Vec<Filter>,
+}
+
+/// SQL fragments for a query. `match_expression` is an FTS5 expression over
+/// `clips_fts`; `conditions` are ANDed and bind `values` in order.
+#[derive(Debug, Default)]
+pub struct SqlQuery {
+    pub match_expression: Option<String>,
+    pub conditions: Vec<String>,
+    pub values: Vec<Value>,
+}
+
+impl ClipQuery {
+    pub fn parse(input: &str) -> Result<Self, QueryError> {
+        let mut query = Self::default();
+        for token in tokenize(input)? {
+            let filter = match token.filter() {
+                Some((key, value)) => Some(parse_predicate(key, value)?),
+                None => None,
+            };
+            match filter {
+                Some(predicate) => query.filters.push(Filter {
+                    predicate,
+                    negated: token.negated,
+                }),
+                None if token.negated => query.excluded.push(token.into_term()),
+                None => query.terms.push(token.into_term()),
+            }
+        }
+        Ok(query)
+    }
+
+    pub fn to_sql(&self) -> SqlQuery {
+        let mut sql = SqlQuery::default();
+        let mut match_terms = Vec::new();
+
+        for term in &self.terms {
+            match fts_term(term) {
+                Some(expression) => match_terms.push(expression),
+                None => {
+                    // Pure punctuation yields no FTS tokens, so match it literally.
+                    sql.conditions
+                        .push("LOWER(clips.content) LIKE ? ESCAPE '\\'".to_string());
+                    sql.values.push(like_pattern(term));
+                }
+            }
+        }
+        if !match_terms.is_empty() {
+            sql.match_expression = Some(match_terms.join(" "));
+        }
+
+        let filters = self.filter_sql();
+        sql.conditions.extend(filters.conditions);
+        sql.values.extend(filters.values);
+        sql
+    }
+
+    /// Exclusions and `key:value` filters only, for callers that match the
+    /// positive terms themselves.
+    pub fn filter_sql(&self) -> SqlQuery {
+        let mut sql = SqlQuery::default();
+        for term in &self.excluded {
+            match fts_term(term) {
+                Some(expression) => {
+                    sql.conditions.push(
+                        "clips.id NOT IN (SELECT rowid FROM clips_fts WHERE clips_fts MATCH ?)"
+                            .to_string(),
+                    );
+                    sql.values
+                        .push(Value::Text(expression.trim_end_matches('*').to_string()));
+                }
+                None => {
+                    sql.conditions
+                        .push("LOWER(clips.content) NOT LIKE ? ESCAPE '\\'".to_string());
+                    sql.values.push(like_pattern(term));
+                }
+            }
+        }
+        for filter in &self.filters {
+            let (condition, values) = predicate_sql(&filter.predicate);
+            // `IS NOT 1` also keeps rows where the column is NULL, e.g.
+            // `-width:>100` on text clips.
+            let condition = if filter.negated {
+                format!("({condition}) IS NOT 1")
+            } else {
+                condition
+            };
+            sql.conditions.push(condition);
+            sql.values.extend(values);
+        }
+        sql
+    }
+}
+
+/// The keys of `key:value` filters. Any other `word:value` is searched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilterKey {
+    Type,
+    Pinned,
+    Tag,
+    App,
+    Source,
+    After,
+    Before,
+    Size,
+    Width,
+    Height,
+}
+
+impl FilterKey {
+    const ALL: [Self; 10] = [
+        Self::Type,
+        Self::Pinned,
+        Self::Tag,
+        Self::App,
+        Self::Source,
+        Self::After,
+        Self::Before,
+        Self::Size,
+        Self::Width,
+        Self::Height,
+    ];
+
+    fn parse(key: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|known| known.name().eq_ignore_ascii_case(key))
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Type => "type",
+            Self::Pinned => "pinned",
+            Self::Tag => "tag",
+            Self::App => "app",
+            Self::Source => "source",
+            Self::After => "after",
+            Self::Before => "before",
+            Self::Size => "size",
+            Self::Width => "width",
+            Self::Height => "height",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Token {
+    text: String,
+    quoted: bool,
+    negated: bool,
+}
+
+impl Token {
+    /// Splits a `key:value` filter. Only the known filter keys count;
+    /// anything else with a colon (`https://…`, `12:30`, `localhost:3000`,
+    /// `TODO:fix`) stays a search word.
+    fn filter(&self) -> Option<(FilterKey, &str)> {
+        if self.quoted {
+            return None;
+        }
+        let (key, value) = self.text.split_once(':')?;
+        if value.starts_with('/') {
+            return None;
+        }
+        Some((FilterKey::parse(key)?, value))
+    }
+
+    fn into_term(self) -> Term {
+        if self.quoted {
+            Term::Phrase(self.text)
+        } else {
+            Term::Word(self.text)
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&ch) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut negated = false;
+        if ch == '-' {
+            chars.next();
+            match chars.peek() {
+                Some(next) if !next.is_whitespace() => negated = true,
+                _ => {
+                    tokens.push(Token {
+                        text: "-".to_string(),
+                        quoted: false,
+                        negated: false,
+                    });
+                    continue;
+                }
+            }
+        }
+
+        let mut text = String::new();
+        let mut quoted = false;
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            quoted = true;
+            read_quoted(&mut chars, &mut text)?;
+        } else {
+            while let Some(&next) = chars.peek() {
+                if next.is_whitespace() {
+                    break;
+                }
+                chars.next();
+                // `app:"Google Chrome"` quotes just the value.
+                if next == '"' && text.ends_with(':') {
+                    read_quoted(&mut chars, &mut text)?;
+                    break;
+                }
+                text.push(next);
+            }
+        }
+
+        if !text.is_empty() {
+            tokens.push(Token {
+                text,
+                quoted,
+                negated,
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+fn read_quoted(
+    chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+    text: &mut String,
+) -> Result<(), QueryError> {
+    for next in chars.by_ref() {
+        if next == '"' {
+            return Ok(());
+        }
+        text.push(next);
+    }
+    Err(QueryError::UnterminatedQuote)
+}
+
+fn parse_predicate(key: FilterKey, value: &str) -> Result<Predicate, QueryError> {
+    if value.is_empty() {
+        return Err(QueryError::MissingValue(key.name().to_string()));
+    }
+
+    let invalid = |expected| QueryError::InvalidValue {
+        key: key.name().to_string(),
+        value: value.to_string(),
+        expected,
+    };
+    let predicate = match key {
+        FilterKey::Type => {
+            let types = value
+                .split(',')
+                .map(|item| item.trim().to_ascii_lowercase())
+                .collect::<Vec<_>>();
+            if !types
+                .iter()
+                .all(|item| CONTENT_TYPES.contains(&item.as_str()))
+            {
+                return Err(invalid("text, url, code, image or files"));
+            }
+            Predicate::ContentType(types)
+        }
+        FilterKey::Pinned => match value.to_ascii_lowercase().as_str() {
+            "yes" | "true" | "1" => Predicate::Pinned(true),
+            "no" | "false" | "0" => Predicate::Pinned(false),
+            _ => return Err(invalid("yes or no")),
+        },
+        FilterKey::Tag => Predicate::Tag(value.to_string()),
+        FilterKey::App => Predicate::App(value.to_string()),
+        FilterKey::Source => {
+            let source = value.to_ascii_lowercase();
+            if !SOURCES.contains(&source.as_str()) {
+                return Err(invalid("clipboard or primary"));
+            }
+            Predicate::Source(source)
+        }
+        FilterKey::After => {
+            Predicate::CreatedAfter(parse_date(value).ok_or_else(|| invalid("YYYY-MM-DD"))?)
+        }
+        FilterKey::Before => {
+            Predicate::CreatedBefore(parse_date(value).ok_or_else(|| invalid("YYYY-MM-DD"))?)
+        }
+        FilterKey::Size => {
+            let (comparison, amount) = split_comparison(value);
+            let bytes =
+                parse_byte_size(amount).ok_or_else(|| invalid("a size like >1mb or <=500kb"))?;
+            Predicate::ByteSize(comparison, bytes)
+        }
+        FilterKey::Width | FilterKey::Height => {
+            let (comparison, amount) = split_comparison(value);
+            let pixels = amount
+                .strip_suffix("px")
+                .unwrap_or(amount)
+                .parse::<i64>()
+                .ok()
+                .filter(|pixels| *pixels >= 0)
+                .ok_or_else(|| invalid("a pixel count like >1920 or <=600"))?;
+            if key == FilterKey::Width {
+                Predicate::Width(comparison, pixels)
+            } else {
+                Predicate::Height(comparison, pixels)
+            }
+        }
+    };
+    Ok(predicate)
+}
+
+fn split_comparison(value: &str) -> (Comparison, &str) {
+    for (prefix, comparison) in [
+        (">=", Comparison::Ge),
+        ("<=", Comparison::Le),
+        (">", Comparison::Gt),
+        ("<", Comparison::Lt),
+        ("=", Comparison::Eq),
+    ] {
+        if let Some(rest) = value.strip_prefix(prefix) {
+            return (comparison, rest);
+        }
+    }
+    (Comparison::Eq, value)
+}
+
+fn parse_byte_size(value: &str) -> Option<i64> {
+    let lower = value.to_ascii_lowercase();
+    let split = lower
+        .find(|ch: char| !ch.is_ascii_digit() && ch != '.')
+        .unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(split);
+    let multiplier = match unit {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1024.0,
+        "m" | "mb" => 1024.0 * 1024.0,
+        "g" | "gb" => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    let number = number.parse::<f64>().ok().filter(|n| n.is_finite())?;
+    Some((number * multiplier).round() as i64)
+}
+
+fn parse_date(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '-');
+    let mut field = |digits: usize| {
+        parts
+            .next()
+            .filter(|part| part.len() == digits && part.bytes().all(|b| b.is_ascii_digit()))?
+            .parse::<u32>()
+            .ok()
+    };
+    let (year, month, day) = (field(4)?, field(2)?, field(2)?);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+    (1..=days_in_month)
+        .contains(&day)
+        .then(|| format!("{year:04}-{month:02}-{day:02}"))
+}
+
+fn predicate_sql(predicate: &Predicate) -> (String, Vec<Value>) {
+    match predicate {
+        Predicate::ContentType(types) => (
+            format!(
+                "clips.content_type IN ({})",
+                vec!["?"; types.len()].join(", ")
+            ),
+            types.iter().cloned().map(Value::Text).collect(),
+        ),
+        Predicate::Pinned(pinned) => (
+            "clips.pinned = ?".to_string(),
+            vec![Value::Integer(i64::from(*pinned))],
+        ),
+        Predicate::Tag(name) => (
+            "EXISTS (
+                SELECT 1 FROM clip_tags JOIN tags ON tags.id = clip_tags.tag_id
+                WHERE clip_tags.clip_id = clips.id AND tags.name = ?
+            )"
+            .to_string(),
+            vec![Value::Text(name.clone())],
+        ),
+        Predicate::App(app) => (
+            "clips.source_app = ? COLLATE NOCASE".to_string(),
+            vec![Value::Text(app.clone())],
+        ),
+        Predicate::Source(source) => (
+            "clips.source = ?".to_string(),
+            vec![Value::Text(source.clone())],
+        ),
+        Predicate::CreatedAfter(date) => (
+            "clips.created_at >= ?".to_string(),
+            vec![Value::Text(date.clone())],
+        ),
+        Predicate::CreatedBefore(date) => (
+            "clips.created_at < ?".to_string(),
+            vec![Value::Text(date.clone())],
+        ),
+        Predicate::ByteSize(comparison, bytes) => (
+            format!("clips.byte_size {} ?", comparison.as_sql()),
+            vec![Value::Integer(*bytes)],
+        ),
+        Predicate::Width(comparison, pixels) => (
+            format!("clips.pixel_width {} ?", comparison.as_sql()),
+            vec![Value::Integer(*pixels)],
+        ),
+        Predicate::Height(comparison, pixels) => (
+            format!("clips.pixel_height {} ?", comparison.as_sql()),
+            vec![Value::Integer(*pixels)],
+        ),
+    }
+}
+
+/// Quotes a term for FTS5 so operators typed by the user are searched for
+/// literally. Words match as prefixes so results update while typing.
+fn fts_term(term: &Term) -> Option<String> {
+    let (text, suffix) = match term {
+        Term::Word(word) => (word, "*"),
+        Term::Phrase(phrase) => (phrase, ""),
+    };
+    text.chars()
+        .any(char::is_alphanumeric)
+        .then(|| format!("\"{}\"{suffix}", text.replace('"', "\"\"")))
+}
+
+/// A `LIKE ... ESCAPE '\'` pattern matching `term` anywhere, with `%` and
+/// `_` in it taken literally.
+fn like_pattern(term: &Term) -> Value {
+    let (Term::Word(text) | Term::Phrase(text)) = term;
+    let mut pattern = String::from("%");
+    for ch in text.to_lowercase().chars() {
+        if matches!(ch, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(ch);
+    }
+    pattern.push('%');
+    Value::Text(pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_filters_phrases_and_exclusions() {
+        let query = ClipQuery::parse(
+            r#"type:url pinned:yes app:com.apple.Safari after:2026-10-01 "exact phrase" -draft"#,
+        )
+        .expect("parse");
+
+        assert_eq!(query.terms, vec![Term::Phrase("exact phrase".to_string())]);
+        assert_eq!(query.excluded, vec![Term::Word("draft".to_string())]);
+        let predicates = query
+            .filters
+            .iter()
+            .map(|filter| filter.predicate.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            predicates,
+            vec![
+                Predicate::ContentType(vec!["url".to_string()]),
+                Predicate::Pinned(true),
+                Predicate::App("com.apple.Safari".to_string()),
+                Predicate::CreatedAfter("2026-10-01".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_comparisons_units_and_quoted_values() {
+        let query =
+            ClipQuery::parse(r#"size:>1.5mb width:>=1920px -height:<100 app:"Google Chrome""#)
+                .expect("parse");
+        assert_eq!(
+            query.filters,
+            vec![
+                Filter {
+                    predicate: Predicate::ByteSize(Comparison::Gt, 1_572_864),
+                    negated: false,
+                },
+                Filter {
+                    predicate: Predicate::Width(Comparison::Ge, 1920),
+                    negated: false,
+                },
+                Filter {
+                    predicate: Predicate::Height(Comparison::Lt, 100),
+                    negated: true,
+                },
+                Filter {
+                    predicate: Predicate::App("Google Chrome".to_string()),
+                    negated: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn colons_that_are_not_filters_stay_search_words() {
+        let query = ClipQuery::parse(
+            "https://example.com 12:30 note: localhost:3000 TODO:fix colour:red -error:ENOENT",
+        )
+        .expect("parse");
+        assert!(query.filters.is_empty());
+        assert_eq!(
+            query.terms,
+            vec![
+                Term::Word("https://example.com".to_string()),
+                Term::Word("12:30".to_string()),
+                Term::Word("note:".to_string()),
+                Term::Word("localhost:3000".to_string()),
+                Term::Word("TODO:fix".to_string()),
+                Term::Word("colour:red".to_string()),
+            ]
+        );
+        assert_eq!(query.excluded, vec![Term::Word("error:ENOENT".to_string())]);
+    }
+
+    #[test]
+    fn rejects_invalid_queries_with_clear_errors() {
+        assert_eq!(
+            ClipQuery::parse("type:"),
+            Err(QueryError::MissingValue("type".to_string()))
+        );
+        assert_eq!(
+            ClipQuery::parse("\"open phrase"),
+            Err(QueryError::UnterminatedQuote)
+        );
+        for (input, expected) in [
+            ("type:video", "text, url, code, image or files"),
+            ("pinned:maybe", "yes or no"),
+            ("after:2026-02-30", "YYYY-MM-DD"),
+            ("before:yesterday", "YYYY-MM-DD"),
+            ("size:>lots", "a size like >1mb or <=500kb"),
+            ("width:-5", "a pixel count like >1920 or <=600"),
+        ] {
+            match ClipQuery::parse(input) {
+                Err(QueryError::InvalidValue { expected: got, .. }) => {
+                    assert_eq!(got, expected, "{input}")
+                }
+                other => panic!("{input} parsed as {other:?}"),
+            }
+        }
+        assert_eq!(
+            ClipQuery::parse("after:2026-02-30")
+                .unwrap_err()
+                .to_string(),
+            "invalid value `2026-02-30` for `after:`, expected YYYY-MM-DD"
+        );
+    }
+
+    #[test]
+    fn builds_fts_expression_and_conditions() {
+        let sql = ClipQuery::parse(r#"kube "exact phrase" -draft -{ pinned:no {"#)
+            .expect("parse")
+            .to_sql();
+        assert_eq!(
+            sql.match_expression.as_deref(),
+            Some(r#""kube"* "exact phrase""#)
+        );
+        assert_eq!(
+            sql.conditions,
+            vec![
+                "LOWER(clips.content) LIKE ? ESCAPE '\\'",
+                "clips.id NOT IN (SELECT rowid FROM clips_fts WHERE clips_fts MATCH ?)",
+                "LOWER(clips.content) NOT LIKE ? ESCAPE '\\'",
+                "clips.pinned = ?",
+            ]
+        );
+        assert_eq!(sql.values[1], Value::Text("\"draft\"".to_string()));
+    }
+
+    #[test]
+    fn like_patterns_escape_wildcards() {
+        let sql = ClipQuery::parse(r"% -_ \").expect("parse").to_sql();
+        assert_eq!(
+            sql.values,
+            vec![
+                Value::Text(r"%\%%".to_string()),
+                Value::Text(r"%\\%".to_string()),
+                Value::Text(r"%\_%".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,252 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::{Clip, Database, DbError};
+
+const MAX_TAG_NAME_CHARS: usize = 64;
+
+/// Clips carrying a tag marked `protected` are kept by pruning the same way
+/// pinned clips are.
+pub const UNPROTECTED_CLIP: &str = "
+    clips.pinned = 0
+    AND NOT EXISTS (
+        SELECT 1 FROM clip_tags JOIN tags ON tags.id = clip_tags.tag_id
+        WHERE clip_tags.clip_id = clips.id AND tags.protected = 1
+    )
+";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    /// Pruning never removes clips with a protected tag.
+    pub protected: bool,
+    pub clip_count: i64,
+}
+
+/// A tag as listed on a clip.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipTag {
+    pub id: i64,
+    pub name: String,
+}
+
+impl Database {
+    pub fn list_tags(&self) -> Result<Vec<Tag>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!("{TAG_SELECT} ORDER BY tags.name"))?;
+        let rows = stmt.query_map([], tag_from_row)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(DbError::from)
+    }
+
+    pub fn create_tag(&self, name: &str, protected: bool) -> Result<Tag, DbError> {
+        let conn = self.conn()?;
+        let name = checked_name(&conn, name, None)?;
+        conn.execute(
+            "INSERT INTO tags (name, protected) VALUES (?1, ?2)",
+            params![name, protected],
+        )?;
+        Ok(read_tag(&conn, conn.last_insert_rowid())?)
+    }
+
+    pub fn rename_tag(&self, id: i64, name: &str) -> Result<Option<Tag>, DbError> {
+        let conn = self.conn()?;
+        let name = checked_name(&conn, name, Some(id))?;
+        conn.execute("UPDATE tags SET name = ?1 WHERE id = ?2", params![name, id])?;
+        read_tag(&conn, id).optional().map_err(DbError::from)
+    }
+
+    pub fn set_tag_protected(&self, id: i64, protected: bool) -> Result<Option<Tag>, DbError> {
+        let conn = self.conn()?;
+        conn.execute(
+            "UPDATE tags SET protected = ?1 WHERE id = ?2",
+            params![protected, id],
+        )?;
+        read_tag(&conn, id).optional().map_err(DbError::from)
+    }
+
+    /// Deletes a tag and unassigns it from every clip; the clips stay.
+    pub fn delete_tag(&self, id: i64) -> Result<Option<Tag>, DbError> {
+        let conn = self.conn()?;
+        let tag = read_tag(&conn, id).optional()?;
+        if tag.is_some() {
+            conn.execute("DELETE FROM tags WHERE id = ?1", params![id])?;
+        }
+        Ok(tag)
+    }
+
+    /// Assigns a tag to a clip; assigning it twice is a no-op. `None` when
+    /// either doesn't exist.
+    pub fn tag_clip(&self, clip_id: i64, tag_id: i64) -> Result<Option<Clip>, DbError> {
+        let conn = self.conn()?;
+        if read_tag(&conn, tag_id).optional()?.is_none() {
+            return Ok(None);
+        }
+        let Some(clip) = self.get_clip_internal(&conn, clip_id).optional()? else {
+            return Ok(None);
+        };
+        conn.execute(
+            "INSERT OR IGNORE INTO clip_tags (clip_id, tag_id) VALUES (?1, ?2)",
+            params![clip.id, tag_id],
+        )?;
+        Ok(Some(self.get_clip_internal(&conn, clip_id)?))
+    }
+
+    pub fn untag_clip(&self, clip_id: i64, tag_id: i64) -> Result<Option<Clip>, DbError> {
+        let conn = self.conn()?;
+        conn.execute(
+            "DELETE FROM clip_tags WHERE clip_id = ?1 AND tag_id = ?2",
+            params![clip_id, tag_id],
+        )?;
+        self.get_clip_internal(&conn, clip_id)
+            .optional()
+            .map_err(DbError::from)
+    }
+}
+
+const TAG_SELECT: &str = "
+    SELECT tags.id, tags.name, tags.protected,
+           (SELECT COUNT(*) FROM clip_tags WHERE clip_tags.tag_id = tags.id)
+    FROM tags
+";
+
+fn read_tag(conn: &Connection, id: i64) -> Result<Tag, rusqlite::Error> {
+    conn.query_row(
+        &format!("{TAG_SELECT} WHERE tags.id = ?1"),
+        params![id],
+        tag_from_row,
+    )
+}
+
+fn tag_from_row(row: &rusqlite::Row<'_>) -> Result<Tag, rusqlite::Error> {
+    Ok(Tag {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        protected: row.get(2)?,
+        clip_count: row.get(3)?,
+    })
+}
+
+/// Trims `name` and rejects blanks, overlong names and names another tag
+/// already has, ignoring case.
+fn checked_name(conn: &Connection, name: &str, own_id: Option<i64>) -> Result<String, DbError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(DbError::InvalidTag("name can't be empty".to_string()));
+    }
+    if name.chars().count() > MAX_TAG_NAME_CHARS {
+        return Err(DbError::InvalidTag(format!(
+            "names are limited to {MAX_TAG_NAME_CHARS} characters"
+        )));
+    }
+    let taken: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM tags WHERE name = ?1 AND id IS NOT ?2",
+            params![name, own_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if taken.is_some() {
+        return Err(DbError::InvalidTag(format!(
+            "a tag named `{name}` already exists"
+        )));
+    }
+    Ok(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::ClipListRequest;
+
+    fn tag_names(clip: &Clip) -> Vec<&str> {
+        clip.tags.iter().map(|tag| tag.name.as_str()).collect()
+    }
+
+    #[test]
+    fn creates_renames_and_deletes_tags() {
+        let db = Database::new_in_memory().expect("db init");
+        let work = db.create_tag("  work ", false).expect("create work");
+        assert_eq!(work.name, "work");
+        assert!(matches!(
+            db.create_tag("Work", false),
+            Err(DbError::InvalidTag(_))
+        ));
+        assert!(matches!(
+            db.create_tag(" ", false),
+            Err(DbError::InvalidTag(_))
+        ));
+
+        let renamed = db
+            .rename_tag(work.id, "Work")
+            .expect("rename")
+            .expect("tag");
+        assert_eq!(renamed.name, "Work");
+        let keep = db.create_tag("keep", true).expect("create keep");
+        assert!(matches!(
+            db.rename_tag(keep.id, "work"),
+            Err(DbError::InvalidTag(_))
+        ));
+
+        let clip = db.insert_clip("snippet", "text").expect("insert");
+        db.tag_clip(clip.id, work.id).expect("tag").expect("clip");
+        let clip = db.tag_clip(clip.id, keep.id).expect("tag").expect("clip");
+        assert_eq!(tag_names(&clip), vec!["keep", "Work"]);
+        assert_eq!(db.tag_clip(clip.id, 999).expect("tag missing"), None);
+
+        assert_eq!(
+            db.delete_tag(work.id).expect("delete").map(|t| t.id),
+            Some(work.id)
+        );
+        assert_eq!(db.delete_tag(work.id).expect("delete again"), None);
+        let clip = db.get_clip(clip.id).expect("get").expect("clip");
+        assert_eq!(tag_names(&clip), vec!["keep"]);
+        let tags = db.list_tags().expect("list tags");
+        assert_eq!(tags.len(), 1);
+        assert_eq!((tags[0].protected, tags[0].clip_count), (true, 1));
+
+        let clip = db
+            .untag_clip(clip.id, keep.id)
+            .expect("untag")
+            .expect("clip");
+        assert!(clip.tags.is_empty());
+    }
+
+    #[test]
+    fn filters_by_tag_and_prunes_around_protected_tags() {
+        let db = Database::new_in_memory().expect("db init");
+        let keep = db.create_tag("keep", true).expect("create keep");
+        let later = db.create_tag("read later", false).expect("create later");
+        let kept = db.insert_clip("kept", "text").expect("insert kept");
+        let tagged = db.insert_clip("tagged", "text").expect("insert tagged");
+        db.insert_clip("plain", "text").expect("insert plain");
+        db.tag_clip(kept.id, keep.id).expect("tag kept");
+        db.tag_clip(tagged.id, later.id).expect("tag tagged");
+
+        let list = |query| {
+            db.list_clips(ClipListRequest {
+                query: Some(query),
+                limit: 10,
+                ..Default::default()
+            })
+            .expect("list")
+            .items
+            .into_iter()
+            .map(|clip| clip.content)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(list(r#"tag:"Read Later""#), vec!["tagged"]);
+        assert_eq!(list("-tag:keep"), vec!["plain", "tagged"]);
+
+        let protected = db
+            .retention_candidates()
+            .expect("candidates")
+            .into_iter()
+            .filter(|clip| clip.protected)
+            .map(|clip| clip.id)
+            .collect::<Vec<_>>();
+        assert_eq!(protected, vec![kept.id]);
+    }
+}
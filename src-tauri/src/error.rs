@@ -6,6 +6,8 @@ pub enum AppError {
     Db(#[from] crate::db::DbError),
     #[error("clipboard error: {0}")]
     Clipboard(#[from] crate::clipboard::ClipboardError),
+    #[error("encryption error: {0}")]
+    Crypto(#[from] crate::utils::crypto::CryptoError),
     #[error("not found")]
     NotFound,
     #[error("internal error: {0}")]
@@ -5,19 +5,53 @@ mod error;
 mod services;
 mod utils;
 
+/// The event surface for embedding the engine without a webview.
+pub use services::events::{ClipEvent, EventSink, RecordingEventSink};
+
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-
-use commands::AppState;
-use services::clip_engine::ClipEngine;
-use services::media_store::MediaStore;
-use tauri::{AppHandle, LogicalPosition, LogicalSize, Manager, WebviewWindow, WindowEvent};
+use std::time::Duration;
+
+use commands::{AppState, HistoryGate, HistoryState};
+use db::Settings;
+use error::AppError;
+use services::clip_engine::{ClipEngine, EngineConfig};
+use services::events::TauriEventSink;
+use services::media_store::{mime_type_for_path, path_from_uri, MediaStore};
+use services::pause::{is_paused, now_millis, PauseDuration};
+use tauri::http::header::{self, HeaderValue};
+use tauri::http::{Response, StatusCode};
+use tauri::image::Image;
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
+use tauri::{
+    AppHandle, Emitter, Listener, LogicalPosition, LogicalSize, Manager, WebviewWindow, WindowEvent,
+};
 use tauri_plugin_autostart::{MacosLauncher, ManagerExt as AutostartManagerExt};
 use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Modifiers, Shortcut, ShortcutState};
 use tracing::level_filters::LevelFilter;
-use tracing::warn;
+use tracing::{error, info, warn};
+use utils::crypto::{Cipher, CryptoError};
 
 const FIXED_WINDOW_WIDTH: f64 = 560.0;
+/// Time for focus to return to the target app before pasting into it.
+const PASTE_DELAY: Duration = Duration::from_millis(250);
+const TRAY_ID: &str = "klippy-tray";
+/// Serves stored images to the webview, decrypting them when encryption at
+/// rest is on. The frontend builds its URLs with `convertFileSrc`.
+const MEDIA_SCHEME: &str = "media";
+/// Tells the webview the history can be listed, after startup or unlock.
+const HISTORY_OPENED_EVENT: &str = "history://opened";
+const MENU_PAUSE_FIVE_MINUTES: &str = "pause-five-minutes";
+const MENU_PAUSE_ONE_HOUR: &str = "pause-one-hour";
+const MENU_PAUSE_UNTIL_RESUMED: &str = "pause-until-resumed";
+const MENU_RESUME: &str = "resume";
+const MENU_QUIT: &str = "quit";
+
+struct TrayIcons {
+    active: Image<'static>,
+    paused: Image<'static>,
+}
 
 #[derive(Default)]
 struct WindowPlacementState {
@@ -76,6 +110,11 @@ fn toggle_window(app: &AppHandle, window: &WebviewWindow) {
         return;
     }
 
+    // Still frontmost at this point, so this is where a paste goes back to.
+    if let Some(state) = app.try_state::<AppState>() {
+        state.engine.remember_previous_app();
+    }
+
     let placement = app.state::<WindowPlacementState>();
     place_window_top_right(window, placement.inner());
 
@@ -88,6 +127,297 @@ fn should_hide_on_focus_loss(is_visible: bool, is_minimized: bool) -> bool {
     is_visible && !is_minimized
 }
 
+fn window_shortcut() -> Shortcut {
+    Shortcut::new(Some(Modifiers::SUPER | Modifiers::SHIFT), Code::KeyV)
+}
+
+/// Control + Option + Cmd + P (Ctrl + Alt + Super + P elsewhere). Cmd +
+/// Shift + P belongs to editor command palettes and private browser windows,
+/// and a global shortcut would take it from them everywhere.
+fn pause_shortcut() -> Shortcut {
+    Shortcut::new(
+        Some(Modifiers::CONTROL | Modifiers::ALT | Modifiers::SUPER),
+        Code::KeyP,
+    )
+}
+
+fn toggle_tracking(app: &AppHandle) {
+    let Some(state) = app.try_state::<AppState>() else {
+        return;
+    };
+    let result = state
+        .engine
+        .db()
+        .get_settings()
+        .map_err(error::AppError::from)
+        .and_then(|settings| {
+            if is_paused(&settings, now_millis()) {
+                state.engine.resume()
+            } else {
+                state.engine.pause(PauseDuration::UntilResumed)
+            }
+        });
+    if let Err(err) = result {
+        warn!("failed to toggle capture: {err}");
+    }
+}
+
+fn handle_tray_menu(app: &AppHandle, id: &str) {
+    let Some(state) = app.try_state::<AppState>() else {
+        return;
+    };
+    let result = match id {
+        MENU_PAUSE_FIVE_MINUTES => state.engine.pause(PauseDuration::FiveMinutes),
+        MENU_PAUSE_ONE_HOUR => state.engine.pause(PauseDuration::OneHour),
+        MENU_PAUSE_UNTIL_RESUMED => state.engine.pause(PauseDuration::UntilResumed),
+        MENU_RESUME => state.engine.resume(),
+        MENU_QUIT => {
+            app.exit(0);
+            return;
+        }
+        _ => return,
+    };
+    if let Err(err) = result {
+        warn!("tray action {id} failed: {err}");
+    }
+}
+
+fn build_tray_menu(app: &AppHandle, paused: bool) -> tauri::Result<Menu<tauri::Wry>> {
+    let pause_five_minutes = MenuItem::with_id(
+        app,
+        MENU_PAUSE_FIVE_MINUTES,
+        "Pause for 5 Minutes",
+        !paused,
+        None::<&str>,
+    )?;
+    let pause_one_hour = MenuItem::with_id(
+        app,
+        MENU_PAUSE_ONE_HOUR,
+        "Pause for 1 Hour",
+        !paused,
+        None::<&str>,
+    )?;
+    let pause_until_resumed = MenuItem::with_id(
+        app,
+        MENU_PAUSE_UNTIL_RESUMED,
+        "Pause Until Resumed",
+        !paused,
+        None::<&str>,
+    )?;
+    let resume = MenuItem::with_id(app, MENU_RESUME, "Resume Capture", paused, None::<&str>)?;
+    let separator = PredefinedMenuItem::separator(app)?;
+    let quit = MenuItem::with_id(app, MENU_QUIT, "Quit Klippy", true, None::<&str>)?;
+    Menu::with_items(
+        app,
+        &[
+            &pause_five_minutes,
+            &pause_one_hour,
+            &pause_until_resumed,
+            &resume,
+            &separator,
+            &quit,
+        ],
+    )
+}
+
+/// Swaps the tray icon, tooltip and menu to match whether capture is paused.
+fn show_tracking_state(app: &AppHandle, settings: &Settings) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let paused = is_paused(settings, now_millis());
+
+    if let Some(icons) = app.try_state::<TrayIcons>() {
+        let icon = if paused {
+            icons.paused.clone()
+        } else {
+            icons.active.clone()
+        };
+        if let Err(err) = tray.set_icon(Some(icon)) {
+            warn!("failed to update tray icon: {err}");
+        }
+    }
+    let tooltip = match (paused, settings.tracking_paused_until) {
+        (false, _) => "Klippy",
+        (true, Some(_)) => "Klippy (capture paused for a while)",
+        (true, None) => "Klippy (capture paused)",
+    };
+    if let Err(err) = tray.set_tooltip(Some(tooltip)) {
+        warn!("failed to update tray tooltip: {err}");
+    }
+    match build_tray_menu(app, paused) {
+        Ok(menu) => {
+            if let Err(err) = tray.set_menu(Some(menu)) {
+                warn!("failed to update tray menu: {err}");
+            }
+        }
+        Err(err) => warn!("failed to build tray menu: {err}"),
+    }
+}
+
+/// Greys out and fades RGBA pixels, for the paused tray icon.
+fn dim_rgba(rgba: &mut [u8]) {
+    for pixel in rgba.chunks_exact_mut(4) {
+        let luma =
+            (u32::from(pixel[0]) * 30 + u32::from(pixel[1]) * 59 + u32::from(pixel[2]) * 11) / 100;
+        pixel[0] = luma as u8;
+        pixel[1] = luma as u8;
+        pixel[2] = luma as u8;
+        pixel[3] = (u32::from(pixel[3]) * 2 / 5) as u8;
+    }
+}
+
+/// Answers a media protocol request with a stored image, refusing any path
+/// outside the media store.
+fn media_response(app: &AppHandle, uri_path: &str) -> Response<Vec<u8>> {
+    let respond = |status: StatusCode, content_type: &'static str, body: Vec<u8>| {
+        let mut response = Response::new(body);
+        *response.status_mut() = status;
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        // Decrypted images must not land in the webview's disk cache.
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        response
+    };
+    let Some(state) = app.try_state::<AppState>() else {
+        return respond(StatusCode::SERVICE_UNAVAILABLE, "text/plain", Vec::new());
+    };
+    let Some(path) = path_from_uri(uri_path).filter(|path| state.media_store.contains(path)) else {
+        return respond(StatusCode::FORBIDDEN, "text/plain", Vec::new());
+    };
+    match state.media_store.read(&path.to_string_lossy()) {
+        Ok(bytes) => respond(StatusCode::OK, mime_type_for_path(&path), bytes),
+        Err(err) => {
+            warn!("failed to serve media {}: {err}", path.display());
+            respond(StatusCode::NOT_FOUND, "text/plain", Vec::new())
+        }
+    }
+}
+
+/// Opens the history, starts capturing into it and hands it to the
+/// commands. Runs once per launch: in the background from setup, or from
+/// `unlock_history` when the key waits on a passphrase.
+fn open_history(
+    app: &AppHandle,
+    app_data_dir: PathBuf,
+    cipher: Option<Cipher>,
+) -> Result<(), String> {
+    let key_dir = key_dir(app).map_err(|err| err.to_string())?;
+    let cipher = cipher.map(Arc::new);
+    let db_path = app_data_dir.join("klippy.sqlite3");
+    let db = match &cipher {
+        Some(cipher) => db::Database::new_encrypted(&db_path, cipher),
+        None => db::Database::new(&db_path),
+    };
+    let db = Arc::new(db.map_err(|err| err.to_string())?);
+    let mut media_store =
+        MediaStore::new(&app_data_dir.join("media")).map_err(|err| err.to_string())?;
+    if let Some(cipher) = cipher {
+        media_store = media_store.with_cipher(cipher);
+        services::encryption::encrypt_existing_media(&db, &media_store)
+            .map_err(|err| err.to_string())?;
+    }
+    let media_store = Arc::new(media_store);
+    let referenced = db.referenced_media_paths().map_err(|err| err.to_string())?;
+    media_store
+        .cleanup_orphans(&referenced)
+        .map_err(|err| err.to_string())?;
+    let app_bundle_id = app.config().identifier.clone();
+    let clipboard = clipboard::default_service(&app_bundle_id);
+    let engine = Arc::new(ClipEngine::new(
+        db.clone(),
+        clipboard,
+        media_store.clone(),
+        Arc::new(TauriEventSink::new(app.clone())),
+        EngineConfig {
+            app_bundle_id,
+            paste_delay: PASTE_DELAY,
+        },
+    ));
+    engine.start().map_err(|err| err.to_string())?;
+    tauri::async_runtime::spawn(services::reaper::run_reaper(Arc::downgrade(&engine)));
+
+    let settings = db.get_settings().map_err(|err| err.to_string())?;
+    engine
+        .enforce_retention(&settings)
+        .map_err(|err| err.to_string())?;
+    let image_reconcile_marker = app_data_dir.join(".image-reconcile-v2.done");
+    if !image_reconcile_marker.exists() {
+        match engine.reconcile_recent_image_duplicates(500) {
+            Ok(count) => {
+                if let Err(err) = std::fs::write(&image_reconcile_marker, count.to_string()) {
+                    warn!("failed to persist image reconciliation marker: {err}");
+                }
+            }
+            Err(err) => warn!("failed to reconcile image duplicates: {err}"),
+        }
+    }
+
+    app.manage(AppState {
+        engine,
+        media_store,
+        app_data_dir,
+        key_dir,
+    });
+    show_tracking_state(app, &settings);
+    *app.state::<HistoryGate>().lock() = HistoryState::Open;
+    if let Err(err) = app.emit(HISTORY_OPENED_EVENT, ()) {
+        warn!("failed to emit {HISTORY_OPENED_EVENT}: {err}");
+    }
+    Ok(())
+}
+
+/// Opens the history on a blocking thread, so the one-time encryption of an
+/// existing history never holds up the event loop. Without a history there
+/// is nothing to run, so a failure ends the app as a failed setup would.
+fn open_history_in_background(app: &AppHandle, app_data_dir: PathBuf, cipher: Option<Cipher>) {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(err) = open_history(&app, app_data_dir, cipher) {
+            error!("failed to open the history: {err}");
+            app.exit(1);
+        }
+    });
+}
+
+/// Where generated key files go on systems without a keychain: apart from
+/// the history, so copying or syncing it leaves the key behind. That is the
+/// config directory on Linux and the local, non-roaming app data directory
+/// on Windows.
+fn key_dir(app: &AppHandle) -> tauri::Result<PathBuf> {
+    if cfg!(windows) {
+        app.path().app_local_data_dir()
+    } else {
+        app.path().app_config_dir()
+    }
+}
+
+/// Opens a history whose key is derived from a passphrase, once the user has
+/// entered it. Does nothing unless the history is locked.
+fn unlock_history(app: &AppHandle, passphrase: &str) -> Result<(), String> {
+    let gate = app.state::<HistoryGate>();
+    {
+        let mut state = gate.lock();
+        if *state != HistoryState::Locked {
+            return Ok(());
+        }
+        *state = HistoryState::Opening;
+    }
+    let result = app
+        .path()
+        .app_data_dir()
+        .map_err(|err| err.to_string())
+        .and_then(|app_data_dir| {
+            let cipher = services::encryption::load_cipher(&app_data_dir, Some(passphrase))
+                .map_err(|err| err.to_string())?;
+            open_history(app, app_data_dir, cipher)
+        });
+    if result.is_err() {
+        *gate.lock() = HistoryState::Locked;
+    }
+    result
+}
+
 pub fn run() {
     tracing_subscriber::fmt()
         .with_max_level(LevelFilter::INFO)
@@ -95,6 +425,11 @@ pub fn run() {
         .compact()
         .init();
 
+    // Taken before the builder starts any runtime threads, while changing the
+    // environment is still safe, and kept from anything spawned later.
+    let passphrase = std::env::var(services::encryption::PASSPHRASE_ENV).ok();
+    std::env::remove_var(services::encryption::PASSPHRASE_ENV);
+
     let app_builder = tauri::Builder::default()
         .plugin(tauri_plugin_autostart::init(
             MacosLauncher::LaunchAgent,
@@ -102,21 +437,30 @@ pub fn run() {
         ))
         .plugin(
             tauri_plugin_global_shortcut::Builder::new()
-                .with_handler(|app, _shortcut, event| {
+                .with_handler(|app, shortcut, event| {
                     if event.state != ShortcutState::Pressed {
                         return;
                     }
-                    if let Some(window) = app.get_webview_window("main") {
+                    if *shortcut == pause_shortcut() {
+                        toggle_tracking(app);
+                    } else if let Some(window) = app.get_webview_window("main") {
                         toggle_window(app, &window);
                     }
                 })
                 .build(),
         )
-        .setup(|app| {
+        .register_asynchronous_uri_scheme_protocol(MEDIA_SCHEME, |ctx, request, responder| {
+            let app = ctx.app_handle().clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                responder.respond(media_response(&app, request.uri().path()));
+            });
+        })
+        .setup(move |app| {
             #[cfg(target_os = "macos")]
             app.set_activation_policy(tauri::ActivationPolicy::Accessory);
 
             app.manage(WindowPlacementState::default());
+            app.manage(HistoryGate::new(HistoryState::Opening));
 
             if let Some(window) = app.get_webview_window("main") {
                 let _ = window.set_minimizable(false);
@@ -162,55 +506,29 @@ pub fn run() {
             std::fs::create_dir_all(&app_data_dir)
                 .map_err(|err| crate::error::AppError::Internal(err.to_string()).to_string())?;
 
-            let db_path = app_data_dir.join("klippy.sqlite3");
-            let db = Arc::new(db::Database::new(&db_path).map_err(|err| err.to_string())?);
-            let media_store = Arc::new(
-                MediaStore::new(&app_data_dir.join("media")).map_err(|err| err.to_string())?,
-            );
-            let referenced = db.referenced_media_paths().map_err(|err| err.to_string())?;
-            media_store
-                .cleanup_orphans(&referenced)
-                .map_err(|err| err.to_string())?;
-            let clipboard = clipboard::default_service();
-            let engine = Arc::new(ClipEngine::new(
-                db.clone(),
-                clipboard,
-                media_store,
-                app.handle().clone(),
-            ));
-            engine.start().map_err(|err| err.to_string())?;
-
-            let settings = db.get_settings().map_err(|err| err.to_string())?;
-            let pruned = db.prune_excess(settings.history_limit).map_err(|err| err.to_string())?;
-            engine
-                .cleanup_media_for_clips(&pruned)
-                .map_err(|err| err.to_string())?;
-            let image_reconcile_marker = app_data_dir.join(".image-reconcile-v2.done");
-            if !image_reconcile_marker.exists() {
-                match engine.reconcile_recent_image_duplicates(500) {
-                    Ok(count) => {
-                        if let Err(err) = std::fs::write(&image_reconcile_marker, count.to_string())
-                        {
-                            warn!("failed to persist image reconciliation marker: {err}");
-                        }
-                    }
-                    Err(err) => warn!("failed to reconcile image duplicates: {err}"),
-                }
-            }
-
-            let shortcut = Shortcut::new(Some(Modifiers::SUPER | Modifiers::SHIFT), Code::KeyV);
             app.global_shortcut()
-                .register(shortcut)
+                .register(window_shortcut())
                 .map_err(|err| err.to_string())?;
+            if let Err(err) = app.global_shortcut().register(pause_shortcut()) {
+                warn!("failed to register pause shortcut: {err}");
+            }
 
             if let Err(err) = app.autolaunch().enable() {
                 warn!("failed to enable autostart: {err}");
             }
 
             if let Some(icon) = app.default_window_icon().cloned() {
-                let _tray = tauri::tray::TrayIconBuilder::with_id("klippy-tray")
+                let mut paused_rgba = icon.rgba().to_vec();
+                dim_rgba(&mut paused_rgba);
+                app.manage(TrayIcons {
+                    active: Image::new_owned(icon.rgba().to_vec(), icon.width(), icon.height()),
+                    paused: Image::new_owned(paused_rgba, icon.width(), icon.height()),
+                });
+
+                let _tray = tauri::tray::TrayIconBuilder::with_id(TRAY_ID)
                     .icon(icon)
                     .show_menu_on_left_click(false)
+                    .on_menu_event(|app, event| handle_tray_menu(app, event.id().as_ref()))
                     .on_tray_icon_event(|tray, event| {
                         if let tauri::tray::TrayIconEvent::Click {
                             button: tauri::tray::MouseButton::Left,
@@ -230,7 +548,29 @@ pub fn run() {
                 warn!("no default window icon available for tray icon");
             }
 
-            app.manage(AppState { engine });
+            let handle = app.handle().clone();
+            app.listen_any(
+                "settings://updated",
+                move |event| match serde_json::from_str::<Settings>(event.payload()) {
+                    Ok(settings) => show_tracking_state(&handle, &settings),
+                    Err(err) => warn!("unreadable settings event: {err}"),
+                },
+            );
+
+            match services::encryption::load_cipher(&app_data_dir, passphrase.as_deref()) {
+                Ok(cipher) => open_history_in_background(app.handle(), app_data_dir, cipher),
+                Err(AppError::Crypto(
+                    CryptoError::MissingPassphrase | CryptoError::WrongPassphrase,
+                )) => {
+                    // The window asks for the passphrase and hands it to `unlock_history`.
+                    info!("history stays locked until its passphrase is entered");
+                    *app.state::<HistoryGate>().lock() = HistoryState::Locked;
+                    if let Some(window) = app.get_webview_window("main") {
+                        toggle_window(app.handle(), &window);
+                    }
+                }
+                Err(err) => return Err(err.to_string().into()),
+            }
 
             Ok(())
         })
@@ -288,9 +628,27 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             commands::list_clips,
             commands::copy_clip,
+            commands::paste_clip,
             commands::set_pinned,
+            commands::reorder_pinned,
             commands::delete_clip,
             commands::clear_all_clips,
+            commands::get_settings,
+            commands::update_settings,
+            commands::preview_retention,
+            commands::get_encryption_status,
+            commands::enable_encryption,
+            commands::history_state,
+            commands::unlock_history,
+            commands::pause_tracking,
+            commands::resume_tracking,
+            commands::list_tags,
+            commands::create_tag,
+            commands::rename_tag,
+            commands::set_tag_protected,
+            commands::delete_tag,
+            commands::tag_clip,
+            commands::untag_clip,
             commands::stop_app
         ]);
 
@@ -301,7 +659,7 @@ pub fn run() {
 
 #[cfg(test)]
 mod tests {
-    use super::should_hide_on_focus_loss;
+    use super::{dim_rgba, should_hide_on_focus_loss};
 
     #[test]
     fn hides_only_when_window_is_visible_and_not_minimized() {
@@ -310,4 +668,11 @@ mod tests {
         assert!(!should_hide_on_focus_loss(true, true));
         assert!(!should_hide_on_focus_loss(false, true));
     }
+
+    #[test]
+    fn paused_tray_icon_is_grey_and_faded() {
+        let mut rgba = vec![255, 0, 0, 255, 10, 200, 30, 0];
+        dim_rgba(&mut rgba);
+        assert_eq!(rgba, vec![76, 76, 76, 102, 124, 124, 124, 0]);
+    }
 }
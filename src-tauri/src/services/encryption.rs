@@ -0,0 +1,398 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::services::media_store::MediaStore;
+use crate::utils::crypto::{self, Cipher, CryptoError, KEY_LEN, SALT_LEN};
+
+/// Lives beside the history in plaintext, since it is read before the key
+/// is known. It names where the key comes from but never holds it.
+const CONFIG_FILE: &str = "encryption.json";
+/// Name of a generated key file, kept in a key directory apart from the
+/// history where there is no keychain.
+const DEFAULT_KEY_FILE: &str = "klippy.key";
+pub const PASSPHRASE_ENV: &str = "KLIPPY_PASSPHRASE";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "keySource", rename_all = "camelCase")]
+pub enum EncryptionConfig {
+    /// A random key kept in the login keychain.
+    Keychain,
+    /// A random key kept in a local file. Safest on storage that is not
+    /// synced or backed up along with the history.
+    KeyFile { path: PathBuf },
+    /// A key derived from a passphrase asked for at launch, or read from
+    /// `KLIPPY_PASSPHRASE`. The fingerprint tells a wrong passphrase apart.
+    Passphrase { salt: String, fingerprint: String },
+}
+
+/// How the user asked to turn encryption on. Not `Debug`, so the passphrase
+/// cannot end up in a log.
+#[derive(Clone, Deserialize)]
+#[serde(tag = "keySource", rename_all = "camelCase")]
+pub enum EnableEncryption {
+    /// A new random key in the keychain, or in `klippy.key` in the key
+    /// directory on systems without one.
+    GeneratedKey,
+    /// Uses an existing key file at `path`, or creates one there.
+    KeyFile {
+        path: PathBuf,
+    },
+    Passphrase {
+        passphrase: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionStatus {
+    pub config: Option<EncryptionConfig>,
+    /// Whether this run opened the history with a key. Turning encryption
+    /// on takes effect, and encrypts existing history, at the next launch.
+    pub active: bool,
+}
+
+pub fn load_config(app_data_dir: &Path) -> AppResult<Option<EncryptionConfig>> {
+    match fs::read_to_string(app_data_dir.join(CONFIG_FILE)) {
+        Ok(json) => serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|err| AppError::Internal(format!("invalid {CONFIG_FILE}: {err}"))),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(AppError::Internal(err.to_string())),
+    }
+}
+
+pub fn status(app_data_dir: &Path, active: bool) -> AppResult<EncryptionStatus> {
+    Ok(EncryptionStatus {
+        config: load_config(app_data_dir)?,
+        active,
+    })
+}
+
+/// The key for this run, or `None` when encryption is off. `passphrase` is
+/// only consulted for passphrase-derived keys, which fail with
+/// `MissingPassphrase` or `WrongPassphrase` until the right one is given.
+pub fn load_cipher(app_data_dir: &Path, passphrase: Option<&str>) -> AppResult<Option<Cipher>> {
+    let cipher = match load_config(app_data_dir)? {
+        None => return Ok(None),
+        Some(EncryptionConfig::Keychain) => crypto::decode_hex::<KEY_LEN>(&keychain::load()?)
+            .map(Cipher::new)
+            .ok_or_else(|| CryptoError::Keychain("the stored key is not valid".to_string()))?,
+        Some(EncryptionConfig::KeyFile { path }) => Cipher::from_key_file(&path)?,
+        Some(EncryptionConfig::Passphrase { salt, fingerprint }) => {
+            let salt = crypto::decode_hex::<SALT_LEN>(&salt).ok_or(CryptoError::InvalidSalt)?;
+            let passphrase = passphrase.ok_or(CryptoError::MissingPassphrase)?;
+            let cipher = Cipher::from_passphrase(passphrase, &salt)?;
+            if cipher.fingerprint() != fingerprint {
+                return Err(CryptoError::WrongPassphrase.into());
+            }
+            cipher
+        }
+    };
+    Ok(Some(cipher))
+}
+
+/// Records how the key is found from the next launch on. There is no way
+/// back to plaintext, so a second call is refused. `key_dir` must lie
+/// outside `app_data_dir`, so that copying the history leaves its key
+/// behind.
+pub fn enable(
+    app_data_dir: &Path,
+    key_dir: &Path,
+    request: EnableEncryption,
+) -> AppResult<EncryptionConfig> {
+    if load_config(app_data_dir)?.is_some() {
+        return Err(AppError::Internal("encryption is already on".to_string()));
+    }
+    let config = match request {
+        EnableEncryption::GeneratedKey if keychain::AVAILABLE => {
+            keychain::store(&crypto::encode_hex(&crypto::random_key()))?;
+            keychain::load()?;
+            EncryptionConfig::Keychain
+        }
+        EnableEncryption::GeneratedKey => key_file(key_dir.join(DEFAULT_KEY_FILE))?,
+        EnableEncryption::KeyFile { path } => key_file(path)?,
+        EnableEncryption::Passphrase { passphrase } => {
+            if passphrase.is_empty() {
+                return Err(CryptoError::MissingPassphrase.into());
+            }
+            let salt = crypto::random_salt();
+            let cipher = Cipher::from_passphrase(&passphrase, &salt)?;
+            EncryptionConfig::Passphrase {
+                salt: crypto::encode_hex(&salt),
+                fingerprint: cipher.fingerprint(),
+            }
+        }
+    };
+    let json =
+        serde_json::to_string_pretty(&config).map_err(|err| AppError::Internal(err.to_string()))?;
+    fs::write(app_data_dir.join(CONFIG_FILE), json)
+        .map_err(|err| AppError::Internal(err.to_string()))?;
+    Ok(config)
+}
+
+fn key_file(path: PathBuf) -> AppResult<EncryptionConfig> {
+    if !path.exists() {
+        Cipher::create_key_file(&path)?;
+    }
+    Cipher::from_key_file(&path)?;
+    Ok(EncryptionConfig::KeyFile { path })
+}
+
+/// Seals media stored before encryption was turned on, points its clips at
+/// the sealed copies and removes the plaintext files. Safe to rerun after an
+/// interruption; returns how many files were sealed.
+pub fn encrypt_existing_media(db: &Database, media_store: &MediaStore) -> AppResult<usize> {
+    let mut encrypted = 0;
+    for path in db.referenced_media_paths()? {
+        let Some(sealed_path) = media_store.encrypt_file(&path)? else {
+            continue;
+        };
+        db.replace_media_path(&path, &sealed_path)?;
+        media_store.delete_files_for_clip(Some(&path), None)?;
+        encrypted += 1;
+    }
+    if encrypted > 0 {
+        info!("encrypted {encrypted} media files stored before encryption was on");
+    }
+    Ok(encrypted)
+}
+
+#[cfg(target_os = "macos")]
+mod keychain {
+    use std::ptr::{self, NonNull};
+
+    use objc2_core_foundation::{CFBoolean, CFData, CFDictionary, CFRetained, CFString, CFType};
+    use objc2_security::{
+        errSecDuplicateItem, errSecSuccess, kSecAttrAccount, kSecAttrService, kSecClass,
+        kSecClassGenericPassword, kSecReturnData, kSecValueData, SecItemAdd, SecItemCopyMatching,
+    };
+
+    use crate::utils::crypto::CryptoError;
+
+    const SERVICE: &str = "com.wdftech.klippy";
+    const ACCOUNT: &str = "history-key";
+
+    pub(super) const AVAILABLE: bool = true;
+
+    /// Adds `key` to the login keychain, keeping a key stored there already.
+    pub(super) fn store(key: &str) -> Result<(), CryptoError> {
+        let data = CFData::from_bytes(key.as_bytes());
+        // SAFETY: the attribute constants are immutable Security statics.
+        let query = generic_password(unsafe { kSecValueData }, &data);
+        // SAFETY: `query` is a valid dictionary and no result is asked for.
+        let status = unsafe { SecItemAdd(query.as_opaque(), ptr::null_mut()) };
+        if status == errSecSuccess || status == errSecDuplicateItem {
+            Ok(())
+        } else {
+            Err(failed(status))
+        }
+    }
+
+    pub(super) fn load() -> Result<String, CryptoError> {
+        // SAFETY: the attribute constants are immutable Security statics.
+        let query = generic_password(unsafe { kSecReturnData }, CFBoolean::new(true));
+        let mut result: *const CFType = ptr::null();
+        // SAFETY: `query` is a valid dictionary and `result` a valid out pointer.
+        let status = unsafe { SecItemCopyMatching(query.as_opaque(), &mut result) };
+        if status != errSecSuccess {
+            return Err(failed(status));
+        }
+        // SAFETY: a successful copy hands over one retained reference.
+        let data = NonNull::new(result.cast_mut())
+            .map(|result| unsafe { CFRetained::from_raw(result) })
+            .and_then(|result| result.downcast::<CFData>().ok())
+            .ok_or_else(|| CryptoError::Keychain("the stored key is not data".to_string()))?;
+        String::from_utf8(data.to_vec())
+            .map_err(|_| CryptoError::Keychain("the stored key is not text".to_string()))
+    }
+
+    fn generic_password(
+        key: &CFString,
+        value: &CFType,
+    ) -> CFRetained<CFDictionary<CFString, CFType>> {
+        let service = CFString::from_str(SERVICE);
+        let account = CFString::from_str(ACCOUNT);
+        // SAFETY: the attribute constants are immutable Security statics.
+        let (class_key, class, service_key, account_key) = unsafe {
+            (
+                kSecClass,
+                kSecClassGenericPassword,
+                kSecAttrService,
+                kSecAttrAccount,
+            )
+        };
+        CFDictionary::from_slices(
+            &[class_key, service_key, account_key, key],
+            &[class.as_ref(), service.as_ref(), account.as_ref(), value],
+        )
+    }
+
+    fn failed(status: i32) -> CryptoError {
+        CryptoError::Keychain(format!("status {status}"))
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+mod keychain {
+    use crate::utils::crypto::CryptoError;
+
+    pub(super) const AVAILABLE: bool = false;
+
+    pub(super) fn store(_key: &str) -> Result<(), CryptoError> {
+        Err(unavailable())
+    }
+
+    pub(super) fn load() -> Result<String, CryptoError> {
+        Err(unavailable())
+    }
+
+    fn unavailable() -> CryptoError {
+        CryptoError::Keychain("not available on this system".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    use image::{DynamicImage, ImageFormat, RgbaImage};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::clipboard::ImagePayload;
+    use crate::db::ImageClipInsert;
+
+    fn passphrase(passphrase: &str) -> EnableEncryption {
+        EnableEncryption::Passphrase {
+            passphrase: passphrase.to_string(),
+        }
+    }
+
+    #[test]
+    fn enabling_records_the_key_source_once() {
+        let dir = env::temp_dir().join(format!("klippy-encryption-{}", Uuid::new_v4()));
+        let key_dir = env::temp_dir().join(format!("klippy-keys-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).expect("app data dir");
+        assert!(load_cipher(&dir, None).expect("off").is_none());
+
+        let path = key_dir.join("history.key");
+        let config = enable(
+            &dir,
+            &key_dir,
+            EnableEncryption::KeyFile { path: path.clone() },
+        )
+        .expect("enable key file");
+        assert_eq!(config, EncryptionConfig::KeyFile { path });
+        assert_eq!(load_config(&dir).expect("config"), Some(config));
+        let first = load_cipher(&dir, None).expect("load").expect("cipher");
+        let second = load_cipher(&dir, None).expect("reload").expect("cipher");
+        assert_eq!(first.sqlcipher_key(), second.sqlcipher_key());
+        assert!(enable(&dir, &key_dir, passphrase("hunter2")).is_err());
+
+        fs::remove_file(dir.join(CONFIG_FILE)).expect("reset");
+        assert!(matches!(
+            enable(&dir, &key_dir, passphrase("")),
+            Err(AppError::Crypto(CryptoError::MissingPassphrase))
+        ));
+        enable(&dir, &key_dir, passphrase("hunter2")).expect("enable passphrase");
+        assert!(matches!(
+            load_cipher(&dir, None),
+            Err(AppError::Crypto(CryptoError::MissingPassphrase))
+        ));
+        assert!(matches!(
+            load_cipher(&dir, Some("hunter3")),
+            Err(AppError::Crypto(CryptoError::WrongPassphrase))
+        ));
+        let derived = load_cipher(&dir, Some("hunter2"))
+            .expect("derive")
+            .expect("cipher");
+        assert_ne!(derived.sqlcipher_key(), first.sqlcipher_key());
+        let _ = fs::remove_dir_all(dir);
+        let _ = fs::remove_dir_all(key_dir);
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn generated_keys_are_kept_outside_the_app_data_directory() {
+        let dir = env::temp_dir().join(format!("klippy-encryption-{}", Uuid::new_v4()));
+        let key_dir = env::temp_dir().join(format!("klippy-keys-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).expect("app data dir");
+
+        let config = enable(&dir, &key_dir, EnableEncryption::GeneratedKey).expect("enable");
+        assert_eq!(
+            config,
+            EncryptionConfig::KeyFile {
+                path: key_dir.join(DEFAULT_KEY_FILE)
+            }
+        );
+        let names = fs::read_dir(&dir)
+            .expect("app data dir")
+            .map(|entry| entry.expect("entry").file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [CONFIG_FILE]);
+        assert!(load_cipher(&dir, None).expect("load").is_some());
+        let _ = fs::remove_dir_all(dir);
+        let _ = fs::remove_dir_all(key_dir);
+    }
+
+    #[test]
+    fn existing_media_is_sealed_renamed_and_still_readable() {
+        let dir = env::temp_dir().join(format!("klippy-encryption-{}", Uuid::new_v4()));
+        let plain_store = MediaStore::new(&dir).expect("media store");
+        let rgba = RgbaImage::from_raw(1, 1, vec![200, 10, 10, 255]).expect("pixels");
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(rgba)
+            .write_to(&mut png, ImageFormat::Png)
+            .expect("encode");
+        let image = ImagePayload {
+            bytes: png.into_inner(),
+            mime: "image/png".to_string(),
+            format: "png".to_string(),
+            width: 1,
+            height: 1,
+        };
+        let stored = plain_store.store_image(&image).expect("store");
+        let db = Database::new_in_memory().expect("db");
+        let insert = || ImageClipInsert {
+            content: "Image 1x1",
+            hash: "pixels",
+            media_path: &stored.media_path,
+            thumb_path: &stored.thumb_path,
+            mime_type: &stored.mime_type,
+            byte_size: stored.byte_size,
+            pixel_width: 1,
+            pixel_height: 1,
+            source: "clipboard",
+            source_app: None,
+        };
+        let first = db.insert_image_clip(insert()).expect("first clip");
+        let second = db.insert_image_clip(insert()).expect("second clip");
+
+        let cipher = Arc::new(Cipher::new([5; 32]));
+        let store = MediaStore::new(&dir)
+            .expect("media store")
+            .with_cipher(Arc::clone(&cipher));
+        assert_eq!(encrypt_existing_media(&db, &store).expect("encrypt"), 2);
+        assert_eq!(encrypt_existing_media(&db, &store).expect("again"), 0);
+
+        let first = db.get_clip(first.id).expect("get").expect("first");
+        let second = db.get_clip(second.id).expect("get").expect("second");
+        assert_eq!(first.media_path, second.media_path);
+        assert_eq!(first.thumb_path, second.thumb_path);
+        let media_path = first.media_path.expect("media path");
+        assert_ne!(media_path, stored.media_path);
+        assert!(!Path::new(&stored.media_path).exists());
+        assert!(!Path::new(&stored.thumb_path).exists());
+        assert!(Cipher::is_sealed(&fs::read(&media_path).expect("raw")));
+        assert_eq!(store.read(&media_path).expect("read"), image.bytes);
+        assert!(plain_store.read(&media_path).is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::{Mutex, PoisonError};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use tracing::warn;
+
+use crate::db::{Clip, Settings, Tag};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipEvent {
+    Created(Clip),
+    Updated(Clip),
+    Deleted(i64),
+    Cleared,
+    /// The pinned section was reordered; carries its ids, top first.
+    PinsReordered(Vec<i64>),
+    SettingsUpdated(Settings),
+    /// Any tag was created, renamed, deleted or (un)assigned; carries the
+    /// full tag list.
+    TagsUpdated(Vec<Tag>),
+}
+
+impl ClipEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Created(_) => "clips://created",
+            Self::Updated(_) | Self::Cleared | Self::PinsReordered(_) => "clips://updated",
+            Self::Deleted(_) => "clips://deleted",
+            Self::SettingsUpdated(_) => "settings://updated",
+            Self::TagsUpdated(_) => "tags://updated",
+        }
+    }
+}
+
+/// Where the engine reports history changes. The app forwards them to the
+/// webview; tests and headless embedders can record or ignore them.
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: ClipEvent);
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct DeletedPayload {
+    id: i64,
+}
+
+pub struct TauriEventSink {
+    app: AppHandle,
+}
+
+impl TauriEventSink {
+    pub fn new(app: AppHandle) -> Self {
+        Self { app }
+    }
+}
+
+impl EventSink for TauriEventSink {
+    fn emit(&self, event: ClipEvent) {
+        let name = event.name();
+        let result = match event {
+            ClipEvent::Created(clip) | ClipEvent::Updated(clip) => self.app.emit(name, clip),
+            ClipEvent::Deleted(id) => self.app.emit(name, DeletedPayload { id }),
+            ClipEvent::Cleared => self.app.emit(name, true),
+            ClipEvent::PinsReordered(ids) => self.app.emit(name, ids),
+            ClipEvent::SettingsUpdated(settings) => self.app.emit(name, settings),
+            ClipEvent::TagsUpdated(tags) => self.app.emit(name, tags),
+        };
+        if let Err(err) = result {
+            warn!("failed to emit {name}: {err}");
+        }
+    }
+}
+
+/// Keeps every event in memory, for embedders without a webview (a daemon,
+/// a CLI, tests) that inspect what the engine did after the fact.
+#[derive(Debug, Default)]
+pub struct RecordingEventSink {
+    events: Mutex<Vec<ClipEvent>>,
+}
+
+impl RecordingEventSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything emitted so far, oldest first.
+    pub fn events(&self) -> Vec<ClipEvent> {
+        self.lock().clone()
+    }
+
+    /// Returns and forgets everything emitted so far, for callers that poll.
+    pub fn take(&self) -> Vec<ClipEvent> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    /// A panic while recording cannot leave the list half-written, so a
+    /// poisoned lock is still safe to use.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<ClipEvent>> {
+        self.events.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl EventSink for RecordingEventSink {
+    fn emit(&self, event: ClipEvent) {
+        self.lock().push(event);
+    }
+}
@@ -2,11 +2,13 @@ use std::collections::HashSet;
 use std::fs;
 use std::io::Cursor;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use image::ImageFormat;
 
 use crate::clipboard::ImagePayload;
 use crate::error::{AppError, AppResult};
+use crate::utils::crypto::Cipher;
 use crate::utils::hash::sha256_hex_bytes;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -22,6 +24,8 @@ pub struct StoredImage {
 pub struct MediaStore {
     originals_dir: PathBuf,
     thumbs_dir: PathBuf,
+    /// Seals originals and thumbnails on write when encryption is on.
+    cipher: Option<Arc<Cipher>>,
 }
 
 impl MediaStore {
@@ -33,17 +37,27 @@ impl MediaStore {
         Ok(Self {
             originals_dir,
             thumbs_dir,
+            cipher: None,
         })
     }
 
+    pub fn with_cipher(mut self, cipher: Arc<Cipher>) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    pub fn encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
     pub fn store_image(&self, payload: &ImagePayload) -> AppResult<StoredImage> {
-        let digest = sha256_hex_bytes(&payload.bytes);
+        let digest = self.digest(&payload.bytes);
         let extension = extension_for_format(&payload.format);
         let media_path = self.originals_dir.join(format!("{digest}.{extension}"));
         let thumb_path = self.thumbs_dir.join(format!("{digest}.png"));
 
         if !media_path.exists() {
-            fs::write(&media_path, &payload.bytes).map_err(to_internal)?;
+            self.write(&media_path, &payload.bytes)?;
         }
 
         if !thumb_path.exists() {
@@ -54,7 +68,7 @@ impl MediaStore {
             thumbnail
                 .write_to(&mut output, ImageFormat::Png)
                 .map_err(|err| AppError::Internal(format!("failed to encode thumbnail: {err}")))?;
-            fs::write(&thumb_path, output.into_inner()).map_err(to_internal)?;
+            self.write(&thumb_path, &output.into_inner())?;
         }
 
         Ok(StoredImage {
@@ -67,6 +81,59 @@ impl MediaStore {
         })
     }
 
+    /// Reads a stored file, decrypting it if it was sealed. Files written
+    /// before encryption was turned on are read as they are.
+    pub fn read(&self, path: &str) -> AppResult<Vec<u8>> {
+        let bytes = fs::read(path).map_err(to_internal)?;
+        if !Cipher::is_sealed(&bytes) {
+            return Ok(bytes);
+        }
+        let cipher = self.cipher.as_ref().ok_or_else(|| {
+            AppError::Internal(format!("{path} is encrypted but no key is loaded"))
+        })?;
+        Ok(cipher.open(&bytes)?)
+    }
+
+    /// Whether `path` names a file directly inside the originals or thumbs
+    /// directory, the only files `read` should serve to the webview.
+    pub fn contains(&self, path: &Path) -> bool {
+        path.file_name().is_some()
+            && path
+                .parent()
+                .is_some_and(|dir| dir == self.originals_dir || dir == self.thumbs_dir)
+    }
+
+    /// Seals a plaintext file stored before encryption was turned on and
+    /// renames it by keyed digest, since plain digests identify known
+    /// content. Returns the new path, or `None` when there is nothing to do.
+    /// The plaintext file is left for the caller to remove once no clip
+    /// refers to it.
+    pub fn encrypt_file(&self, path: &str) -> AppResult<Option<String>> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(None);
+        };
+        let path = Path::new(path);
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(to_internal(err)),
+        };
+        if Cipher::is_sealed(&bytes) || !self.contains(path) {
+            return Ok(None);
+        }
+        let mut name = cipher.name_digest(&bytes);
+        if let Some(extension) = path.extension() {
+            name = format!("{name}.{}", extension.to_string_lossy());
+        }
+        let sealed_path = path.with_file_name(name);
+        if !sealed_path.exists() {
+            self.write(&sealed_path, &bytes)?;
+        }
+        Ok(Some(sealed_path.to_string_lossy().to_string()))
+    }
+
+    /// Removes the given files. Clips of the same image share them, so
+    /// callers pass only paths no remaining clip references.
     pub fn delete_files_for_clip(
         &self,
         media_path: Option<&str>,
@@ -87,6 +154,23 @@ impl MediaStore {
         Ok(())
     }
 
+    /// Bytes taken by every original and thumbnail on disk.
+    pub fn total_bytes(&self) -> AppResult<i64> {
+        let mut total = 0;
+        for dir in [&self.originals_dir, &self.thumbs_dir] {
+            for entry in fs::read_dir(dir).map_err(to_internal)? {
+                let metadata = entry
+                    .map_err(to_internal)?
+                    .metadata()
+                    .map_err(to_internal)?;
+                if metadata.is_file() {
+                    total += metadata.len() as i64;
+                }
+            }
+        }
+        Ok(total)
+    }
+
     pub fn canonical_hash_for_image_bytes(bytes: &[u8]) -> AppResult<String> {
         let decoded = image::load_from_memory(bytes)
             .map_err(|err| AppError::Internal(format!("failed to decode image: {err}")))?;
@@ -95,9 +179,57 @@ impl MediaStore {
     }
 
     pub fn canonical_hash_from_path(&self, media_path: &str) -> AppResult<String> {
-        let bytes = fs::read(media_path).map_err(to_internal)?;
+        let bytes = self.read(media_path)?;
         Self::canonical_hash_for_image_bytes(&bytes)
     }
+
+    fn digest(&self, bytes: &[u8]) -> String {
+        match &self.cipher {
+            Some(cipher) => cipher.name_digest(bytes),
+            None => sha256_hex_bytes(bytes),
+        }
+    }
+
+    /// Writes through a temporary file so a crash never leaves a truncated
+    /// file under a content-addressed name.
+    fn write(&self, path: &Path, bytes: &[u8]) -> AppResult<()> {
+        let temp_path = path.with_extension("partial");
+        match &self.cipher {
+            Some(cipher) => fs::write(&temp_path, cipher.seal(bytes)),
+            None => fs::write(&temp_path, bytes),
+        }
+        .and_then(|()| fs::rename(&temp_path, path))
+        .map_err(to_internal)
+    }
+}
+
+/// The file path in a media protocol URL. `convertFileSrc` percent-encodes
+/// the whole path into the URL's first segment.
+pub fn path_from_uri(uri_path: &str) -> Option<PathBuf> {
+    let encoded = uri_path.strip_prefix('/').unwrap_or(uri_path).as_bytes();
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut index = 0;
+    while index < encoded.len() {
+        if encoded[index] == b'%' {
+            let hex = std::str::from_utf8(encoded.get(index + 1..index + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(encoded[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+pub fn mime_type_for_path(path: &Path) -> &'static str {
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    match extension.to_ascii_lowercase().as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "tiff" | "tif" => "image/tiff",
+        "webp" => "image/webp",
+        _ => "image/png",
+    }
 }
 
 fn extension_for_format(format: &str) -> &'static str {
@@ -133,3 +265,35 @@ fn remove_file_if_exists(path: &str) -> AppResult<()> {
 fn to_internal(err: std::io::Error) -> AppError {
     AppError::Internal(err.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_only_decoded_paths_inside_the_store() {
+        let root = std::env::temp_dir().join(format!("klippy-media-{}", uuid::Uuid::new_v4()));
+        let store = MediaStore::new(&root).expect("media store");
+        let original = root.join("originals").join("ab12.jpg");
+
+        let encoded = format!(
+            "/{}",
+            original
+                .to_string_lossy()
+                .replace('%', "%25")
+                .replace('/', "%2F")
+                .replace(' ', "%20")
+        );
+        let decoded = path_from_uri(&encoded).expect("decode");
+        assert_eq!(decoded, original);
+        assert!(store.contains(&decoded));
+        assert_eq!(mime_type_for_path(&decoded), "image/jpeg");
+
+        assert!(!store.contains(&root.join("klippy.key")));
+        assert!(!store.contains(&root.join("originals")));
+        assert!(!store.contains(&root.join("originals").join("..").join("klippy.key")));
+        assert!(path_from_uri("/%2").is_none());
+        assert!(path_from_uri("/%ff").is_none());
+        let _ = fs::remove_dir_all(root);
+    }
+}
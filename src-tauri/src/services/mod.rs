@@ -1,3 +1,7 @@
 pub mod clip_engine;
+pub mod encryption;
+pub mod events;
 pub mod media_store;
+pub mod pause;
 pub mod prune;
+pub mod reaper;
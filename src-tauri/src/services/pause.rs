@@ -0,0 +1,47 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use crate::db::Settings;
+
+/// The pause lengths offered by the tray menu, shortcut and webview.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PauseDuration {
+    FiveMinutes,
+    OneHour,
+    UntilResumed,
+}
+
+impl PauseDuration {
+    pub fn length(self) -> Option<Duration> {
+        match self {
+            Self::FiveMinutes => Some(Duration::from_secs(5 * 60)),
+            Self::OneHour => Some(Duration::from_secs(60 * 60)),
+            Self::UntilResumed => None,
+        }
+    }
+
+    /// When a pause starting at `now` (epoch milliseconds) ends.
+    pub fn until(self, now: i64) -> Option<i64> {
+        self.length()
+            .map(|length| now.saturating_add(length.as_millis() as i64))
+    }
+}
+
+pub fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Whether capture is paused at `now`. A timed pause that has run out no
+/// longer counts, even before anything has cleared it.
+pub fn is_paused(settings: &Settings, now: i64) -> bool {
+    settings.tracking_paused
+        && match settings.tracking_paused_until {
+            Some(until) => until > now,
+            None => true,
+        }
+}
@@ -1,11 +1,374 @@
-use std::sync::Arc;
+use std::collections::HashMap;
 
-use crate::db::{Clip, Database};
+use serde::Serialize;
 
-pub fn run_prune(
-    db: &Arc<Database>,
-    history_limit: i64,
-) -> Result<Vec<Clip>, crate::error::AppError> {
-    db.prune_excess(history_limit)
-        .map_err(crate::error::AppError::from)
+use crate::db::{Clip, ContentTypeLimits, Database, RetentionCandidate, Settings};
+use crate::error::AppResult;
+use crate::services::media_store::MediaStore;
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1_000;
+
+/// The limits pruning enforces together. Pinned clips and clips with a
+/// protected tag count toward every limit but are never removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub max_count: i64,
+    /// Oldest clip age kept, in milliseconds.
+    pub max_age: Option<i64>,
+    /// Database plus media bytes kept.
+    pub max_total_bytes: Option<i64>,
+    /// Most clips kept per content type.
+    pub type_quotas: ContentTypeLimits,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RetentionReason {
+    MaxAge,
+    TypeQuota,
+    MaxCount,
+    MaxTotalBytes,
+}
+
+/// A clip the policy removes, with the first limit it broke.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Removal {
+    pub clip_id: i64,
+    pub reason: RetentionReason,
+    /// Estimated bytes freed: stored content, plus the original media file
+    /// when no clip left behind shares it.
+    pub bytes: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionReport {
+    /// Nothing was deleted; `removed` is what would have been.
+    pub dry_run: bool,
+    /// Bottom of the history first.
+    pub removed: Vec<Removal>,
+    /// Storage in use before pruning. Only measured for dry runs or when a
+    /// byte limit is set.
+    pub used_bytes: Option<i64>,
+    pub freed_bytes: i64,
+}
+
+impl RetentionPolicy {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let enabled = |value: i64| Some(value).filter(|value| *value > 0);
+        Self {
+            max_count: settings.history_limit.max(1),
+            max_age: enabled(settings.max_age_days).map(|days| days.saturating_mul(DAY_MILLIS)),
+            max_total_bytes: enabled(settings.max_storage_bytes),
+            type_quotas: settings.type_quotas,
+        }
+    }
+
+    /// Picks the clips this policy removes from `clips`, which must be
+    /// ordered from the bottom of the history up. Limits apply in turn, age
+    /// first, then type quotas, count and bytes, each seeing what the earlier
+    /// ones removed.
+    pub fn plan(&self, clips: &[RetentionCandidate], used_bytes: i64, now: i64) -> Vec<Removal> {
+        let mut plan = Plan::new(clips);
+
+        if let Some(max_age) = self.max_age {
+            for (index, clip) in clips.iter().enumerate() {
+                if now.saturating_sub(clip.created_at_ms) > max_age {
+                    plan.remove(index, RetentionReason::MaxAge);
+                }
+            }
+        }
+
+        let mut kept_by_type: HashMap<&str, i64> = HashMap::new();
+        for (index, clip) in clips.iter().enumerate() {
+            if !plan.is_removed(index) {
+                *kept_by_type.entry(&clip.content_type).or_default() += 1;
+            }
+        }
+        for (index, clip) in clips.iter().enumerate() {
+            let Some(quota) = self.type_quotas.for_type(&clip.content_type) else {
+                continue;
+            };
+            let kept = kept_by_type.entry(&clip.content_type).or_default();
+            if *kept > quota && plan.remove(index, RetentionReason::TypeQuota).is_some() {
+                *kept -= 1;
+            }
+        }
+
+        let mut kept = (0..clips.len())
+            .filter(|index| !plan.is_removed(*index))
+            .count() as i64;
+        for index in 0..clips.len() {
+            if kept <= self.max_count {
+                break;
+            }
+            if plan.remove(index, RetentionReason::MaxCount).is_some() {
+                kept -= 1;
+            }
+        }
+
+        if let Some(max_total_bytes) = self.max_total_bytes {
+            let mut remaining = used_bytes - plan.freed_bytes();
+            for index in 0..clips.len() {
+                if remaining <= max_total_bytes {
+                    break;
+                }
+                if let Some(freed) = plan.remove(index, RetentionReason::MaxTotalBytes) {
+                    remaining -= freed;
+                }
+            }
+        }
+
+        plan.into_removals()
+    }
+}
+
+/// Removals picked so far. Clips of the same image share one media file, so
+/// its bytes only count as freed with the last clip that uses it.
+struct Plan<'a> {
+    clips: &'a [RetentionCandidate],
+    removed: Vec<Option<(RetentionReason, i64)>>,
+    media_refs: HashMap<&'a str, usize>,
+}
+
+impl<'a> Plan<'a> {
+    fn new(clips: &'a [RetentionCandidate]) -> Self {
+        let mut media_refs: HashMap<&str, usize> = HashMap::new();
+        for path in clips.iter().filter_map(|clip| clip.media_path.as_deref()) {
+            *media_refs.entry(path).or_default() += 1;
+        }
+        Self {
+            clips,
+            removed: vec![None; clips.len()],
+            media_refs,
+        }
+    }
+
+    fn is_removed(&self, index: usize) -> bool {
+        self.removed[index].is_some()
+    }
+
+    /// Marks the clip at `index` for removal and returns the bytes that
+    /// frees, or `None` when it is protected or already marked.
+    fn remove(&mut self, index: usize, reason: RetentionReason) -> Option<i64> {
+        let clip = &self.clips[index];
+        if clip.protected || self.is_removed(index) {
+            return None;
+        }
+        let mut bytes = clip.content_bytes;
+        if let Some(refs) = clip
+            .media_path
+            .as_deref()
+            .and_then(|path| self.media_refs.get_mut(path))
+        {
+            *refs -= 1;
+            if *refs == 0 {
+                bytes += clip.media_bytes;
+            }
+        }
+        self.removed[index] = Some((reason, bytes));
+        Some(bytes)
+    }
+
+    fn freed_bytes(&self) -> i64 {
+        self.removed.iter().flatten().map(|(_, bytes)| bytes).sum()
+    }
+
+    fn into_removals(self) -> Vec<Removal> {
+        self.clips
+            .iter()
+            .zip(self.removed)
+            .filter_map(|(clip, removed)| {
+                removed.map(|(reason, bytes)| Removal {
+                    clip_id: clip.id,
+                    reason,
+                    bytes,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Evaluates `policy` at `now` and, unless `dry_run`, deletes what it picked.
+/// Returns the report and the deleted clips, whose media is left to the
+/// caller.
+pub fn run_retention(
+    db: &Database,
+    media_store: &MediaStore,
+    policy: &RetentionPolicy,
+    now: i64,
+    dry_run: bool,
+) -> AppResult<(RetentionReport, Vec<Clip>)> {
+    let used_bytes = if dry_run || policy.max_total_bytes.is_some() {
+        Some(db.storage_bytes()? + media_store.total_bytes()?)
+    } else {
+        None
+    };
+    let removed = policy.plan(
+        &db.retention_candidates()?,
+        used_bytes.unwrap_or_default(),
+        now,
+    );
+    let deleted = if dry_run || removed.is_empty() {
+        Vec::new()
+    } else {
+        let ids = removed
+            .iter()
+            .map(|removal| removal.clip_id)
+            .collect::<Vec<_>>();
+        db.delete_clips_by_ids(&ids)?
+    };
+    let report = RetentionReport {
+        dry_run,
+        freed_bytes: removed.iter().map(|removal| removal.bytes).sum(),
+        removed,
+        used_bytes,
+    };
+    Ok((report, deleted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_800_000_000_000;
+
+    fn candidate(id: i64, content_type: &str, age_days: i64, bytes: i64) -> RetentionCandidate {
+        RetentionCandidate {
+            id,
+            content_type: content_type.to_string(),
+            created_at_ms: NOW - age_days * DAY_MILLIS,
+            protected: false,
+            content_bytes: bytes,
+            media_path: None,
+            media_bytes: 0,
+        }
+    }
+
+    fn policy(max_count: i64) -> RetentionPolicy {
+        RetentionPolicy {
+            max_count,
+            max_age: None,
+            max_total_bytes: None,
+            type_quotas: ContentTypeLimits::default(),
+        }
+    }
+
+    fn reasons(removed: &[Removal]) -> Vec<(i64, RetentionReason)> {
+        removed
+            .iter()
+            .map(|removal| (removal.clip_id, removal.reason))
+            .collect()
+    }
+
+    #[test]
+    fn count_limit_removes_oldest_and_keeps_protected() {
+        let mut clips = vec![
+            candidate(1, "text", 3, 10),
+            candidate(2, "text", 2, 10),
+            candidate(3, "text", 1, 10),
+        ];
+        clips[0].protected = true;
+
+        assert_eq!(
+            reasons(&policy(1).plan(&clips, 0, NOW)),
+            vec![
+                (2, RetentionReason::MaxCount),
+                (3, RetentionReason::MaxCount)
+            ]
+        );
+        assert!(policy(3).plan(&clips, 0, NOW).is_empty());
+    }
+
+    #[test]
+    fn limits_combine_and_report_the_first_one_broken() {
+        let clips = vec![
+            candidate(1, "text", 40, 100),
+            candidate(2, "image", 20, 5_000),
+            candidate(3, "image", 10, 5_000),
+            candidate(4, "text", 5, 100),
+            candidate(5, "image", 2, 5_000),
+            candidate(6, "url", 1, 100),
+        ];
+        let policy = RetentionPolicy {
+            max_count: 4,
+            max_age: Some(30 * DAY_MILLIS),
+            max_total_bytes: Some(5_500),
+            type_quotas: ContentTypeLimits {
+                image: Some(2),
+                ..Default::default()
+            },
+        };
+
+        let removed = policy.plan(&clips, 15_400, NOW);
+        assert_eq!(
+            reasons(&removed),
+            vec![
+                (1, RetentionReason::MaxAge),
+                (2, RetentionReason::TypeQuota),
+                (3, RetentionReason::MaxTotalBytes),
+            ]
+        );
+        assert_eq!(
+            removed.iter().map(|removal| removal.bytes).sum::<i64>(),
+            10_100
+        );
+    }
+
+    #[test]
+    fn shared_media_is_freed_with_its_last_clip() {
+        let shared = |id| RetentionCandidate {
+            media_path: Some("/media/a.png".to_string()),
+            media_bytes: 5_000,
+            ..candidate(id, "image", 3 - id, 10)
+        };
+        let clips = vec![shared(1), shared(2), candidate(3, "text", 0, 100)];
+        let policy = RetentionPolicy {
+            max_total_bytes: Some(5_000),
+            ..policy(10)
+        };
+
+        let removed = policy.plan(&clips, 5_120, NOW);
+        assert_eq!(
+            removed
+                .iter()
+                .map(|removal| (removal.clip_id, removal.bytes))
+                .collect::<Vec<_>>(),
+            vec![(1, 10), (2, 5_010)]
+        );
+    }
+
+    #[test]
+    fn dry_runs_report_without_deleting() {
+        let db = Database::new_in_memory().expect("db init");
+        let media_dir = std::env::temp_dir().join(format!("klippy-prune-{}", uuid::Uuid::new_v4()));
+        let media_store = MediaStore::new(&media_dir).expect("media store");
+        let pinned = db.insert_clip("pinned", "text").expect("insert pinned");
+        db.set_pinned(pinned.id, true).expect("pin");
+        let old = db.insert_clip("old", "text").expect("insert old");
+        let new = db.insert_clip("new", "text").expect("insert new");
+        let policy = policy(2);
+
+        let (report, deleted) =
+            run_retention(&db, &media_store, &policy, NOW, true).expect("dry run");
+        assert!(report.dry_run && deleted.is_empty());
+        assert_eq!(
+            reasons(&report.removed),
+            vec![(old.id, RetentionReason::MaxCount)]
+        );
+        assert_eq!(report.freed_bytes, 3);
+        assert!(report.used_bytes.is_some_and(|bytes| bytes > 0));
+        assert!(db.get_clip(old.id).expect("get").is_some());
+
+        let (report, deleted) =
+            run_retention(&db, &media_store, &policy, NOW, false).expect("prune");
+        assert_eq!(report.used_bytes, None);
+        assert_eq!(
+            deleted.iter().map(|clip| clip.id).collect::<Vec<_>>(),
+            vec![old.id]
+        );
+        assert!(db.get_clip(new.id).expect("get").is_some());
+        let _ = std::fs::remove_dir_all(media_dir);
+    }
 }
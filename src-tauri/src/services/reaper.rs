@@ -0,0 +1,38 @@
+use std::sync::Weak;
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::services::clip_engine::ClipEngine;
+use crate::services::pause::now_millis;
+
+/// Longest the reaper sleeps between checks. Expiries are wall-clock times,
+/// so this bounds the delay after a system sleep or a clock change.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Deletes expired clips until the engine is dropped. Sleeps until the next
+/// expiry is due, or until `ClipEngine::wake_reaper` reports a new one.
+pub async fn run_reaper(engine: Weak<ClipEngine>) {
+    let Some(wake) = engine.upgrade().map(|engine| engine.reaper_wake()) else {
+        return;
+    };
+    loop {
+        let now = now_millis();
+        let next = {
+            let Some(engine) = engine.upgrade() else {
+                return;
+            };
+            match engine.reap_expired(now) {
+                Ok(next) => next,
+                Err(err) => {
+                    warn!("failed to delete expired clips: {err}");
+                    None
+                }
+            }
+        };
+        let wait = next.map_or(MAX_WAIT, |at| {
+            Duration::from_millis(at.saturating_sub(now).max(0) as u64).min(MAX_WAIT)
+        });
+        let _ = tokio::time::timeout(wait, wake.notified()).await;
+    }
+}
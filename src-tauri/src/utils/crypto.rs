@@ -0,0 +1,246 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+pub const KEY_LEN: usize = 32;
+pub const SALT_LEN: usize = 16;
+/// Starts every sealed blob, so plaintext written before encryption was
+/// turned on is told apart from ciphertext.
+const SEALED_MAGIC: &[u8; 4] = b"KLE1";
+const NONCE_LEN: usize = 24;
+const NAME_KEY_CONTEXT: &[u8] = b"klippy media names v1";
+const FINGERPRINT_CONTEXT: &[u8] = b"klippy key fingerprint v1";
+
+#[derive(Debug, Error)]
+pub enum CryptoError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid key file {path}: expected {len} hex characters", len = KEY_LEN * 2)]
+    InvalidKeyFile { path: PathBuf },
+    #[error("invalid salt: expected {len} hex characters", len = SALT_LEN * 2)]
+    InvalidSalt,
+    #[error("encryption is on but no passphrase was given")]
+    MissingPassphrase,
+    #[error("the passphrase does not match the one encryption was turned on with")]
+    WrongPassphrase,
+    #[error("failed to derive a key from the passphrase: {0}")]
+    Kdf(String),
+    #[error("keychain error: {0}")]
+    Keychain(String),
+    #[error("encrypted data is corrupt or was written with another key")]
+    Decrypt,
+}
+
+/// The key that encrypts clips at rest. The database hands it to SQLCipher
+/// as a raw key; media blobs are sealed with XChaCha20-Poly1305.
+pub struct Cipher {
+    key: [u8; KEY_LEN],
+    aead: XChaCha20Poly1305,
+    name_key: [u8; KEY_LEN],
+}
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("Cipher(..)")
+    }
+}
+
+impl Cipher {
+    pub fn new(key: [u8; KEY_LEN]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(NAME_KEY_CONTEXT);
+        hasher.update(key);
+        Self {
+            key,
+            aead: XChaCha20Poly1305::new(&key.into()),
+            name_key: hasher.finalize().into(),
+        }
+    }
+
+    /// Reads a key file: the key as hex, optionally followed by a newline.
+    pub fn from_key_file(path: &Path) -> Result<Self, CryptoError> {
+        let text = fs::read_to_string(path)?;
+        decode_hex::<KEY_LEN>(text.trim())
+            .map(Self::new)
+            .ok_or_else(|| CryptoError::InvalidKeyFile {
+                path: path.to_path_buf(),
+            })
+    }
+
+    /// Writes a random key to a new file at `path`, readable only by the
+    /// current user. Fails if the file already exists.
+    pub fn create_key_file(path: &Path) -> Result<Self, CryptoError> {
+        let key = random_key();
+        write_private(path, encode_hex(&key).as_bytes())?;
+        Ok(Self::new(key))
+    }
+
+    /// Derives the key from `passphrase` with Argon2id.
+    pub fn from_passphrase(passphrase: &str, salt: &[u8; SALT_LEN]) -> Result<Self, CryptoError> {
+        let mut key = [0; KEY_LEN];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|err| CryptoError::Kdf(err.to_string()))?;
+        Ok(Self::new(key))
+    }
+
+    /// The key in SQLCipher's raw key form, which skips its own key
+    /// derivation.
+    pub fn sqlcipher_key(&self) -> String {
+        format!("x'{}'", encode_hex(&self.key))
+    }
+
+    /// Encrypts `plaintext` under a fresh random nonce.
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, plaintext)
+            .expect("XChaCha20-Poly1305 encrypts any in-memory buffer");
+        let mut sealed = Vec::with_capacity(SEALED_MAGIC.len() + NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(SEALED_MAGIC);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let body = sealed
+            .strip_prefix(SEALED_MAGIC.as_slice())
+            .filter(|body| body.len() >= NONCE_LEN)
+            .ok_or(CryptoError::Decrypt)?;
+        let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+        self.aead
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CryptoError::Decrypt)
+    }
+
+    pub fn is_sealed(bytes: &[u8]) -> bool {
+        bytes.starts_with(SEALED_MAGIC)
+    }
+
+    /// Identifies the key without revealing it, so a mistyped passphrase is
+    /// caught before anything is opened with the key it derives.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(FINGERPRINT_CONTEXT);
+        hasher.update(self.key);
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// A keyed digest of `bytes` for naming stored files. Equal content
+    /// still shares a name, but names reveal nothing without the key.
+    pub fn name_digest(&self, bytes: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.name_key);
+        hasher.update(bytes);
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+pub fn random_key() -> [u8; KEY_LEN] {
+    XChaCha20Poly1305::generate_key(&mut OsRng).into()
+}
+
+pub fn random_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub fn decode_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    if text.len() != N * 2 || !text.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(text.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_blobs_open_only_with_their_key() {
+        let cipher = Cipher::new([7; KEY_LEN]);
+        let sealed = cipher.seal(b"quarterly numbers");
+        assert!(Cipher::is_sealed(&sealed));
+        assert!(!sealed
+            .windows(b"quarterly".len())
+            .any(|window| window == b"quarterly"));
+        assert_ne!(sealed, cipher.seal(b"quarterly numbers"));
+        assert_eq!(cipher.open(&sealed).expect("open"), b"quarterly numbers");
+
+        let other = Cipher::new([8; KEY_LEN]);
+        assert!(matches!(other.open(&sealed), Err(CryptoError::Decrypt)));
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().expect("tag") ^= 1;
+        assert!(matches!(cipher.open(&tampered), Err(CryptoError::Decrypt)));
+        assert!(matches!(cipher.open(b"KLE1"), Err(CryptoError::Decrypt)));
+        assert_ne!(cipher.name_digest(b"a"), other.name_digest(b"a"));
+    }
+
+    #[test]
+    fn key_files_are_created_once_and_passphrases_derive_stable_keys() {
+        let dir = std::env::temp_dir().join(format!("klippy-crypto-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("klippy.key");
+        assert!(matches!(
+            Cipher::from_key_file(&path),
+            Err(CryptoError::Io(_))
+        ));
+        let created = Cipher::create_key_file(&path).expect("create key");
+        let loaded = Cipher::from_key_file(&path).expect("load key");
+        assert_eq!(created.sqlcipher_key(), loaded.sqlcipher_key());
+        assert!(Cipher::create_key_file(&path).is_err());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).expect("metadata").permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::write(&path, "not hex").expect("corrupt key");
+        assert!(matches!(
+            Cipher::from_key_file(&path),
+            Err(CryptoError::InvalidKeyFile { .. })
+        ));
+        let _ = fs::remove_dir_all(dir);
+
+        let salt = [1; SALT_LEN];
+        let derived = Cipher::from_passphrase("correct horse", &salt).expect("derive");
+        let again = Cipher::from_passphrase("correct horse", &salt).expect("derive again");
+        let other = Cipher::from_passphrase("correct horse", &[2; SALT_LEN]).expect("other salt");
+        assert_eq!(derived.sqlcipher_key(), again.sqlcipher_key());
+        assert_ne!(derived.sqlcipher_key(), other.sqlcipher_key());
+        assert_eq!(derived.fingerprint(), again.fingerprint());
+        assert_ne!(derived.fingerprint(), other.fingerprint());
+        assert_eq!(decode_hex::<SALT_LEN>(&encode_hex(&salt)), Some(salt));
+    }
+}
@@ -1 +1,3 @@
+pub mod crypto;
 pub mod hash;
+pub mod secrets;
@@ -69,6 +69,7 @@ export const App = (props: { store: ClipStore }) => {
 
         <footer class="mt-auto border-t border-slate-300/70 bg-slate-50 px-4 py-2 text-[11px] text-slate-500">
           <span class="rounded bg-slate-100 px-1.5 py-0.5 font-mono text-[11px]">↑↓</span> navigate ·{' '}
+          <span class="rounded bg-slate-100 px-1.5 py-0.5 font-mono text-[11px]">⌘↵</span> paste ·{' '}
           <span class="rounded bg-slate-100 px-1.5 py-0.5 font-mono text-[11px]">⌘⇧V</span> show/hide
         </footer>
       </section>
@@ -3,7 +3,7 @@ import { describe, expect, it, vi } from 'vitest';
 import { ClipList } from './ClipList';
 
 vi.mock('@tauri-apps/api/core', () => ({
-  convertFileSrc: (filePath: string) => `asset://${filePath}`,
+  convertFileSrc: (filePath: string, protocol = 'asset') => `${protocol}://${filePath}`,
 }));
 
 describe('ClipList', () => {
@@ -49,6 +49,31 @@ describe('ClipList', () => {
     expect(onDelete).toHaveBeenCalledWith(10);
   });
 
+  it('bolds search highlights', () => {
+    render(() => (
+      <ClipList
+        items={[
+          {
+            id: 7,
+            content: 'Deploy the cluster',
+            contentType: 'text',
+            pinned: false,
+            createdAt: new Date().toISOString(),
+            highlights: [{ start: 11, end: 18 }],
+          },
+        ]}
+        selectedIndex={0}
+        onSelect={() => undefined}
+        onCopy={() => undefined}
+        onPin={() => undefined}
+        onDelete={() => undefined}
+      />
+    ));
+
+    expect(screen.getByText('cluster').tagName).toBe('STRONG');
+    expect(screen.getByTestId('clip-content-7').textContent).toBe('Deploy the cluster');
+  });
+
   it('renders image metadata row', () => {
     render(() => (
       <ClipList
@@ -104,7 +129,7 @@ describe('ClipList', () => {
     ));
 
     const preview = screen.getByAltText('Clipboard image preview') as HTMLImageElement;
-    expect(preview.getAttribute('src')).toContain('thumb.png');
+    expect(preview.getAttribute('src')).toBe('media:///tmp/thumb.png');
 
     fireEvent.error(preview);
     expect(preview.getAttribute('src')).toContain('original.png');
@@ -1,4 +1,4 @@
-import { createEffect, createSignal, onCleanup } from 'solid-js';
+import { createEffect, createSignal, For, onCleanup } from 'solid-js';
 import { convertFileSrc } from '@tauri-apps/api/core';
 import type { Clip } from '../lib/types';
 
@@ -17,6 +17,9 @@ const contentClassByType = (type: Clip['contentType']) => {
   if (type === 'url') {
     return 'break-all whitespace-pre-line text-sky-700 underline decoration-sky-300/70 underline-offset-2';
   }
+  if (type === 'files') {
+    return 'break-all whitespace-pre-line font-mono text-[11px] leading-[1.3] text-slate-700';
+  }
   if (type === 'code') {
     return 'break-words whitespace-pre-wrap font-mono text-[11px] leading-[1.3] text-slate-700';
   }
@@ -37,7 +40,25 @@ const imageMetaText = (clip: Clip) => {
   return parts.join(' | ');
 };
 
-const mediaSrc = (path?: string | null) => (path ? convertFileSrc(path) : null);
+const highlightSegments = (clip: Clip) => {
+  const segments: { text: string; hit: boolean }[] = [];
+  let cursor = 0;
+  for (const range of clip.highlights ?? []) {
+    if (range.start > cursor) {
+      segments.push({ text: clip.content.slice(cursor, range.start), hit: false });
+    }
+    segments.push({ text: clip.content.slice(range.start, range.end), hit: true });
+    cursor = range.end;
+  }
+  if (cursor < clip.content.length) {
+    segments.push({ text: clip.content.slice(cursor), hit: false });
+  }
+  return segments;
+};
+
+// Images come through the backend's media protocol, which decrypts them
+// when encryption at rest is on.
+const mediaSrc = (path?: string | null) => (path ? convertFileSrc(path, 'media') : null);
 
 export const ClipRow = (props: {
   clip: Clip;
@@ -161,7 +182,9 @@ export const ClipRow = (props: {
             data-testid={`clip-content-${props.clip.id}`}
             class={`clip-two-lines block w-full text-left text-[12px] leading-[1.25] transition-colors hover:text-black ${contentClassByType(props.clip.contentType)}`}
           >
-            {props.clip.content}
+            <For each={highlightSegments(props.clip)}>
+              {(segment) => (segment.hit ? <strong class="font-semibold text-black">{segment.text}</strong> : segment.text)}
+            </For>
           </p>
         )}
       </div>
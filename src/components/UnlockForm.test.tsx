@@ -0,0 +1,16 @@
+import { fireEvent, render, screen } from '@solidjs/testing-library';
+import { describe, expect, it, vi } from 'vitest';
+import { UnlockForm } from './UnlockForm';
+
+describe('UnlockForm', () => {
+  it('submits the passphrase and shows why unlocking failed', async () => {
+    const onUnlock = vi.fn().mockRejectedValue('the passphrase does not match');
+    render(() => <UnlockForm onUnlock={onUnlock} />);
+
+    fireEvent.input(screen.getByLabelText('Passphrase'), { target: { value: 'hunter3' } });
+    fireEvent.click(screen.getByText('Unlock'));
+
+    expect(onUnlock).toHaveBeenCalledWith('hunter3');
+    expect(await screen.findByText('the passphrase does not match')).toBeInTheDocument();
+  });
+});
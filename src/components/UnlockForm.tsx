@@ -0,0 +1,56 @@
+import { createSignal, Show } from 'solid-js';
+
+/** Asks for the passphrase of an encrypted history; `onUnlock` rejects when it is wrong. */
+export const UnlockForm = (props: { onUnlock: (passphrase: string) => Promise<void> }) => {
+  const [passphrase, setPassphrase] = createSignal('');
+  const [error, setError] = createSignal<string | null>(null);
+  const [busy, setBusy] = createSignal(false);
+
+  const submit = async (event: SubmitEvent) => {
+    event.preventDefault();
+    setBusy(true);
+    setError(null);
+    try {
+      await props.onUnlock(passphrase());
+    } catch (err) {
+      setError(String(err));
+    } finally {
+      setBusy(false);
+    }
+  };
+
+  return (
+    <main class="flex h-screen items-center justify-center bg-slate-100 p-3 text-slate-900">
+      <form
+        class="flex w-full max-w-[360px] flex-col gap-3 rounded-2xl border border-slate-300/80 bg-slate-50 p-5 shadow-[0_10px_28px_rgba(15,23,42,0.1)]"
+        onSubmit={(event) => {
+          void submit(event);
+        }}
+      >
+        <h2 class="text-lg font-semibold text-slate-900">History is locked</h2>
+        <p class="text-sm leading-6 text-slate-500">
+          Enter the passphrase your clipboard history is encrypted with.
+        </p>
+        <input
+          autofocus
+          aria-label="Passphrase"
+          class="w-full rounded-lg border border-slate-300 bg-white px-3.5 py-2 text-[15px] text-slate-900 outline-none transition placeholder:text-slate-400 focus:border-slate-500 focus:shadow-[0_0_0_2px_rgba(100,116,139,0.2)]"
+          placeholder="Passphrase"
+          type="password"
+          value={passphrase()}
+          onInput={(event) => setPassphrase(event.currentTarget.value)}
+        />
+        <Show when={error()}>
+          <p class="text-[13px] text-rose-700">{error()}</p>
+        </Show>
+        <button
+          class="rounded-xl border border-slate-300 bg-white px-3 py-1.5 text-[13px] font-medium text-slate-700 transition hover:border-slate-500"
+          disabled={busy() || passphrase().length === 0}
+          type="submit"
+        >
+          Unlock
+        </button>
+      </form>
+    </main>
+  );
+};
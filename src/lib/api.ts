@@ -1,14 +1,84 @@
 import { invoke } from '@tauri-apps/api/core';
-import type { ClipPage } from './types';
+import type {
+  ClipPage,
+  ClipSort,
+  ClipSource,
+  EnableEncryption,
+  EncryptionStatus,
+  HistoryState,
+  PauseDuration,
+  RetentionReport,
+  SearchMode,
+  Settings,
+  SettingsUpdate,
+  Tag,
+} from './types';
 
-export const listClips = async (query: string | null, limit: number, offset: number): Promise<ClipPage> =>
-  invoke('list_clips', { query, limit, offset });
+export const listClips = async (
+  query: string | null,
+  limit: number,
+  cursor: string | null = null,
+  source: ClipSource | null = null,
+  mode: SearchMode = 'fullText',
+  includeTotal = false,
+  sort: ClipSort = 'recent',
+): Promise<ClipPage> =>
+  invoke('list_clips', { query, source, mode, sort, cursor, limit, includeTotal });
 
-export const copyClip = async (id: number): Promise<void> => invoke('copy_clip', { id });
+export const copyClip = async (id: number, plainText = false): Promise<void> =>
+  invoke('copy_clip', { id, plainText });
+
+export const pasteClip = async (id: number, plainText = false): Promise<void> =>
+  invoke('paste_clip', { id, plainText });
 
 export const setPinned = async (id: number, pinned: boolean): Promise<void> => invoke('set_pinned', { id, pinned });
+/** Orders the pinned section by `ids`, top first; unlisted pins keep their order below. */
+export const reorderPinned = async (ids: number[]): Promise<void> => invoke('reorder_pinned', { ids });
 
 export const deleteClip = async (id: number): Promise<void> => invoke('delete_clip', { id });
 export const clearAllClips = async (): Promise<number> => invoke('clear_all_clips');
 
+export const getSettings = async (): Promise<Settings> => invoke('get_settings');
+export const updateSettings = async (update: SettingsUpdate): Promise<Settings> =>
+  invoke('update_settings', { update });
+
+/** Reports what pruning would remove with `update` applied, without deleting anything. */
+export const previewRetention = async (update?: SettingsUpdate): Promise<RetentionReport> =>
+  invoke('preview_retention', { update });
+
+export const getEncryptionStatus = async (): Promise<EncryptionStatus> =>
+  invoke('get_encryption_status');
+
+/** Takes effect, and encrypts the existing history, at the next launch. */
+export const enableEncryption = async (request: EnableEncryption): Promise<EncryptionStatus> =>
+  invoke('enable_encryption', { request });
+
+/** Other calls fail until this is `open`; `history://opened` is emitted when it gets there. */
+export const getHistoryState = async (): Promise<HistoryState> => invoke('history_state');
+export const unlockHistory = async (passphrase: string): Promise<void> =>
+  invoke('unlock_history', { passphrase });
+
+export const pauseTracking = async (duration: PauseDuration): Promise<Settings> =>
+  invoke('pause_tracking', { duration });
+export const resumeTracking = async (): Promise<Settings> => invoke('resume_tracking');
+
 export const stopApp = async (): Promise<void> => invoke('stop_app');
+
+export const listTags = async (): Promise<Tag[]> => invoke('list_tags');
+
+export const createTag = async (name: string, isProtected = false): Promise<Tag> =>
+  invoke('create_tag', { name, protected: isProtected });
+
+export const renameTag = async (id: number, name: string): Promise<Tag> =>
+  invoke('rename_tag', { id, name });
+
+export const setTagProtected = async (id: number, isProtected: boolean): Promise<Tag> =>
+  invoke('set_tag_protected', { id, protected: isProtected });
+
+export const deleteTag = async (id: number): Promise<void> => invoke('delete_tag', { id });
+
+export const tagClip = async (clipId: number, tagId: number): Promise<void> =>
+  invoke('tag_clip', { clipId, tagId });
+
+export const untagClip = async (clipId: number, tagId: number): Promise<void> =>
+  invoke('untag_clip', { clipId, tagId });
@@ -5,6 +5,7 @@ const mocks = vi.hoisted(() => {
   return {
     listClips: vi.fn(),
     copyClip: vi.fn(),
+    pasteClip: vi.fn(),
     setPinned: vi.fn(),
     deleteClip: vi.fn(),
     clearAllClips: vi.fn(),
@@ -15,6 +16,7 @@ const mocks = vi.hoisted(() => {
 vi.mock('./api', () => ({
   listClips: mocks.listClips,
   copyClip: mocks.copyClip,
+  pasteClip: mocks.pasteClip,
   setPinned: mocks.setPinned,
   deleteClip: mocks.deleteClip,
   clearAllClips: mocks.clearAllClips,
@@ -42,8 +44,8 @@ describe('useClipStore', () => {
           createdAt: new Date().toISOString(),
         },
       ],
-      total: 2,
-      nextOffset: null,
+      total: null,
+      nextCursor: null,
     });
   });
 
@@ -65,6 +67,18 @@ describe('useClipStore', () => {
     expect(mocks.copyClip).not.toHaveBeenCalled();
   });
 
+  it('pastes the selected item on Cmd+Enter', async () => {
+    const store = useClipStore();
+    await store.init();
+    store.setSelectedIndex(1);
+
+    const event = new KeyboardEvent('keydown', { key: 'Enter', metaKey: true });
+    await store.onKeyDown(event);
+
+    expect(mocks.pasteClip).toHaveBeenCalledWith(2, false);
+    expect(mocks.copyClip).not.toHaveBeenCalled();
+  });
+
   it('navigates with arrow keys', async () => {
     const store = useClipStore();
     await store.init();
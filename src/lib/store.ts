@@ -4,6 +4,7 @@ import {
   copyClip,
   deleteClip,
   listClips,
+  pasteClip,
   setPinned,
   stopApp,
 } from './api';
@@ -22,6 +23,7 @@ export interface ClipStore {
   setSelectedIndex: (value: number) => void;
   onKeyDown: (event: KeyboardEvent) => Promise<void>;
   copy: (id: number) => Promise<void>;
+  paste: (id: number) => Promise<void>;
   pin: (id: number, pinned: boolean) => Promise<void>;
   remove: (id: number) => Promise<void>;
   clearAll: () => Promise<void>;
@@ -38,7 +40,7 @@ export const useClipStore = (): ClipStore => {
   const reload = async () => {
     setLoading(true);
     try {
-      const page = await listClips(query().trim() === '' ? null : query(), PAGE_SIZE, 0);
+      const page = await listClips(query().trim() === '' ? null : query(), PAGE_SIZE);
       setItems(page.items);
       const currentSelected = selectedIndex();
       if (page.items.length === 0) {
@@ -55,8 +57,12 @@ export const useClipStore = (): ClipStore => {
     await reload();
   };
 
-  const copy = async (id: number) => {
-    await copyClip(id);
+  const copy = async (id: number, plainText = false) => {
+    await copyClip(id, plainText);
+  };
+
+  const paste = async (id: number, plainText = false) => {
+    await pasteClip(id, plainText);
   };
 
   const pin = async (id: number, pinned: boolean) => {
@@ -109,7 +115,15 @@ export const useClipStore = (): ClipStore => {
       return;
     }
 
-    // Enter-to-copy disabled by product requirement.
+    // Enter-to-copy disabled by product requirement; ⌘/Ctrl+Enter pastes
+    // into the app the window was opened from.
+    if (event.key === 'Enter' && (event.metaKey || event.ctrlKey)) {
+      event.preventDefault();
+      const selected = currentItems[selectedIndex()];
+      if (selected) {
+        await paste(selected.id, event.shiftKey);
+      }
+    }
   };
 
   return {
@@ -123,6 +137,7 @@ export const useClipStore = (): ClipStore => {
     setSelectedIndex,
     onKeyDown,
     copy,
+    paste,
     pin,
     remove,
     clearAll,
@@ -1,21 +1,139 @@
-export type ContentType = 'text' | 'url' | 'code' | 'image';
+export type ContentType = 'text' | 'url' | 'code' | 'image' | 'files';
+export type ClipSource = 'clipboard' | 'primary';
+export type SearchMode = 'fullText' | 'fuzzy';
+/** Order of clips listed without search terms. */
+export type ClipSort = 'recent' | 'frecency';
+/** What capture does with a clip holding a detected secret. */
+export type SecretAction = 'store' | 'expire' | 'mask' | 'skip';
 
 export interface Clip {
   id: number;
   content: string;
   contentType: ContentType;
   pinned: boolean;
+  /** Position among pinned clips, highest first; 0 when not pinned. */
+  pinOrder?: number;
+  /** Capture time; re-copies leave it alone. */
   createdAt: string;
+  /** When the clip last moved to the top: its capture or latest re-copy. */
+  sortAt?: string;
   mediaPath?: string | null;
   thumbPath?: string | null;
   mimeType?: string | null;
   byteSize?: number;
   pixelWidth?: number | null;
   pixelHeight?: number | null;
+  source?: ClipSource;
+  sourceApp?: string | null;
+  hasHtml?: boolean;
+  hasRtf?: boolean;
+  files?: ClipFile[] | null;
+  copyCount?: number;
+  useCount?: number;
+  lastUsedAt?: string | null;
+  /** Epoch milliseconds the clip deletes itself at, unless pinned by then. */
+  expiresAt?: number | null;
+  tags?: ClipTag[];
+  highlights?: MatchRange[];
+}
+
+export interface ClipTag {
+  id: number;
+  name: string;
+}
+
+export interface Tag extends ClipTag {
+  /** Clips with a protected tag are never pruned, like pinned ones. */
+  protected: boolean;
+  clipCount: number;
+}
+
+/** Half-open range into `content`, in UTF-16 code units. */
+export interface MatchRange {
+  start: number;
+  end: number;
+}
+
+export interface ClipFile {
+  path: string;
+  isDir: boolean;
+  byteSize: number | null;
 }
 
 export interface ClipPage {
   items: Clip[];
-  total: number;
-  nextOffset: number | null;
+  total: number | null;
+  nextCursor: string | null;
+}
+
+export interface Settings {
+  historyLimit: number;
+  trackingPaused: boolean;
+  /** Epoch milliseconds a timed pause ends at; null while paused means until resumed. */
+  trackingPausedUntil: number | null;
+  maxClipBytes: number;
+  restoreClipboardAfterPaste: boolean;
+  denylistBundleIds: string[];
+  capturePrimarySelection: boolean;
+  uniqueHistory: boolean;
+  secretActions: SecretActions;
+  /** How long clips kept with the `expire` secret action last. */
+  secretExpirySeconds: number;
+  /** Seconds newly captured clips of each type last. */
+  defaultTtlSeconds: ContentTypeLimits;
+  /** Pruning removes clips older than this; 0 turns the limit off. */
+  maxAgeDays: number;
+  /** Pruning keeps database plus media storage under this; 0 turns the limit off. */
+  maxStorageBytes: number;
+  /** Most clips pruning keeps of each type. */
+  typeQuotas: ContentTypeLimits;
+}
+
+/** An optional number per content type; null or missing means no limit. */
+export type ContentTypeLimits = Partial<Record<ContentType, number | null>>;
+
+export type RetentionReason = 'maxAge' | 'typeQuota' | 'maxCount' | 'maxTotalBytes';
+
+export interface RetentionReport {
+  dryRun: boolean;
+  /** Oldest first, with the first limit each clip broke. */
+  removed: { clipId: number; reason: RetentionReason; bytes: number }[];
+  /** Only measured for dry runs or when a storage limit is set. */
+  usedBytes: number | null;
+  freedBytes: number;
+}
+
+/** Where the encryption key comes from; the key itself is never sent. */
+export type EncryptionConfig =
+  | { keySource: 'keychain' }
+  | { keySource: 'keyFile'; path: string }
+  | { keySource: 'passphrase'; salt: string; fingerprint: string };
+
+/** Generated keys go to the keychain where there is one; passphrases are asked for at each launch. */
+export type EnableEncryption =
+  | { keySource: 'generatedKey' }
+  | { keySource: 'keyFile'; path: string }
+  | { keySource: 'passphrase'; passphrase: string };
+
+/** `locked` while a passphrase-encrypted history waits for its passphrase. */
+export type HistoryState = 'opening' | 'locked' | 'open';
+
+export interface EncryptionStatus {
+  config: EncryptionConfig | null;
+  /** False until the app restarts after encryption is turned on. */
+  active: boolean;
+}
+
+export interface SecretActions {
+  awsKey: SecretAction;
+  githubToken: SecretAction;
+  slackToken: SecretAction;
+  jwt: SecretAction;
+  privateKey: SecretAction;
+  cardNumber: SecretAction;
 }
+
+/** Fields to change; omitted ones keep their stored value. */
+export type SettingsUpdate = Partial<Omit<Settings, 'trackingPausedUntil'>>;
+
+export type PauseDuration = 'fiveMinutes' | 'oneHour' | 'untilResumed';
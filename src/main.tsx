@@ -1,15 +1,31 @@
 import { render } from "solid-js/web";
-import { onCleanup, onMount } from "solid-js";
+import { createSignal, onCleanup, onMount, Show } from "solid-js";
 import { listen } from "@tauri-apps/api/event";
 import { App } from "./App";
+import { UnlockForm } from "./components/UnlockForm";
+import { getHistoryState, unlockHistory } from "./lib/api";
 import { useClipStore } from "./lib/store";
 import "./styles/tailwind.css";
 
 const Root = () => {
   const store = useClipStore();
+  const [locked, setLocked] = createSignal(false);
+
+  const unlock = async (passphrase: string) => {
+    await unlockHistory(passphrase);
+    setLocked(false);
+  };
 
   onMount(async () => {
-    await store.init();
+    // Listen first: the history may finish opening while its state is asked for.
+    const unlistenOpened = await listen("history://opened", async () => {
+      await store.init();
+    });
+    const state = await getHistoryState();
+    setLocked(state === "locked");
+    if (state === "open") {
+      await store.init();
+    }
     const blockContextMenu = (event: MouseEvent) => {
       event.preventDefault();
     };
@@ -24,16 +40,25 @@ const Root = () => {
     const unlistenDeleted = await listen("clips://deleted", async () => {
       await store.reload();
     });
+    const unlistenTags = await listen("tags://updated", async () => {
+      await store.reload();
+    });
 
     onCleanup(() => {
       document.removeEventListener("contextmenu", blockContextMenu);
       unlistenCreated();
       unlistenUpdated();
       unlistenDeleted();
+      unlistenTags();
+      unlistenOpened();
     });
   });
 
-  return <App store={store} />;
+  return (
+    <Show when={!locked()} fallback={<UnlockForm onUnlock={unlock} />}>
+      <App store={store} />
+    </Show>
+  );
 };
 
 render(() => <Root />, document.getElementById("root") as HTMLElement);